@@ -0,0 +1,453 @@
+//! Replays state traces recorded with `--trace-state` directly against a state backend.
+//!
+//! Reports the replay throughput and the latency of each operation type, without the cost of
+//! running the dataflow. Values are synthetic byte vectors of the recorded sizes. Read-modify-writes
+//! follow the kind recorded in the trace: appending ones append their bytes to the value, and
+//! combining ones add their bytes to those of the value, which keeps its size.
+//!
+//! Statistics of the backend, such as the FASTER log size or the RocksDB memtable size, are
+//! printed as `replay_backend_stats\t<accesses>\t<metric>\t<value>` lines every 2^20 accesses
+//! and at the end of the replay.
+
+extern crate clap;
+extern crate faster_rs;
+extern crate hdrhist;
+extern crate nexmark;
+extern crate rocksdb;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate streaming_harness;
+extern crate tempfile;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::time::Instant;
+
+use clap::{App, Arg};
+use faster_rs::{status, FasterKv, FasterRmw};
+use hdrhist::HDRHist;
+use rocksdb::{BlockBasedOptions, MergeOperands, Options, DB};
+use streaming_harness::util::ToNanos;
+use tempfile::TempDir;
+
+use nexmark::queries::maybe_refresh_faster;
+use nexmark::stats::{rocksdb_instance_stats, FasterStats};
+use nexmark::tools::read_backend_config;
+use nexmark::trace::{Op, Record, RmwKind, TraceReader};
+
+/// The operations of a key-value store that trace accesses are replayed with.
+trait Store {
+    /// Reads `key` and returns whether it was found.
+    fn get(&mut self, key: u64) -> bool;
+    fn put(&mut self, key: u64, value: &[u8]);
+    /// Reads and deletes `key` and returns whether it was found.
+    fn remove(&mut self, key: u64) -> bool;
+    /// Appends `value` to the value of `key`, or combines it with the value, as `kind` says.
+    fn rmw(&mut self, key: u64, value: &[u8], kind: RmwKind);
+    /// Returns the current statistics of the backend by name.
+    fn stats(&self) -> Vec<(&'static str, f64)> {
+        Vec::new()
+    }
+    /// Called once the replay is over.
+    fn finish(&mut self) {}
+}
+
+// Number of accesses between two samples of the backend statistics
+const STATS_INTERVAL: u64 = 1 << 20;
+
+// Adds the bytes of `operand` to those of `value`, as combining read-modify-writes of counts,
+// sums or sketches rewrite every byte of a value of the same size.
+fn combine(value: &mut Vec<u8>, operand: &[u8]) {
+    if value.len() < operand.len() {
+        value.resize(operand.len(), 0);
+    }
+    for (byte, operand) in value.iter_mut().zip(operand.iter()) {
+        *byte = byte.wrapping_add(*operand);
+    }
+}
+
+/// A value that FASTER combines with `combine` on read-modify-writes, instead of appending to it
+/// as it does for `Vec<u8>`. It is serialized like the vector it wraps.
+#[derive(Serialize, Deserialize)]
+struct Combined(Vec<u8>);
+
+impl FasterRmw for Combined {
+    fn rmw(&self, modification: Self) -> Self {
+        let mut value = self.0.clone();
+        combine(&mut value, &modification.0);
+        Combined(value)
+    }
+}
+
+struct FasterStore {
+    store: FasterKv,
+    serial: u64,
+    stats: FasterStats,
+    _directory: TempDir,
+}
+
+impl FasterStore {
+    fn new(config: &HashMap<String, String>) -> Self {
+        let table_size = config.get("tablesize").map_or(1 << 24, |s| {
+            s.parse::<u64>().expect("couldn't parse FASTER table size")
+        });
+        let log_size = config.get("logsize").map_or(1 << 30, |s| {
+            s.parse::<u64>().expect("couldn't parse FASTER log size")
+        });
+        let directory = TempDir::new_in(".").expect("Unable to create FASTER directory");
+        let store = FasterKv::new(
+            table_size,
+            log_size,
+            directory.path().to_str().unwrap().to_string(),
+        )
+        .expect("Unable to create FASTER store");
+        FasterStore {
+            store: store,
+            serial: 1,
+            stats: FasterStats::new(table_size),
+            _directory: directory,
+        }
+    }
+}
+
+impl Store for FasterStore {
+    fn get(&mut self, key: u64) -> bool {
+        let (status, recv): (u8, Receiver<Vec<u8>>) = self.store.read(&key, self.serial);
+        self.stats.count(status);
+        if status == status::PENDING {
+            self.store.complete_pending(true);
+            self.stats.completed(&self.store);
+        }
+        maybe_refresh_faster(&self.store, &mut self.serial, &mut self.stats);
+        recv.recv().is_ok()
+    }
+
+    fn put(&mut self, key: u64, value: &[u8]) {
+        let status = self.store.upsert(&key, &value.to_vec(), self.serial);
+        self.stats.count(status);
+        maybe_refresh_faster(&self.store, &mut self.serial, &mut self.stats);
+    }
+
+    fn remove(&mut self, key: u64) -> bool {
+        let found = self.get(key);
+        let status = self.store.delete(&key, self.serial);
+        self.stats.count(status);
+        maybe_refresh_faster(&self.store, &mut self.serial, &mut self.stats);
+        found
+    }
+
+    fn rmw(&mut self, key: u64, value: &[u8], kind: RmwKind) {
+        let status = match kind {
+            RmwKind::Combine => self.store.rmw(&key, &Combined(value.to_vec()), self.serial),
+            _ => self.store.rmw(&key, &value.to_vec(), self.serial),
+        };
+        self.stats.count(status);
+        maybe_refresh_faster(&self.store, &mut self.serial, &mut self.stats);
+    }
+
+    fn stats(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("log_size_bytes", self.stats.log_size_bytes as f64),
+            ("index_size_bytes", self.stats.index_size_bytes as f64),
+            ("pending_ops", self.stats.pending_ops as f64),
+            ("total_pending_ops", self.stats.total_pending_ops as f64),
+        ]
+    }
+
+    fn finish(&mut self) {
+        self.store.complete_pending(true);
+        self.stats.completed(&self.store);
+        // Prints the occupancy of the hash index, including its overflow buckets
+        self.store.dump_distribution();
+    }
+}
+
+fn append_merge(
+    _key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &mut MergeOperands,
+) -> Option<Vec<u8>> {
+    let mut result = existing.map_or(Vec::new(), |v| v.to_vec());
+    for operand in operands {
+        result.extend_from_slice(operand);
+    }
+    Some(result)
+}
+
+fn combine_merge(
+    _key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &mut MergeOperands,
+) -> Option<Vec<u8>> {
+    let mut result = existing.map_or(Vec::new(), |v| v.to_vec());
+    for operand in operands {
+        combine(&mut result, operand);
+    }
+    Some(result)
+}
+
+struct RocksDBStore {
+    db: DB,
+    // The read-modify-writes that use MERGE instead of GET and PUT, if any
+    merge: Option<RmwKind>,
+    _directory: TempDir,
+}
+
+impl RocksDBStore {
+    fn new(config: &HashMap<String, String>, merge: Option<RmwKind>) -> Self {
+        let parse = |key: &str| {
+            config.get(key).map(|s| {
+                s.parse::<usize>()
+                    .expect("couldn't parse RocksDB configuration")
+            })
+        };
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        if let Some(write_buffer_size) = parse("writebuffersize") {
+            options.set_write_buffer_size(write_buffer_size);
+        }
+        let mut block_options = BlockBasedOptions::default();
+        if let Some(block_size) = parse("blocksize") {
+            block_options.set_block_size(block_size);
+        }
+        if let Some(lru_size) = parse("lrusize") {
+            block_options.set_lru_cache(lru_size);
+        }
+        options.set_block_based_table_factory(&block_options);
+        // Dump statistics to the LOG often enough to sample them during the replay, with the
+        // block cache hits and misses
+        options.set_stats_dump_period_sec(1);
+        options.enable_statistics();
+        match merge {
+            Some(RmwKind::Append) => options.set_merge_operator("append", append_merge, None),
+            Some(RmwKind::Combine) => options.set_merge_operator("combine", combine_merge, None),
+            _ => {}
+        }
+        let directory = TempDir::new_in(".").expect("Unable to create RocksDB directory");
+        let db = DB::open(&options, directory.path()).expect("Unable to open RocksDB");
+        RocksDBStore {
+            db: db,
+            merge: merge,
+            _directory: directory,
+        }
+    }
+}
+
+impl Store for RocksDBStore {
+    fn get(&mut self, key: u64) -> bool {
+        self.db
+            .get(&key.to_le_bytes())
+            .expect("RocksDB get failed")
+            .is_some()
+    }
+
+    fn put(&mut self, key: u64, value: &[u8]) {
+        self.db
+            .put(&key.to_le_bytes(), value)
+            .expect("RocksDB put failed");
+    }
+
+    fn remove(&mut self, key: u64) -> bool {
+        let found = self.get(key);
+        self.db
+            .delete(&key.to_le_bytes())
+            .expect("RocksDB delete failed");
+        found
+    }
+
+    fn stats(&self) -> Vec<(&'static str, f64)> {
+        let rocksdb = rocksdb_instance_stats(&self.db);
+        let mut stats = vec![
+            ("memtable_bytes", rocksdb.memtable_bytes.unwrap_or(0) as f64),
+            ("estimated_keys", rocksdb.estimated_keys.unwrap_or(0) as f64),
+            ("ingest_bytes", rocksdb.ingest_bytes as f64),
+            ("compaction_write_bytes", rocksdb.compaction_write_bytes as f64),
+            ("write_amplification", rocksdb.write_amplification),
+            ("stall_seconds", rocksdb.stall_seconds),
+        ];
+        if let Some(hit_rate) = rocksdb.block_cache_hit_rate {
+            stats.push(("block_cache_hit_rate", hit_rate));
+        }
+        stats
+    }
+
+    fn rmw(&mut self, key: u64, value: &[u8], kind: RmwKind) {
+        match self.merge {
+            Some(merge) if merge == kind => {
+                self.db
+                    .merge(&key.to_le_bytes(), value)
+                    .expect("RocksDB merge failed");
+            }
+            // The dataflow does not run such queries on a merge backend either
+            Some(merge) => panic!(
+                "Cannot replay {:?} read-modify-writes with a {:?} merge operator",
+                kind, merge
+            ),
+            None => {
+                let mut current = self
+                    .db
+                    .get(&key.to_le_bytes())
+                    .expect("RocksDB get failed")
+                    .map_or(Vec::new(), |v| v.to_vec());
+                match kind {
+                    RmwKind::Combine => combine(&mut current, value),
+                    _ => current.extend_from_slice(value),
+                }
+                self.put(key, &current);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct InMemoryStore {
+    map: HashMap<u64, Vec<u8>>,
+}
+
+impl Store for InMemoryStore {
+    fn get(&mut self, key: u64) -> bool {
+        self.map.get(&key).is_some()
+    }
+
+    fn put(&mut self, key: u64, value: &[u8]) {
+        self.map.insert(key, value.to_vec());
+    }
+
+    fn remove(&mut self, key: u64) -> bool {
+        self.map.remove(&key).is_some()
+    }
+
+    fn rmw(&mut self, key: u64, value: &[u8], kind: RmwKind) {
+        let current = self.map.entry(key).or_insert_with(Vec::new);
+        match kind {
+            RmwKind::Combine => combine(current, value),
+            _ => current.extend_from_slice(value),
+        }
+    }
+}
+
+fn print_stats(accesses: u64, store: &dyn Store) {
+    for (metric, value) in store.stats() {
+        println!("replay_backend_stats\t{}\t{}\t{}", accesses, metric, value);
+    }
+}
+
+fn main() {
+    let matches = App::new("replay_trace")
+        .arg(
+            Arg::with_name("backend")
+                .long("backend")
+                .takes_value(true)
+                .possible_values(&[
+                    "faster",
+                    "rocksdb",
+                    "rocksdb-merge",
+                    "rocksdb-merge2",
+                    "mem",
+                ]),
+        )
+        .arg(
+            Arg::with_name("backend-config")
+                .long("backend-config")
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("traces").required(true).multiple(true))
+        .get_matches();
+
+    let backend = matches.value_of("backend").unwrap_or("faster");
+
+    // Defaults to the configuration file the dataflow backend reads
+    let backend_config = matches.value_of("backend-config").or(match backend {
+        "faster" => Some("faster.config"),
+        "rocksdb" => Some("rocksdb.config"),
+        "rocksdb-merge" => Some("rocksdbmerge.config"),
+        "rocksdb-merge2" => Some("rocksdbmerge2.config"),
+        _ => None,
+    });
+    let config = backend_config.map_or(HashMap::new(), |path| read_backend_config(Path::new(path)));
+
+    // As in the dataflow, `rocksdb-merge` merges appending read-modify-writes, and
+    // `rocksdb-merge2` adds up combining ones
+    let mut store: Box<dyn Store> = match backend {
+        "faster" => Box::new(FasterStore::new(&config)),
+        "rocksdb" => Box::new(RocksDBStore::new(&config, None)),
+        "rocksdb-merge" => Box::new(RocksDBStore::new(&config, Some(RmwKind::Append))),
+        "rocksdb-merge2" => Box::new(RocksDBStore::new(&config, Some(RmwKind::Combine))),
+        _ => Box::new(InMemoryStore::default()),
+    };
+
+    let mut latencies: Vec<HDRHist> = Op::ALL.iter().map(|_| HDRHist::new()).collect();
+    let mut all_latencies = HDRHist::new();
+    let mut counts = vec![0u64; Op::ALL.len()];
+    let mut values: HashMap<u32, Vec<u8>> = HashMap::new();
+    let mut accesses = 0;
+
+    let timer = Instant::now();
+    for (trace_index, path) in matches.values_of("traces").unwrap().enumerate() {
+        let reader = TraceReader::open(Path::new(path))
+            .unwrap_or_else(|e| panic!("Cannot open state trace {}: {}", path, e));
+        for record in reader {
+            let access = match record {
+                Record::Access(access) => access,
+                Record::State(_) => continue,
+            };
+            // States of different traces and operators do not share keys
+            let mut hasher = DefaultHasher::new();
+            (trace_index, access.id, access.key_hash).hash(&mut hasher);
+            let key = hasher.finish();
+            let value = values
+                .entry(access.value_size().unwrap_or(0))
+                .or_insert_with(|| vec![0; access.value_size().unwrap_or(0) as usize]);
+
+            let start = Instant::now();
+            match access.op {
+                Op::Get => {
+                    store.get(key);
+                }
+                Op::Insert | Op::Set => store.put(key, value),
+                Op::Remove | Op::Take => {
+                    store.remove(key);
+                }
+                Op::Rmw => store.rmw(key, value, access.rmw),
+            }
+            let latency_ns = start.elapsed().to_nanos();
+
+            latencies[access.op as usize].add_value(latency_ns);
+            all_latencies.add_value(latency_ns);
+            counts[access.op as usize] += 1;
+            accesses += 1;
+            if accesses % STATS_INTERVAL == 0 {
+                print_stats(accesses, store.as_ref());
+            }
+        }
+    }
+    store.finish();
+    let elapsed_ns = timer.elapsed().to_nanos();
+    print_stats(accesses, store.as_ref());
+
+    let total: u64 = counts.iter().sum();
+    println!("replay_backend\t{}", backend);
+    println!("replay_ops\t{}", total);
+    println!("replay_elapsed_ns\t{}", elapsed_ns);
+    println!(
+        "replay_throughput\t{}",
+        total as f64 / (elapsed_ns as f64 / 1_000_000_000.0)
+    );
+    for op in Op::ALL.iter() {
+        let count = counts[*op as usize];
+        if count == 0 {
+            continue;
+        }
+        println!("replay_ops_{}\t{}", op.name(), count);
+        for (value, prob, count) in latencies[*op as usize].ccdf() {
+            println!("latency_ccdf_{}\t{}\t{}\t{}", op.name(), value, prob, count);
+        }
+    }
+    for (value, prob, count) in all_latencies.ccdf() {
+        println!("latency_ccdf\t{}\t{}\t{}", value, prob, count);
+    }
+}
@@ -0,0 +1,310 @@
+//! Summarises the state workload of each operator in state traces recorded with `--trace-state`.
+//!
+//! Prints one `workload\t<operator>\t<metric>\t<value>` line per operator and metric, and the
+//! live state size of each operator over dataflow time as
+//! `live_state\t<operator>\t<time>\t<keys>\t<bytes>`. Operators are named
+//! `<dataflow>/<operator>`, where the dataflow is the query that built them.
+
+extern crate clap;
+extern crate nexmark;
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use clap::{App, Arg};
+
+use nexmark::trace::{Access, Op, Record, RmwKind, StateInfo, TraceReader};
+
+#[derive(Default)]
+struct KeyStats {
+    accesses: u64,
+    // Serialized size of the value, approximated for read-modify-writes: appending ones grow it
+    // by the size of their modification, and combining ones keep the larger of the two sizes
+    live_size: u64,
+    live: bool,
+    // Dataflow time at which the current value was created
+    created: u64,
+}
+
+#[derive(Default)]
+struct OperatorStats {
+    states: Vec<String>,
+    ops: [u64; 6],
+    keys: HashMap<(usize, u32, u64), KeyStats>,
+    value_sizes: Vec<u32>,
+    lifetimes: Vec<u64>,
+    scan_like: u64,
+    live_keys: i64,
+    live_bytes: i64,
+    // Live state size at the end of each interval, summed over traces
+    timeline: BTreeMap<u64, (i64, i64)>,
+}
+
+impl OperatorStats {
+    fn accesses(&self) -> u64 {
+        self.ops.iter().sum()
+    }
+
+    fn record(&mut self, trace: usize, access: &Access, scan_threshold: u32) {
+        self.ops[access.op as usize] += 1;
+        if let Some(size) = access.value_size() {
+            self.value_sizes.push(size);
+            let is_read = match access.op {
+                Op::Get | Op::Remove | Op::Take => true,
+                _ => false,
+            };
+            if is_read && size >= scan_threshold {
+                self.scan_like += 1;
+            }
+        }
+        let key = self
+            .keys
+            .entry((trace, access.id, access.key_hash))
+            .or_insert_with(KeyStats::default);
+        key.accesses += 1;
+        let size = access.value_size().unwrap_or(0) as u64;
+        match access.op {
+            Op::Get => {}
+            Op::Insert | Op::Set | Op::Rmw => {
+                if !key.live {
+                    key.live = true;
+                    key.created = access.time;
+                    key.live_size = 0;
+                    self.live_keys += 1;
+                }
+                let new_size = match (access.op, access.rmw) {
+                    (Op::Rmw, RmwKind::Combine) => std::cmp::max(key.live_size, size),
+                    (Op::Rmw, _) => key.live_size + size,
+                    _ => size,
+                };
+                self.live_bytes += new_size as i64 - key.live_size as i64;
+                key.live_size = new_size;
+            }
+            Op::Remove | Op::Take => {
+                if key.live {
+                    self.lifetimes.push(access.time.saturating_sub(key.created));
+                    self.live_keys -= 1;
+                    self.live_bytes -= key.live_size as i64;
+                    key.live = false;
+                    key.live_size = 0;
+                }
+            }
+        }
+    }
+}
+
+fn percentile<T: Copy + Default>(sorted: &[T], p: f64) -> T {
+    if sorted.is_empty() {
+        return T::default();
+    }
+    let index = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[index]
+}
+
+/// Fits `frequency ~ rank^-s` to the key access frequencies by least squares in log-log space.
+fn zipf_exponent(frequencies: &[u64]) -> f64 {
+    if frequencies.len() < 2 {
+        return 0.0;
+    }
+    let n = frequencies.len() as f64;
+    let points = frequencies
+        .iter()
+        .enumerate()
+        .map(|(rank, f)| (((rank + 1) as f64).ln(), (*f as f64).ln()));
+    let (mut sx, mut sy, mut sxx, mut sxy) = (0.0, 0.0, 0.0, 0.0);
+    for (x, y) in points {
+        sx += x;
+        sy += y;
+        sxx += x * x;
+        sxy += x * y;
+    }
+    let slope = (n * sxy - sx * sy) / (n * sxx - sx * sx);
+    -slope
+}
+
+fn main() {
+    let matches = App::new("trace_report")
+        .arg(
+            Arg::with_name("scan-threshold")
+                .long("scan-threshold")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .long("interval")
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("top-k").long("top-k").takes_value(true))
+        .arg(Arg::with_name("traces").required(true).multiple(true))
+        .get_matches();
+
+    // Reads and removes of values of at least this many bytes are counted as scan-like
+    let scan_threshold: u32 = matches
+        .value_of("scan-threshold")
+        .unwrap_or("1024")
+        .parse()
+        .expect("couldn't parse scan threshold");
+
+    // Nanoseconds of dataflow time between two live state size samples
+    let interval: u64 = matches
+        .value_of("interval")
+        .unwrap_or("1000000000")
+        .parse()
+        .expect("couldn't parse interval");
+    assert!(interval > 0);
+
+    let top_k: usize = matches
+        .value_of("top-k")
+        .unwrap_or("10")
+        .parse()
+        .expect("couldn't parse top-k");
+
+    let mut operators: BTreeMap<String, OperatorStats> = BTreeMap::new();
+
+    for (trace_index, path) in matches.values_of("traces").unwrap().enumerate() {
+        let reader = TraceReader::open(Path::new(path))
+            .unwrap_or_else(|e| panic!("Cannot open state trace {}: {}", path, e));
+        let mut states: HashMap<u32, String> = HashMap::new();
+        let mut last_time = 0;
+        for record in reader {
+            match record {
+                Record::State(StateInfo {
+                    id,
+                    dataflow,
+                    operator,
+                    name,
+                    ..
+                }) => {
+                    // Operators with the same name in different dataflows are reported apart
+                    let operator = if dataflow.is_empty() {
+                        operator
+                    } else {
+                        format!("{}/{}", dataflow, operator)
+                    };
+                    let stats = operators
+                        .entry(operator.clone())
+                        .or_insert_with(Default::default);
+                    if !stats.states.contains(&name) {
+                        stats.states.push(name);
+                    }
+                    states.insert(id, operator);
+                }
+                Record::Access(access) => {
+                    let operator = states.get(&access.id).expect("access to undefined state");
+                    // Sample the live state size of all operators at the end of each interval.
+                    // Operators access state at the times of their notifications too, so the
+                    // times of the accesses are not ordered and the highest one is the clock
+                    while last_time / interval < access.time / interval {
+                        let sample = (last_time / interval + 1) * interval;
+                        for stats in operators.values_mut() {
+                            let entry = stats.timeline.entry(sample).or_insert((0, 0));
+                            entry.0 += stats.live_keys;
+                            entry.1 += stats.live_bytes;
+                        }
+                        last_time = sample;
+                    }
+                    operators.get_mut(operator).unwrap().record(
+                        trace_index,
+                        &access,
+                        scan_threshold,
+                    );
+                }
+            }
+        }
+        // Live state is summed over traces, so each trace starts from empty state
+        for stats in operators.values_mut() {
+            stats.live_keys = 0;
+            stats.live_bytes = 0;
+        }
+    }
+
+    for (operator, stats) in operators.iter_mut() {
+        let accesses = stats.accesses();
+        if accesses == 0 {
+            continue;
+        }
+        let fraction = |count: u64| count as f64 / accesses as f64;
+        let ops = &stats.ops;
+        let reads = ops[Op::Get as usize];
+        let writes = ops[Op::Insert as usize] + ops[Op::Set as usize];
+        let deletes = ops[Op::Remove as usize] + ops[Op::Take as usize];
+        let rmws = ops[Op::Rmw as usize];
+
+        let mut frequencies: Vec<u64> = stats.keys.values().map(|k| k.accesses).collect();
+        frequencies.sort_unstable_by(|a, b| b.cmp(a));
+        let top_k_accesses: u64 = frequencies.iter().take(top_k).sum();
+        let top_percent = std::cmp::max(1, frequencies.len() / 100);
+        let top_percent_accesses: u64 = frequencies.iter().take(top_percent).sum();
+
+        stats.value_sizes.sort_unstable();
+        stats.lifetimes.sort_unstable();
+        let mean_lifetime = if stats.lifetimes.is_empty() {
+            0.0
+        } else {
+            stats.lifetimes.iter().sum::<u64>() as f64 / stats.lifetimes.len() as f64
+        };
+
+        let metrics: Vec<(&str, String)> = vec![
+            ("states", stats.states.join(",")),
+            ("accesses", accesses.to_string()),
+            ("read_fraction", fraction(reads).to_string()),
+            ("write_fraction", fraction(writes).to_string()),
+            ("delete_fraction", fraction(deletes).to_string()),
+            ("rmw_fraction", fraction(rmws).to_string()),
+            ("keys", frequencies.len().to_string()),
+            ("zipf_exponent", zipf_exponent(&frequencies).to_string()),
+            ("top_k_share", fraction(top_k_accesses).to_string()),
+            (
+                "top_1_percent_share",
+                fraction(top_percent_accesses).to_string(),
+            ),
+            (
+                "value_size_p50",
+                percentile(&stats.value_sizes, 0.5).to_string(),
+            ),
+            (
+                "value_size_p90",
+                percentile(&stats.value_sizes, 0.9).to_string(),
+            ),
+            (
+                "value_size_p99",
+                percentile(&stats.value_sizes, 0.99).to_string(),
+            ),
+            (
+                "value_size_max",
+                stats.value_sizes.last().cloned().unwrap_or(0).to_string(),
+            ),
+            (
+                "key_lifetime_p50",
+                percentile(&stats.lifetimes, 0.5).to_string(),
+            ),
+            (
+                "key_lifetime_p99",
+                percentile(&stats.lifetimes, 0.99).to_string(),
+            ),
+            ("key_lifetime_mean", mean_lifetime.to_string()),
+            (
+                "deleted_keys_fraction",
+                (stats.lifetimes.len() as f64 / frequencies.len() as f64).to_string(),
+            ),
+            ("scan_like_fraction", fraction(stats.scan_like).to_string()),
+        ];
+        for (metric, value) in metrics {
+            println!("workload\t{}\t{}\t{}", operator, metric, value);
+        }
+        for op in Op::ALL.iter() {
+            println!(
+                "workload\t{}\tops_{}\t{}",
+                operator,
+                op.name(),
+                ops[*op as usize]
+            );
+        }
+    }
+
+    for (operator, stats) in operators.iter() {
+        for (time, (keys, bytes)) in stats.timeline.iter() {
+            println!("live_state\t{}\t{}\t{}\t{}", operator, time, keys, bytes);
+        }
+    }
+}
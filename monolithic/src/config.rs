@@ -71,6 +71,9 @@ impl Config {
     }
 }
 
+use rand::rngs::SmallRng;
+use rand::Rng;
+use std::cmp::{max, min};
 use std::f64::consts::PI;
 
 // type Id = usize;
@@ -108,6 +111,110 @@ enum RateShape {
     Sine,
 }
 
+/// How the ids of auctions, bidders and sellers are chosen among the active ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyDistribution {
+    /// NEXMark default: a two-level split into hot and cold ids, set by the `hot-*-ratio` keys
+    Hot,
+    /// Uniform over the active ids
+    Uniform,
+    /// Zipf with the given exponent, where the most recent id is the most popular
+    Zipf(f64),
+    /// The `size` most recent ids are chosen with the given probability, the rest uniformly
+    HotSet { size: usize, probability: f64 },
+}
+
+impl KeyDistribution {
+    fn from_config(config: &Config, entity: &str) -> Self {
+        match config.get_or(&format!("{}-distribution", entity), "hot").as_str() {
+            "hot" => KeyDistribution::Hot,
+            "uniform" => KeyDistribution::Uniform,
+            "zipf" => {
+                KeyDistribution::Zipf(config.get_as_or(&format!("{}-zipf-exponent", entity), 1.0))
+            }
+            "hotset" => KeyDistribution::HotSet {
+                size: config.get_as_or(&format!("{}-hot-set-size", entity), 100),
+                probability: config.get_as_or(&format!("{}-hot-set-probability", entity), 0.9),
+            },
+            other => panic!("unknown {} distribution: {}", entity, other),
+        }
+    }
+
+    /// Samples the rank of an id among `n` active ids, where rank 0 is the most recent id.
+    ///
+    /// Returns `None` for `KeyDistribution::Hot`, whose ids are not chosen by rank.
+    pub fn sample_rank(&self, rng: &mut SmallRng, n: usize) -> Option<usize> {
+        debug_assert!(n > 0);
+        match *self {
+            KeyDistribution::Hot => None,
+            KeyDistribution::Uniform => Some(rng.gen_range(0, n)),
+            KeyDistribution::Zipf(exponent) => Some(zipf_rank(rng, n, exponent) - 1),
+            KeyDistribution::HotSet { size, probability } => {
+                let size = min(size, n);
+                if size == n || rng.gen::<f64>() < probability {
+                    Some(rng.gen_range(0, size))
+                } else {
+                    Some(rng.gen_range(size, n))
+                }
+            }
+        }
+    }
+}
+
+// log(1 + x) / x, accurate for small x
+fn ln_1p_div(x: f64) -> f64 {
+    if x.abs() > 1e-8 {
+        x.ln_1p() / x
+    } else {
+        1.0 - x * (0.5 - x * (1.0 / 3.0 - 0.25 * x))
+    }
+}
+
+// (exp(x) - 1) / x, accurate for small x
+fn exp_m1_div(x: f64) -> f64 {
+    if x.abs() > 1e-8 {
+        x.exp_m1() / x
+    } else {
+        1.0 + x * 0.5 * (1.0 + x * (1.0 / 3.0) * (1.0 + 0.25 * x))
+    }
+}
+
+/// Samples a rank in `[1, n]` with probability proportional to `rank^-exponent`.
+///
+/// Uses rejection-inversion (Hörmann and Derflinger, 1996), which needs no tables, so `n` may
+/// change with every sample.
+fn zipf_rank(rng: &mut SmallRng, n: usize, exponent: f64) -> usize {
+    let h = |x: f64| (-exponent * x.ln()).exp();
+    let h_integral = |x: f64| {
+        let log_x = x.ln();
+        exp_m1_div((1.0 - exponent) * log_x) * log_x
+    };
+    let h_integral_inverse = |x: f64| {
+        let t = (x * (1.0 - exponent)).max(-1.0);
+        (ln_1p_div(t) * x).exp()
+    };
+    let h_integral_x1 = h_integral(1.5) - 1.0;
+    let h_integral_n = h_integral(n as f64 + 0.5);
+    let s = 2.0 - h_integral_inverse(h_integral(2.5) - h(2.0));
+    loop {
+        let u = h_integral_n + rng.gen::<f64>() * (h_integral_x1 - h_integral_n);
+        let x = h_integral_inverse(u);
+        let k = max(1, min(n, (x + 0.5) as usize));
+        if k as f64 - x <= s || u >= h_integral(k as f64 + 0.5) - h(k as f64) {
+            return k;
+        }
+    }
+}
+
+/// The distribution of the lateness of out-of-order events.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LatenessDistribution {
+    /// Uniform between zero and the maximum lateness
+    Uniform,
+    /// Exponential with the given mean (in ns), truncated at the maximum lateness
+    Exponential(f64),
+}
+
 #[derive(Clone)]
 pub struct NEXMarkConfig {
     pub active_people: usize,
@@ -116,13 +223,32 @@ pub struct NEXMarkConfig {
     pub hot_seller_ratio: usize,
     pub hot_auction_ratio: usize,
     pub hot_bidder_ratio: usize,
+    pub hot_channel_ratio: usize,
+    pub num_channels: usize,
+    pub bid_extra_size: usize,
+    // Payload strings of people, auctions and bids
+    pub generate_payloads: bool,
+    pub item_name_length: usize,
+    pub description_length: usize,
+    pub email_length: usize,
+    pub email_domain_length: usize,
+    // How auctions, bidders and sellers are chosen
+    pub auction_distribution: KeyDistribution,
+    pub bidder_distribution: KeyDistribution,
+    pub seller_distribution: KeyDistribution,
+    // Fraction of events that are delivered late, by at most `max_lateness_ns`
+    pub out_of_order_fraction: f64,
+    pub max_lateness_ns: usize,
+    pub lateness_distribution: LatenessDistribution,
     pub first_event_id: usize,
     pub first_event_number: usize,
     pub base_time_ns: usize,
     pub step_length: usize,
     pub events_per_epoch: usize,
-    pub epoch_period: f32,
+    pub epoch_period: f64,
     pub inter_event_delays_ns: Vec<f64>,
+    // Number of events generated in each step of a non-constant rate shape
+    pub events_per_step: Vec<usize>,
     // Originally constants
     pub num_categories: usize,
     pub auction_id_lead: usize,
@@ -152,6 +278,27 @@ impl NEXMarkConfig {
         let hot_seller_ratio = config.get_as_or("hot-seller-ratio", 4);
         let hot_auction_ratio = config.get_as_or("hot-auction-ratio", 2);
         let hot_bidder_ratio = config.get_as_or("hot-bidder-ratio", 4);
+        let hot_channel_ratio = config.get_as_or("hot-channel-ratio", 2);
+        let num_channels = config.get_as_or("num-channels", 10_000);
+        let bid_extra_size = config.get_as_or("bid-extra-size", 32);
+        let generate_payloads = config.get_as_or("payloads", true);
+        let item_name_length = config.get_as_or("item-name-length", 20);
+        let description_length = config.get_as_or("description-length", 100);
+        let email_length = config.get_as_or("email-length", 7);
+        let email_domain_length = config.get_as_or("email-domain-length", 5);
+        let auction_distribution = KeyDistribution::from_config(config, "auction");
+        let bidder_distribution = KeyDistribution::from_config(config, "bidder");
+        let seller_distribution = KeyDistribution::from_config(config, "seller");
+        let out_of_order_fraction: f64 = config.get_as_or("out-of-order-fraction", 0.0);
+        assert!(out_of_order_fraction >= 0.0 && out_of_order_fraction <= 1.0);
+        let max_lateness_ns = config.get_as_or("max-lateness-ns", 0);
+        let lateness_distribution = match config.get_or("lateness-distribution", "uniform").as_str() {
+            "uniform" => LatenessDistribution::Uniform,
+            "exponential" => LatenessDistribution::Exponential(
+                config.get_as_or("mean-lateness-ns", max_lateness_ns as f64 / 4.0),
+            ),
+            other => panic!("unknown lateness distribution: {}", other),
+        };
         let first_event_id = config.get_as_or("first-event-id", 0);
         let first_event_number = config.get_as_or("first-event-number", 0);
         let num_categories = config.get_as_or("num-categories", 5);
@@ -208,7 +355,7 @@ impl NEXMarkConfig {
                 }
                 RateShape::Sine => {
                     let mid = (first_rate + next_rate) as f64 / 2.0;
-                    let amp = (first_rate - next_rate) as f64 / 2.0;
+                    let amp = (first_rate as f64 - next_rate as f64) / 2.0;
                     for i in 0..sine_approx_steps {
                         let r = (2.0 * PI * i as f64) / sine_approx_steps as f64;
                         let rate = mid + amp * r.cos();
@@ -225,15 +372,19 @@ impl NEXMarkConfig {
             sine_approx_steps
         };
         let step_length = (rate_period + n - 1) / n;
-        let events_per_epoch = 0;
-        let epoch_period = 0.0;
+        // An epoch is one full period of the rate shape, i.e. one step per inter-event delay.
+        let mut events_per_step = Vec::new();
+        let mut events_per_epoch = 0;
+        let mut epoch_period = 0.0;
         if inter_event_delays_ns.len() > 1 {
-            panic!("non-constant rate not supported");
-            // for inter_event_delay in &inter_event_delays {
-            //     let num_events_for_this_cycle = (step_length * 1_000_000) as f64 / inter_event_delay;
-            //     events_per_epoch += num_events_for_this_cycle.round() as usize;
-            //     epoch_period += (num_events_for_this_cycle * inter_event_delay) / 1000.0;
-            // }
+            for inter_event_delay in &inter_event_delays_ns {
+                let num_events_for_this_cycle =
+                    ((step_length * ns_per_unit) as f64 / inter_event_delay).round() as usize;
+                assert!(num_events_for_this_cycle > 0, "rate too low for the rate period");
+                events_per_step.push(num_events_for_this_cycle);
+                events_per_epoch += num_events_for_this_cycle;
+                epoch_period += num_events_for_this_cycle as f64 * inter_event_delay;
+            }
         }
         NEXMarkConfig {
             active_people: active_people,
@@ -242,6 +393,20 @@ impl NEXMarkConfig {
             hot_seller_ratio: hot_seller_ratio,
             hot_auction_ratio: hot_auction_ratio,
             hot_bidder_ratio: hot_bidder_ratio,
+            hot_channel_ratio: hot_channel_ratio,
+            num_channels: num_channels,
+            bid_extra_size: bid_extra_size,
+            generate_payloads: generate_payloads,
+            item_name_length: item_name_length,
+            description_length: description_length,
+            email_length: email_length,
+            email_domain_length: email_domain_length,
+            auction_distribution: auction_distribution,
+            bidder_distribution: bidder_distribution,
+            seller_distribution: seller_distribution,
+            out_of_order_fraction: out_of_order_fraction,
+            max_lateness_ns: max_lateness_ns,
+            lateness_distribution: lateness_distribution,
             first_event_id: first_event_id,
             first_event_number: first_event_number,
             base_time_ns: base_time_ns,
@@ -249,6 +414,7 @@ impl NEXMarkConfig {
             events_per_epoch: events_per_epoch,
             epoch_period: epoch_period,
             inter_event_delays_ns: inter_event_delays_ns,
+            events_per_step: events_per_step,
             // Originally constants
             num_categories: num_categories,
             auction_id_lead: auction_id_lead,
@@ -271,26 +437,56 @@ impl NEXMarkConfig {
         }
     }
 
+    /// Returns the event time (in ns) of the given event number.
     ///
+    /// For a constant rate this is a fixed multiple of the inter-event delay. Otherwise, the
+    /// rate shape repeats every `epoch_period` ns and each of its steps generates
+    /// `events_per_step` events at the step's inter-event delay.
     pub fn event_timestamp_ns(&self, event_number: usize) -> usize {
-        // if self.inter_event_delays.len() == 1 {
-        return self.base_time_ns
-            + ((event_number as f64 * self.inter_event_delays_ns[0]) as usize);
-        // }
-
-        // let epoch = event_number / self.events_per_epoch;
-        // let mut event_i = event_number % self.events_per_epoch;
-        // let mut offset_in_epoch = 0.0;
-        // for inter_event_delay in &self.inter_event_delays {
-        //     let num_events_for_this_cycle = (self.step_length * 1_000_000) as f32 / inter_event_delay;
-        //     if self.out_of_order_group_size < num_events_for_this_cycle.round() as usize {
-        //         let offset_in_cycle = event_i as f32 * inter_event_delay;
-        //         return self.base_time + (epoch as f32 * self.epoch_period + offset_in_epoch + offset_in_cycle / 1000.0).round() as usize;
-        //     }
-        //     event_i -= num_events_for_this_cycle.round() as usize;
-        //     offset_in_epoch += (num_events_for_this_cycle * inter_event_delay) / 1000.0;
-        // }
-        // return 0
+        if self.inter_event_delays_ns.len() == 1 {
+            return self.base_time_ns
+                + ((event_number as f64 * self.inter_event_delays_ns[0]) as usize);
+        }
+
+        let epoch = event_number / self.events_per_epoch;
+        let mut event_i = event_number % self.events_per_epoch;
+        let mut offset_in_epoch = 0.0;
+        for (inter_event_delay, num_events_for_this_cycle) in self
+            .inter_event_delays_ns
+            .iter()
+            .zip(self.events_per_step.iter())
+        {
+            if event_i < *num_events_for_this_cycle {
+                let offset_in_cycle = event_i as f64 * inter_event_delay;
+                return self.base_time_ns
+                    + (epoch as f64 * self.epoch_period + offset_in_epoch + offset_in_cycle)
+                        as usize;
+            }
+            event_i -= num_events_for_this_cycle;
+            offset_in_epoch += *num_events_for_this_cycle as f64 * inter_event_delay;
+        }
+        unreachable!("event {} does not fall in any step of the epoch", event_number)
+    }
+
+    /// Returns by how much (in ns) to delay the delivery of the next event, if it is late.
+    ///
+    /// Events are late with probability `out_of_order_fraction`, and their lateness follows
+    /// `lateness_distribution` up to `max_lateness_ns`.
+    pub fn lateness_ns(&self, rng: &mut SmallRng) -> Option<usize> {
+        if self.out_of_order_fraction == 0.0 || self.max_lateness_ns == 0 {
+            return None;
+        }
+        if rng.gen::<f64>() >= self.out_of_order_fraction {
+            return None;
+        }
+        let lateness = match self.lateness_distribution {
+            LatenessDistribution::Uniform => rng.gen_range(0, self.max_lateness_ns + 1),
+            LatenessDistribution::Exponential(mean) => {
+                let sample = -mean * (1.0 - rng.gen::<f64>()).ln();
+                sample.min(self.max_lateness_ns as f64) as usize
+            }
+        };
+        Some(lateness)
     }
 
     pub fn next_adjusted_event(&self, events_so_far: usize) -> usize {
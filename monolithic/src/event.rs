@@ -4,26 +4,36 @@ use rand::seq::SliceRandom;
 use rand::Rng;
 use std::cmp::{max, min};
 
-use crate::config::NEXMarkConfig;
+use crate::config::{KeyDistribution, NEXMarkConfig};
 
 trait NEXMarkRng {
-    fn gen_string(&mut self, _max: usize) -> String;
+    fn gen_string(&mut self, max: usize) -> String;
     fn gen_price(&mut self) -> usize;
 }
 
 impl NEXMarkRng for SmallRng {
-    fn gen_string(&mut self, _max: usize) -> String {
-        /*
-        use std::iter;
-        use rand::distributions::Alphanumeric;
-
-        let len = self.gen_range(MIN_STRING_LENGTH, max);
-        iter::repeat(())
-            .map(|()| self.sample(Alphanumeric))
-            .take(len)
-            .collect()
-            */
-        String::new()
+    /// Generates a random string of `MIN_STRING_LENGTH` to `max` symbols.
+    ///
+    /// Each random `u64` yields ten symbols and the string is allocated once.
+    fn gen_string(&mut self, max: usize) -> String {
+        let len = if max > MIN_STRING_LENGTH {
+            self.gen_range(MIN_STRING_LENGTH, max)
+        } else {
+            max
+        };
+        let mut bytes = Vec::with_capacity(len);
+        let mut bits: u64 = 0;
+        let mut available_bits = 0;
+        while bytes.len() < len {
+            if available_bits < 6 {
+                bits = self.gen();
+                available_bits = 64;
+            }
+            bytes.push(STRING_SYMBOLS[(bits & 0x3f) as usize]);
+            bits >>= 6;
+            available_bits -= 6;
+        }
+        String::from_utf8(bytes).expect("symbols must be ASCII")
     }
 
     fn gen_price(&mut self) -> usize {
@@ -32,6 +42,15 @@ impl NEXMarkRng for SmallRng {
 }
 
 type Id = usize;
+
+// Channels that receive most of the bids, as in the Beam/Flink NEXMark generators
+const HOT_CHANNELS: [&str; 4] = ["Apple", "Google", "Facebook", "Baidu"];
+const HOT_URLS: [&str; 4] = [
+    "https://www.nexmark.com/apple/item.htm?query=1",
+    "https://www.nexmark.com/google/item.htm?query=1",
+    "https://www.nexmark.com/facebook/item.htm?query=1",
+    "https://www.nexmark.com/baidu/item.htm?query=1",
+];
 #[derive(
     Eq,
     PartialEq,
@@ -81,20 +100,24 @@ impl FasterRmw for Date {
     }
 }
 
-//const MIN_STRING_LENGTH: usize = 3;
+const MIN_STRING_LENGTH: usize = 3;
+// 64 symbols, so that six random bits pick one. Vowels and spaces are more frequent.
+const STRING_SYMBOLS: &[u8; 64] =
+    b"abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyzaeiou       ";
 // const BASE_TIME: usize = 1436918400_000;
 
 // fn split_string_arg(string: String) -> Vec<String> {
 //     string.split(",").map(String::from).collect::<Vec<String>>()
 // }
 
+/// An event with the time it was sent at, as stored in recorded input.
 #[derive(Serialize, Deserialize, Abomonation, Debug)]
-struct EventCarrier {
-    time: Date,
-    event: Event,
+pub struct EventCarrier {
+    pub time: Date,
+    pub event: Event,
 }
 
-#[derive(Eq, PartialEq, Clone, Serialize, Deserialize, Debug, Abomonation)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Serialize, Deserialize, Debug, Abomonation)]
 #[serde(tag = "type")]
 pub enum Event {
     Person(Person),
@@ -197,17 +220,34 @@ impl Person {
     }
 
     fn new(id: usize, time: Date, rng: &mut SmallRng, nex: &NEXMarkConfig) -> Self {
+        let (name, email_address, credit_card) = if nex.generate_payloads {
+            (
+                format!(
+                    "{} {}",
+                    nex.first_names.choose(rng).unwrap(),
+                    nex.last_names.choose(rng).unwrap()
+                ),
+                format!(
+                    "{}@{}.com",
+                    rng.gen_string(nex.email_length),
+                    rng.gen_string(nex.email_domain_length)
+                ),
+                format!(
+                    "{:04} {:04} {:04} {:04}",
+                    rng.gen_range(0, 10000),
+                    rng.gen_range(0, 10000),
+                    rng.gen_range(0, 10000),
+                    rng.gen_range(0, 10000)
+                ),
+            )
+        } else {
+            (String::new(), String::new(), String::new())
+        };
         Person {
             id: Self::last_id(id, nex) + nex.first_person_id,
-            name: String::new(),
-            email_address: String::new(),
-            credit_card: String::new(),
-            // FIXME: Figure out a faster way to allocate the strings MH
-            //            name: format!("{} {}",
-            //                          *rng.choose(&nex.first_names).unwrap(),
-            //                          *rng.choose(&nex.last_names).unwrap()),
-            //            email_address: format!("{}@{}.com", rng.gen_string(7), rng.gen_string(5)),
-            //            credit_card: (0..4).map(|_| format!("{:04}", rng.gen_range(0, 10000))).collect::<Vec<String>>().join(" "),
+            name: name,
+            email_address: email_address,
+            credit_card: credit_card,
             city: nex.us_cities.choose(rng).unwrap().clone(),
             state: nex.us_states.choose(rng).unwrap().clone(),
             date_time: time,
@@ -220,6 +260,20 @@ impl Person {
         people - active + rng.gen_range(0, active + nex.person_id_lead)
     }
 
+    /// Chooses an active person according to `distribution`.
+    fn choose_id(
+        id: usize,
+        rng: &mut SmallRng,
+        nex: &NEXMarkConfig,
+        distribution: &KeyDistribution,
+    ) -> Option<Id> {
+        let people = Self::last_id(id, nex) + 1;
+        let active = min(people, nex.active_people);
+        distribution
+            .sample_rank(rng, active)
+            .map(|rank| people - 1 - rank)
+    }
+
     fn last_id(id: usize, nex: &NEXMarkConfig) -> Id {
         let epoch = id / nex.proportion_denominator;
         let mut offset = id % nex.proportion_denominator;
@@ -262,15 +316,28 @@ impl Auction {
         nex: &NEXMarkConfig,
     ) -> Self {
         let initial_bid = rng.gen_price();
-        let seller = if rng.gen_range(0, nex.hot_seller_ratio) > 0 {
-            (Person::last_id(id, nex) / nex.hot_seller_ratio_2) * nex.hot_seller_ratio_2
+        let seller = match Person::choose_id(id, rng, nex, &nex.seller_distribution) {
+            Some(seller) => seller,
+            None => {
+                if rng.gen_range(0, nex.hot_seller_ratio) > 0 {
+                    (Person::last_id(id, nex) / nex.hot_seller_ratio_2) * nex.hot_seller_ratio_2
+                } else {
+                    Person::next_id(id, rng, nex)
+                }
+            }
+        };
+        let (item_name, description) = if nex.generate_payloads {
+            (
+                rng.gen_string(nex.item_name_length),
+                rng.gen_string(nex.description_length),
+            )
         } else {
-            Person::next_id(id, rng, nex)
+            (String::new(), String::new())
         };
         Auction {
             id: Self::last_id(id, nex) + nex.first_auction_id,
-            item_name: rng.gen_string(20),
-            description: rng.gen_string(100),
+            item_name: item_name,
+            description: description,
             initial_bid: initial_bid,
             reserve: initial_bid + rng.gen_price(),
             date_time: time,
@@ -290,6 +357,24 @@ impl Auction {
         min_auction + rng.gen_range(0, max_auction - min_auction + 1 + nex.auction_id_lead)
     }
 
+    /// Chooses an in-flight auction according to `distribution`.
+    fn choose_id(
+        id: usize,
+        rng: &mut SmallRng,
+        nex: &NEXMarkConfig,
+        distribution: &KeyDistribution,
+    ) -> Option<Id> {
+        let max_auction = Self::last_id(id, nex);
+        let min_auction = if max_auction < nex.in_flight_auctions {
+            0
+        } else {
+            max_auction - nex.in_flight_auctions
+        };
+        distribution
+            .sample_rank(rng, max_auction - min_auction + 1)
+            .map(|rank| max_auction - rank)
+    }
+
     fn last_id(id: usize, nex: &NEXMarkConfig) -> Id {
         let mut epoch = id / nex.proportion_denominator;
         let mut offset = id % nex.proportion_denominator;
@@ -328,8 +413,15 @@ pub struct Bid {
     pub bidder: Id,
     pub price: usize,
     pub date_time: Date,
+    // The payload of a bid, which input files may leave out
+    #[serde(default)]
+    pub channel: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub extra: String,
 }
-// unsafe_abomonate!(Bid : auction, bidder, price, date_time);
+// unsafe_abomonate!(Bid : auction, bidder, price, date_time, channel, url, extra);
 
 impl Bid {
     pub fn from(event: Event) -> Option<Bid> {
@@ -340,21 +432,60 @@ impl Bid {
     }
 
     fn new(id: usize, time: Date, rng: &mut SmallRng, nex: &NEXMarkConfig) -> Self {
-        let auction = if 0 < rng.gen_range(0, nex.hot_auction_ratio) {
-            (Auction::last_id(id, nex) / nex.hot_auction_ratio_2) * nex.hot_auction_ratio_2
+        let auction = match Auction::choose_id(id, rng, nex, &nex.auction_distribution) {
+            Some(auction) => auction,
+            None => {
+                if 0 < rng.gen_range(0, nex.hot_auction_ratio) {
+                    (Auction::last_id(id, nex) / nex.hot_auction_ratio_2) * nex.hot_auction_ratio_2
+                } else {
+                    Auction::next_id(id, rng, nex)
+                }
+            }
+        };
+        let bidder = match Person::choose_id(id, rng, nex, &nex.bidder_distribution) {
+            Some(bidder) => bidder,
+            None => {
+                if 0 < rng.gen_range(0, nex.hot_bidder_ratio) {
+                    (Person::last_id(id, nex) / nex.hot_bidder_ratio_2) * nex.hot_bidder_ratio_2
+                        + 1
+                } else {
+                    Person::next_id(id, rng, nex)
+                }
+            }
+        };
+        // Draw the fields of the original generator first, so that they do not change
+        let price = rng.gen_price();
+        let (channel, url) = if !nex.generate_payloads {
+            (String::new(), String::new())
+        } else if 0 < rng.gen_range(0, nex.hot_channel_ratio) {
+            let i = rng.gen_range(0, HOT_CHANNELS.len());
+            (HOT_CHANNELS[i].to_string(), HOT_URLS[i].to_string())
         } else {
-            Auction::next_id(id, rng, nex)
+            let channel = rng.gen_range(0, nex.num_channels);
+            (
+                format!("channel-{}", channel),
+                format!(
+                    "https://www.nexmark.com/{}/{}/{}/item.htm?query=1&channel_id={}",
+                    rng.gen_string(5),
+                    rng.gen_string(5),
+                    rng.gen_string(5),
+                    channel
+                ),
+            )
         };
-        let bidder = if 0 < rng.gen_range(0, nex.hot_bidder_ratio) {
-            (Person::last_id(id, nex) / nex.hot_bidder_ratio_2) * nex.hot_bidder_ratio_2 + 1
+        let extra = if nex.generate_payloads {
+            rng.gen_string(nex.bid_extra_size)
         } else {
-            Person::next_id(id, rng, nex)
+            String::new()
         };
         Bid {
             auction: auction + nex.first_auction_id,
             bidder: bidder + nex.first_person_id,
-            price: rng.gen_price(),
+            price: price,
             date_time: time,
+            channel: channel,
+            url: url,
+            extra: extra,
         }
     }
 }
@@ -0,0 +1,320 @@
+//! Recording and replaying of generated input, and reading of events from files.
+//!
+//! A recording is a directory with one file `input_<worker>.json` per worker and a settings file
+//! `input.config`. Each line of a worker's file is an [`EventCarrier`] in JSON, holding an event
+//! and the time in nanoseconds since the start of the run at which it was sent to the dataflow.
+//! The settings file holds the generator settings, the duration and the number of workers of the
+//! recorded run as `key = value` lines.
+//!
+//! Input files hold events from other sources, one per line, either as JSON objects tagged with
+//! their `type` like `{"type":"Bid","auction":7,...}`, or as CSV records that start with the type
+//! followed by the fields of the event in declaration order, like `Bid,7,12,100,...`.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::event::{Date, Event, EventCarrier};
+use crate::tools::read_backend_config;
+
+const DURATION_KEY: &str = "duration-ns";
+const PEERS_KEY: &str = "peers";
+
+pub fn input_file(dir: &Path, worker: usize) -> PathBuf {
+    dir.join(format!("input_{}.json", worker))
+}
+
+pub fn settings_file(dir: &Path) -> PathBuf {
+    dir.join("input.config")
+}
+
+/// The settings of a recorded run.
+#[derive(Clone, Debug)]
+pub struct Recording {
+    /// Settings of the event generator, as passed to `Config`
+    pub settings: Vec<(String, String)>,
+    pub duration_ns: u64,
+    pub peers: usize,
+}
+
+impl Recording {
+    pub fn read(dir: &Path) -> Self {
+        let path = settings_file(dir);
+        let mut settings = read_backend_config(&path);
+        let duration_ns = settings
+            .remove(DURATION_KEY)
+            .and_then(|d| d.parse().ok())
+            .unwrap_or_else(|| panic!("{:?} has no valid {}", path, DURATION_KEY));
+        let peers = settings
+            .remove(PEERS_KEY)
+            .and_then(|p| p.parse().ok())
+            .unwrap_or_else(|| panic!("{:?} has no valid {}", path, PEERS_KEY));
+        Recording {
+            settings: settings.into_iter().collect(),
+            duration_ns: duration_ns,
+            peers: peers,
+        }
+    }
+
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        let mut f = File::create(settings_file(dir))?;
+        for (key, value) in self.settings.iter() {
+            writeln!(f, "{} = {}", key, value)?;
+        }
+        writeln!(f, "{} = {}", DURATION_KEY, self.duration_ns)?;
+        writeln!(f, "{} = {}", PEERS_KEY, self.peers)
+    }
+}
+
+/// Appends the events sent by a worker to its input file.
+pub struct EventWriter {
+    writer: BufWriter<File>,
+}
+
+impl EventWriter {
+    pub fn create(path: &Path) -> Self {
+        EventWriter {
+            writer: BufWriter::new(
+                File::create(path).unwrap_or_else(|e| panic!("Cannot create {:?}: {}", path, e)),
+            ),
+        }
+    }
+
+    /// Records that `event` was sent at `time_ns`.
+    pub fn write(&mut self, time_ns: u64, event: &Event) {
+        let carrier = EventCarrier {
+            time: Date::new(time_ns as usize),
+            event: event.clone(),
+        };
+        serde_json::to_writer(&mut self.writer, &carrier).expect("failed to record event");
+        self.writer.write_all(b"\n").expect("failed to record event");
+    }
+
+    pub fn finish(mut self) {
+        self.writer.flush().expect("failed to flush recorded input");
+    }
+}
+
+/// Events with the times at which to send them, in nanoseconds since the start of the run.
+pub struct TimedEvents {
+    events: Peekable<Box<dyn Iterator<Item = (u64, Event)>>>,
+}
+
+impl TimedEvents {
+    pub fn new<I: Iterator<Item = (u64, Event)> + 'static>(events: I) -> Self {
+        let events: Box<dyn Iterator<Item = (u64, Event)>> = Box::new(events);
+        TimedEvents {
+            events: events.peekable(),
+        }
+    }
+
+    /// Reads the events of a worker's input file, with their times divided by `speed`.
+    ///
+    /// Event and expiration times are divided by `speed` as well, so that events keep their
+    /// position relative to the epoch they are sent at.
+    pub fn replay(path: &Path, speed: f64) -> Self {
+        assert!(speed > 0.0, "replay speed must be positive");
+        let file = File::open(path).unwrap_or_else(|e| panic!("Cannot open {:?}: {}", path, e));
+        TimedEvents::new(BufReader::new(file).lines().map(move |line| {
+            let line = line.expect("failed to read recorded input");
+            let carrier: EventCarrier =
+                serde_json::from_str(&line).expect("failed to parse recorded event");
+            let mut event = carrier.event;
+            map_times(&mut event, |t| (t as f64 / speed) as usize);
+            ((*carrier.time as f64 / speed) as u64, event)
+        }))
+    }
+
+    /// Returns the next event if it is due at `time_ns`.
+    pub fn next_until(&mut self, time_ns: u64) -> Option<Event> {
+        if self.events.peek().map_or(false, |(t, _)| *t <= time_ns) {
+            self.events.next().map(|(_, event)| event)
+        } else {
+            None
+        }
+    }
+}
+
+impl Iterator for TimedEvents {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.events.next().map(|(_, event)| event)
+    }
+}
+
+/// Input times divided by `speed`, to measure latencies of an input replayed at a scaled rate.
+pub struct ScaledInputTimes<I> {
+    inner: I,
+    speed: f64,
+    next: Option<u64>,
+}
+
+impl<I: Iterator<Item = u64>> ScaledInputTimes<I> {
+    pub fn new(mut inner: I, speed: f64) -> Self {
+        let next = inner.next().map(|t| (t as f64 / speed) as u64);
+        ScaledInputTimes {
+            inner: inner,
+            speed: speed,
+            next: next,
+        }
+    }
+}
+
+impl<I: Iterator<Item = u64>> Iterator for ScaledInputTimes<I> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let speed = self.speed;
+        ::std::mem::replace(
+            &mut self.next,
+            self.inner.next().map(|t| (t as f64 / speed) as u64),
+        )
+    }
+}
+
+impl<I: Iterator<Item = u64>> ::streaming_harness::input::InputTimeResumableIterator<u64>
+    for ScaledInputTimes<I>
+{
+    fn peek(&mut self) -> Option<&u64> {
+        self.next.as_ref()
+    }
+
+    fn end(&self) -> bool {
+        self.next.is_none()
+    }
+}
+
+/// The format of an input file.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum InputFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(InputFormat::Csv),
+            "json" => Ok(InputFormat::Json),
+            _ => Err(format!("unknown input format '{}', expected csv or json", s)),
+        }
+    }
+}
+
+/// How the events of an input file are paced.
+#[derive(Copy, Clone, Debug)]
+pub enum Pacing {
+    /// By their `date_time`, given in units of `unit_ns` nanoseconds
+    EventTime { unit_ns: u64 },
+    /// At a constant rate of events per second
+    Rate(u64),
+}
+
+// Applies `f` to the event time and, for auctions, the expiration time of `event`.
+fn map_times<F: Fn(usize) -> usize>(event: &mut Event, f: F) {
+    match event {
+        Event::Person(p) => p.date_time = Date::new(f(*p.date_time)),
+        Event::Auction(a) => {
+            a.date_time = Date::new(f(*a.date_time));
+            a.expires = Date::new(f(*a.expires));
+        }
+        Event::Bid(b) => b.date_time = Date::new(f(*b.date_time)),
+    }
+}
+
+fn parse_csv(record: &csv::StringRecord) -> Result<Event, String> {
+    let fields: csv::StringRecord = record.iter().skip(1).collect();
+    let event = match record.get(0) {
+        Some("Person") => fields.deserialize(None).map(Event::Person),
+        Some("Auction") => fields.deserialize(None).map(Event::Auction),
+        Some("Bid") => fields.deserialize(None).map(Event::Bid),
+        other => return Err(format!("unknown event type {:?}", other)),
+    };
+    event.map_err(|e| e.to_string())
+}
+
+fn parse_events(path: &Path, format: InputFormat) -> Vec<Event> {
+    let file = File::open(path).unwrap_or_else(|e| panic!("Cannot open {:?}: {}", path, e));
+    match format {
+        InputFormat::Json => BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+            .map(|(i, line)| {
+                let line = line.unwrap_or_else(|e| panic!("Cannot read {:?}: {}", path, e));
+                serde_json::from_str(&line)
+                    .unwrap_or_else(|e| panic!("{:?}:{}: invalid event: {}", path, i + 1, e))
+            })
+            .collect(),
+        InputFormat::Csv => csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(file)
+            .records()
+            .enumerate()
+            .map(|(i, record)| {
+                let record = record.unwrap_or_else(|e| panic!("Cannot read {:?}: {}", path, e));
+                parse_csv(&record)
+                    .unwrap_or_else(|e| panic!("{:?}:{}: invalid event: {}", path, i + 1, e))
+            })
+            .collect(),
+    }
+}
+
+/// Reads the events of an input file, with the times at which to send them.
+///
+/// Event times are rewritten to nanoseconds since the start of the run, so that they are
+/// comparable to epochs. With `Pacing::EventTime`, the first event is sent at time zero and every
+/// event when its rewritten `date_time` is reached, but not before the events preceding it in the
+/// file. With `Pacing::Rate`, each event's `date_time` is replaced by the time it is sent at and
+/// an auction's expiration time is moved along. Events sent after `duration_ns` are dropped.
+///
+/// Events that are out of order in the file are sent after their event time. The driver holds
+/// back the input frontier by `max_disorder_ns` of the events, so that they are not late.
+pub fn read_input_file(
+    path: &Path,
+    format: InputFormat,
+    pacing: Pacing,
+    duration_ns: Option<u64>,
+) -> Vec<(u64, Event)> {
+    let mut events = parse_events(path, format);
+    let mut timed = Vec::with_capacity(events.len());
+    let first = events.first().map_or(0, |e| *e.time());
+    let mut last_ns = 0;
+    for (i, mut event) in events.drain(..).enumerate() {
+        let time_ns = match pacing {
+            Pacing::EventTime { unit_ns } => {
+                map_times(&mut event, |t| t.saturating_sub(first) * unit_ns as usize);
+                ::std::cmp::max(last_ns, *event.time() as u64)
+            }
+            Pacing::Rate(rate) => {
+                assert!(rate > 0, "input files cannot be paced at a rate of 0");
+                let time_ns = i as u64 * 1_000_000_000 / rate;
+                let date_time = *event.time();
+                map_times(&mut event, |t| t.saturating_sub(date_time) + time_ns as usize);
+                time_ns
+            }
+        };
+        if duration_ns.map_or(false, |d| time_ns >= d) {
+            break;
+        }
+        last_ns = time_ns;
+        timed.push((time_ns, event));
+    }
+    timed
+}
+
+/// Returns the longest time an event is sent after its event time, as events that are out of
+/// order in an input file are sent with the events that precede them.
+pub fn max_disorder_ns(events: &[(u64, Event)]) -> u64 {
+    events
+        .iter()
+        .map(|(time_ns, event)| time_ns.saturating_sub(*event.time() as u64))
+        .max()
+        .unwrap_or(0)
+}
@@ -1,4 +1,5 @@
 extern crate abomonation;
+extern crate csv;
 #[macro_use]
 extern crate abomonation_derive;
 extern crate serde;
@@ -7,11 +8,18 @@ extern crate timely;
 #[macro_use]
 extern crate serde_derive;
 extern crate faster_rs;
+extern crate libc;
 extern crate rand;
+extern crate rocksdb;
 extern crate streaming_harness;
 
 pub mod config;
 pub mod event;
+pub mod input;
+pub mod profile;
+pub mod results;
+pub mod stats;
 pub mod tools;
+pub mod trace;
 
 pub mod queries;
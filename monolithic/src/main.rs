@@ -23,22 +23,17 @@ use clap::{App, Arg};
 use metrics_runtime::Receiver;
 use streaming_harness::util::ToNanos;
 
-use timely::dataflow::operators::{Capture, Probe};
 use timely::dataflow::{InputHandle, ProbeHandle};
 
-use timely::dataflow::channels::pact::Pipeline;
-use timely::dataflow::operators::Operator;
-use timely::dataflow::Scope;
-use timely::dataflow::Stream;
-use timely::state::backends::{
-    FASTERBackend, InMemoryBackend, RocksDBBackend, RocksDBMergeBackend, RocksDBMergeBackend2
-};
-use timely::ExchangeData;
+use timely::state::backends::InMemoryBackend;
 
 use nexmark::event::Event;
+use nexmark::queries::registry::{self, Backend, QueryParams};
 use nexmark::queries::{NexmarkInput, NexmarkTimer};
 //use timely::dataflow::operators::inspect::Inspect;
 
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::time::Duration;
 use std::io::Write;
 use metrics_runtime::exporters::LogExporter;
@@ -46,69 +41,63 @@ use metrics_runtime::observers::YamlBuilder;
 use log::Level;
 use std::fs::File;
 
-#[allow(dead_code)]
-fn verify<S: Scope, T: ExchangeData + Ord + ::std::fmt::Debug>(
-    correct: &Stream<S, T>,
-    output: &Stream<S, T>,
-) -> Stream<S, ()> {
-    use std::collections::HashMap;
-    use timely::dataflow::channels::pact::Exchange;
-    let mut in1_pending: HashMap<_, Vec<_>> = Default::default();
-    let mut in2_pending: HashMap<_, Vec<_>> = Default::default();
-    let mut data_buffer: Vec<T> = Vec::new();
-    correct.binary_notify(
-        &output,
-        Exchange::new(|_| 0),
-        Exchange::new(|_| 0),
-        "Verify",
-        vec![],
-        move |in1, in2, _out, not, _state_handle| {
-            in1.for_each(|time, data| {
-                data.swap(&mut data_buffer);
-                in1_pending
-                    .entry(time.time().clone())
-                    .or_insert_with(Default::default)
-                    .extend(data_buffer.drain(..));
-                not.notify_at(time.retain());
-            });
-            in2.for_each(|time, data| {
-                data.swap(&mut data_buffer);
-                in2_pending
-                    .entry(time.time().clone())
-                    .or_insert_with(Default::default)
-                    .extend(data_buffer.drain(..));
-                not.notify_at(time.retain());
-            });
-            not.for_each(|time, _, _| {
-                let mut v1 = in1_pending.remove(time.time()).unwrap_or_default();
-                let mut v2 = in2_pending.remove(time.time()).unwrap_or_default();
-                v1.sort();
-                v2.sort();
-                assert_eq!(v1.len(), v2.len());
-                let i1 = v1.iter();
-                let i2 = v2.iter();
-                for (a, b) in i1.zip(i2) {
-                    //                    println!("a: {:?}, b: {:?}", a, b);
-                    assert_eq!(a, b, " at {:?}", time.time());
-                }
-            })
-        },
-    )
-}
-
 fn main() {
     let matches = App::new("window_evaluation")
         .arg(
             Arg::with_name("rate")
                 .long("rate")
                 .takes_value(true)
-                .required(true),
+                .required_unless_one(&["replay-input", "input-file"]),
         )
         .arg(
             Arg::with_name("duration")
                 .long("duration")
                 .takes_value(true)
-                .required(true),
+                .required_unless_one(&["replay-input", "input-file"]),
+        )
+        .arg(
+            Arg::with_name("rate-shape")
+                .long("rate-shape")
+                .takes_value(true)
+                .possible_values(&["square", "sine"]),
+        )
+        .arg(
+            Arg::with_name("rate-period")
+                .long("rate-period")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("next-rate")
+                .long("next-rate")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("out-of-order-fraction")
+                .long("out-of-order-fraction")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-lateness")
+                .long("max-lateness")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("lateness-distribution")
+                .long("lateness-distribution")
+                .takes_value(true)
+                .possible_values(&["uniform", "exponential"]),
+        )
+        .arg(
+            Arg::with_name("mean-lateness")
+                .long("mean-lateness")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("nexmark-config")
+                .long("nexmark-config")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
         )
         .arg(
             Arg::with_name("window-slice-count")
@@ -122,6 +111,51 @@ fn main() {
                 .takes_value(true),
                 //.required(true),
         )
+        .arg(
+            Arg::with_name("window-count")
+                .long("window-count")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("window-top-k")
+                .long("window-top-k")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("q5-window-slice-count")
+                .long("q5-window-slice-count")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("q5-window-slide")
+                .long("q5-window-slide")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("q7-window-size")
+                .long("q7-window-size")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("q8-window-size")
+                .long("q8-window-size")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("q12-window-size")
+                .long("q12-window-size")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("q13-side-input-size")
+                .long("q13-side-input-size")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("session-gap")
+                .long("session-gap")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("queries")
                 .long("queries")
@@ -130,6 +164,12 @@ fn main() {
                 .multiple(true)
                 .value_delimiter(" "),
         )
+        .arg(
+            Arg::with_name("backend")
+                .long("backend")
+                .takes_value(true)
+                .possible_values(Backend::NAMES),
+        )
         .arg(
             Arg::with_name("metrics")
                 .long("metrics")
@@ -138,6 +178,10 @@ fn main() {
             Arg::with_name("print-rss")
                 .long("print-rss")
         )
+        .arg(
+            Arg::with_name("no-payloads")
+                .long("no-payloads")
+        )
         .arg(
             Arg::with_name("latency-output")
                 .long("latency-output")
@@ -150,25 +194,148 @@ fn main() {
                 .takes_value(true)
                 .required(false)
         )
+        .arg(
+            Arg::with_name("results-output")
+                .long("results-output")
+                .takes_value(true)
+                .required(false)
+        )
+        .arg(
+            Arg::with_name("backend-stats")
+                .long("backend-stats")
+        )
+        .arg(
+            Arg::with_name("profile-operators")
+                .long("profile-operators")
+        )
+        .arg(
+            Arg::with_name("state-dir")
+                .long("state-dir")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+        )
+        .arg(
+            Arg::with_name("trace-state")
+                .long("trace-state")
+                .takes_value(true)
+                .required(false)
+        )
+        .arg(
+            Arg::with_name("record-input")
+                .long("record-input")
+                .takes_value(true)
+                .required(false)
+        )
+        .arg(
+            Arg::with_name("replay-input")
+                .long("replay-input")
+                .takes_value(true)
+                .required(false)
+                .conflicts_with("record-input")
+        )
+        .arg(
+            Arg::with_name("replay-speed")
+                .long("replay-speed")
+                .takes_value(true)
+                .required(false)
+                .requires("replay-input")
+        )
+        .arg(
+            Arg::with_name("search-throughput")
+                .long("search-throughput")
+                .conflicts_with_all(&["record-input", "replay-input", "input-file", "next-rate"])
+        )
+        .arg(
+            Arg::with_name("latency-bound")
+                .long("latency-bound")
+                .takes_value(true)
+                .requires("search-throughput")
+        )
+        .arg(
+            Arg::with_name("search-steps")
+                .long("search-steps")
+                .takes_value(true)
+                .requires("search-throughput")
+        )
+        .arg(
+            Arg::with_name("input-file")
+                .long("input-file")
+                .takes_value(true)
+                .required(false)
+                .conflicts_with_all(&["record-input", "replay-input"])
+        )
+        .arg(
+            Arg::with_name("input-format")
+                .long("input-format")
+                .takes_value(true)
+                .possible_values(&["csv", "json"])
+                .requires("input-file")
+        )
+        .arg(
+            Arg::with_name("input-time-unit")
+                .long("input-time-unit")
+                .takes_value(true)
+                .possible_values(&["ns", "us", "ms", "s"])
+                .requires("input-file")
+        )
         .arg(Arg::with_name("timely").multiple(true))
         .get_matches();
     let timely_args = matches
         .values_of("timely")
         .map_or(Vec::new(), |vs| vs.map(String::from).collect());
 
+    // Rate and duration are taken from the recording when replaying input, and are optional
+    // when reading an input file
     let rate: u64 = matches
         .value_of("rate")
-        .expect("rate absent")
+        .unwrap_or("0")
         .parse::<u64>()
         .expect("couldn't parse rate");
 
     let duration_ns: u64 = matches
         .value_of("duration")
-        .expect("duration absent")
+        .unwrap_or("0")
         .parse::<u64>()
         .expect("couldn't parse duration")
         * 1_000_000_000;
 
+    // The input rate alternates between `rate` and `next-rate` over a period of `rate-period` seconds
+    let rate_shape = matches.value_of("rate-shape").unwrap_or("sine").to_string();
+
+    let rate_period: u64 = matches
+        .value_of("rate-period")
+        .unwrap_or("600")
+        .parse::<u64>()
+        .expect("couldn't parse rate period");
+
+    let next_rate: u64 = matches
+        .value_of("next-rate")
+        .map_or(rate, |r| r.parse::<u64>().expect("couldn't parse next rate"));
+
+    // A fraction of the events is delivered late by up to `max-lateness` ms
+    let out_of_order_fraction: f64 = matches
+        .value_of("out-of-order-fraction")
+        .unwrap_or("0")
+        .parse::<f64>()
+        .expect("couldn't parse out-of-order fraction");
+
+    let max_lateness_ns: u64 = matches
+        .value_of("max-lateness")
+        .unwrap_or("0")
+        .parse::<u64>()
+        .expect("couldn't parse max lateness")
+        * 1_000_000;
+
+    let lateness_distribution = matches
+        .value_of("lateness-distribution")
+        .unwrap_or("uniform")
+        .to_string();
+
+    let mean_lateness_ns: Option<u64> = matches.value_of("mean-lateness").map(|m| {
+        m.parse::<u64>().expect("couldn't parse mean lateness") * 1_000_000
+    });
+
     let window_slice_count: usize = matches
         .value_of("window-slice-count")
         .unwrap_or("0")
@@ -182,24 +349,273 @@ fn main() {
         .expect("couldn't parse window slide")
         * 1_000_000_000;
 
-    let queries: Vec<_> = matches
+    let window_count: usize = matches
+        .value_of("window-count")
+        .unwrap_or("0")
+        .parse::<usize>()
+        .expect("couldn't parse window count");
+
+    let window_top_k: usize = matches
+        .value_of("window-top-k")
+        .unwrap_or("0")
+        .parse::<usize>()
+        .expect("couldn't parse window top-k");
+
+    // Queries run on the given backend unless their (legacy) name selects one
+    let backend: Backend = matches
+        .value_of("backend")
+        .unwrap_or("faster")
+        .parse()
+        .expect("couldn't parse backend");
+
+    let queries: Vec<(&'static str, Backend)> = matches
         .values_of("queries")
         .unwrap()
-        .map(String::from)
+        .map(|q| registry::resolve(q, backend).unwrap_or_else(|e| panic!("{}", e)))
         .collect();
 
+    // Window parameters of Q5, Q7, Q8, Q12 and sessions (in seconds) and the side input size of Q13
+    // default to the values in `QueryParams`
+    let default_params = QueryParams::default();
+
+    let q5_window_slice_count: usize = matches
+        .value_of("q5-window-slice-count")
+        .map_or(default_params.q5_window_slice_count, |c| {
+            c.parse::<usize>().expect("couldn't parse Q5 window slice count")
+        });
+
+    let q5_window_slide_ns: usize = matches
+        .value_of("q5-window-slide")
+        .map_or(default_params.q5_window_slide_ns, |s| {
+            s.parse::<usize>().expect("couldn't parse Q5 window slide") * 1_000_000_000
+        });
+
+    let q7_window_size_ns: usize = matches
+        .value_of("q7-window-size")
+        .map_or(default_params.q7_window_size_ns, |s| {
+            s.parse::<usize>().expect("couldn't parse Q7 window size") * 1_000_000_000
+        });
+
+    let q8_window_size_ns: usize = matches
+        .value_of("q8-window-size")
+        .map_or(default_params.q8_window_size_ns, |s| {
+            s.parse::<usize>().expect("couldn't parse Q8 window size") * 1_000_000_000
+        });
+
+    let q12_window_size_ns: usize = matches
+        .value_of("q12-window-size")
+        .map_or(default_params.q12_window_size_ns, |s| {
+            s.parse::<usize>().expect("couldn't parse Q12 window size") * 1_000_000_000
+        });
+
+    let q13_side_input_size: usize = matches
+        .value_of("q13-side-input-size")
+        .map_or(default_params.q13_side_input_size, |s| {
+            s.parse::<usize>().expect("couldn't parse Q13 side input size")
+        });
+
+    let session_gap_ns: usize = matches
+        .value_of("session-gap")
+        .map_or(default_params.session_gap_ns, |s| {
+            s.parse::<usize>().expect("couldn't parse session gap") * 1_000_000_000
+        });
+
+    assert!(q5_window_slice_count > 0);
+    assert!(q5_window_slide_ns > 0);
+    assert!(q7_window_size_ns > 0);
+    assert!(q8_window_size_ns > 0);
+    assert!(q12_window_size_ns > 0);
+    assert!(q13_side_input_size > 0);
+    assert!(session_gap_ns > 0);
+
+    let query_params = QueryParams {
+        window_slice_count: window_slice_count,
+        window_slide_ns: window_slide_ns,
+        window_count: window_count,
+        window_top_k: window_top_k,
+        q5_window_slice_count: q5_window_slice_count,
+        q5_window_slide_ns: q5_window_slide_ns,
+        q7_window_size_ns: q7_window_size_ns,
+        q8_window_size_ns: q8_window_size_ns,
+        q12_window_size_ns: q12_window_size_ns,
+        q13_side_input_size: q13_side_input_size,
+        session_gap_ns: session_gap_ns,
+    };
+
     let enable_metrics = matches
         .occurrences_of("metrics") > 0;
 
     let enable_rss = matches
         .occurrences_of("print-rss") > 0;
 
+    // Generate people, auctions and bids without string payloads
+    let enable_payloads = matches
+        .occurrences_of("no-payloads") == 0;
+
+    // Additional generator settings as `key=value` pairs, e.g. `bidder-distribution=zipf`
+    let nexmark_config: Vec<(String, String)> = matches
+        .values_of("nexmark-config")
+        .map(|values| {
+            values
+                .map(|kv| {
+                    let mut parts = kv.splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some(key), Some(value)) => (key.to_string(), value.to_string()),
+                        _ => panic!("--nexmark-config expects key=value, got '{}'", kv),
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Directory to record the events sent by each worker to
+    let record_dir = matches
+        .value_of("record-input")
+        .map(String::from);
+    if let Some(ref dir) = record_dir {
+        std::fs::create_dir_all(dir).expect("Cannot create input recording directory");
+    }
+
+    // Directory of a recording to replay instead of generating events
+    let replay_dir = matches
+        .value_of("replay-input")
+        .map(String::from);
+
+    // Replay the recording this many times faster than it was recorded
+    let replay_speed: f64 = matches
+        .value_of("replay-speed")
+        .unwrap_or("1.0")
+        .parse::<f64>()
+        .expect("couldn't parse replay speed");
+    assert!(replay_speed > 0.0);
+
+    // Settings of the event generator for the given input rates
+    let generator_settings = |rate: u64, next_rate: u64| {
+        let mut settings: Vec<(String, String)> = vec![
+            // 0.06*60*60*12 = 0.06*60*60*12
+            // auction_proportion*sec_in_12h
+            ("in-flight-auctions".to_string(), format!("{}", rate * 2592)),
+            ("events-per-second".to_string(), format!("{}", rate)),
+            ("next-event-rate".to_string(), format!("{}", next_rate)),
+            ("rate-shape".to_string(), rate_shape.clone()),
+            ("rate-period".to_string(), format!("{}", rate_period)),
+            ("payloads".to_string(), format!("{}", enable_payloads)),
+            ("out-of-order-fraction".to_string(), format!("{}", out_of_order_fraction)),
+            ("max-lateness-ns".to_string(), format!("{}", max_lateness_ns)),
+            ("lateness-distribution".to_string(), lateness_distribution.clone()),
+        ];
+        if let Some(mean_lateness_ns) = mean_lateness_ns {
+            settings.push(("mean-lateness-ns".to_string(), format!("{}", mean_lateness_ns)));
+        }
+        settings.extend(nexmark_config.iter().cloned());
+        settings
+    };
+
+    // A replayed run uses the settings of the recorded run
+    let recording = match replay_dir {
+        Some(ref dir) => nexmark::input::Recording::read(std::path::Path::new(dir)),
+        None => nexmark::input::Recording {
+            settings: generator_settings(rate, next_rate),
+            duration_ns: duration_ns,
+            peers: 0,
+        },
+    };
+
+    // Events of an input file, shared by all workers, which send every `peers`-th of them
+    let input_events = matches.value_of("input-file").map(|path| {
+        let path = std::path::Path::new(path);
+        let format: nexmark::input::InputFormat = matches
+            .value_of("input-format")
+            .map_or_else(
+                || if path.extension().map_or(false, |e| e == "csv") { "csv" } else { "json" },
+                |f| f,
+            )
+            .parse()
+            .expect("couldn't parse input format");
+        // Events are paced by `--rate` if given, otherwise by their event times
+        let pacing = if matches.is_present("rate") {
+            assert!(rate > 0, "--rate must be positive");
+            nexmark::input::Pacing::Rate(rate)
+        } else {
+            let unit_ns = match matches.value_of("input-time-unit").unwrap_or("ns") {
+                "s" => 1_000_000_000,
+                "ms" => 1_000_000,
+                "us" => 1_000,
+                "ns" => 1,
+                unit => panic!("unknown input time unit '{}', expected ns, us, ms or s", unit),
+            };
+            nexmark::input::Pacing::EventTime { unit_ns: unit_ns }
+        };
+        let duration_ns = if matches.is_present("duration") { Some(duration_ns) } else { None };
+        std::sync::Arc::new(nexmark::input::read_input_file(path, format, pacing, duration_ns))
+    });
+
+    let duration_ns = match input_events {
+        Some(ref events) => events.last().map_or(0, |(t, _)| *t + 1),
+        None => recording.duration_ns,
+    };
+    // Events of the input file are sent at most this long after their event time
+    let input_disorder_ns = input_events
+        .as_ref()
+        .map_or(0, |events| nexmark::input::max_disorder_ns(events));
+    // Search the highest rate up to which p99 latencies stay within this bound (in ms)
+    let latency_bound_ns: Option<u64> = if matches.is_present("search-throughput") {
+        Some(
+            matches
+                .value_of("latency-bound")
+                .unwrap_or("1000")
+                .parse::<u64>()
+                .expect("couldn't parse latency bound")
+                * 1_000_000,
+        )
+    } else {
+        None
+    };
+
+    let search_steps: usize = matches
+        .value_of("search-steps")
+        .unwrap_or("10")
+        .parse::<usize>()
+        .expect("couldn't parse search steps");
+    // Each process would decide on its own whether a trial is sustainable, from the latencies
+    // of its workers only, and could go on with a different rate than the others
+    assert!(
+        latency_bound_ns.is_none() || nexmark::tools::timely_processes(&timely_args) == 1,
+        "--search-throughput only supports runs of a single process"
+    );
+
+    // Wall-clock duration of the run
+    let run_ns = (duration_ns as f64 / replay_speed) as u64;
+    // Latencies are measured from the third second on
+    assert!(
+        run_ns > 2_000_000_000,
+        "runs must last longer than the first 2 seconds, which latencies are not measured in, \
+         but this one lasts {}ns",
+        run_ns
+    );
+
     let latency_output = matches
         .value_of("latency-output");
 
+    let results_output = matches
+        .value_of("results-output");
+
+    // State directories whose size is sampled, by default the backends' temporary directories
+    let state_dirs: Vec<std::path::PathBuf> = matches
+        .values_of("state-dir")
+        .map_or(Vec::new(), |dirs| dirs.map(std::path::PathBuf::from).collect());
+
     let timeline_output = matches
         .value_of("timeline-output");
 
+    // Directory to write a trace of the state accesses of each worker to
+    let trace_dir = matches
+        .value_of("trace-state")
+        .map(String::from);
+    if let Some(ref dir) = trace_dir {
+        std::fs::create_dir_all(dir).expect("Cannot create state trace directory");
+    }
+
     if enable_metrics {
         // Collect metrics
         env_logger::init();
@@ -211,614 +627,610 @@ fn main() {
         std::thread::spawn(move ||exporter.run());
     }
 
-    let statm_reporter_running = match enable_rss {
-        // Read and report RSS
-        true => Some(nexmark::tools::statm_reporter()),
-        _ => None
-    };
-
-    // define a new computational scope, in which to run NEXMark queries
-    let timelines: Vec<_> = timely::execute_from_args(
-        timely_args.into_iter(),
-        move |worker, _node_state_handle| {
-            let peers = worker.peers();
-            let index = worker.index();
-
-            // Declare re-used input, control and probe handles.
-            let mut input = InputHandle::new();
-            //let mut control_input = InputHandle::new();
-            let mut probe = ProbeHandle::new();
-
-            {
-                //let control = std::rc::Rc::new(timely::dataflow::operators::capture::event::link::EventLink::new());
-
-                let bids = std::rc::Rc::new(
-                    timely::dataflow::operators::capture::event::link::EventLink::new(),
-                );
-                let auctions = std::rc::Rc::new(
-                    timely::dataflow::operators::capture::event::link::EventLink::new(),
-                );
-                let people = std::rc::Rc::new(
-                    timely::dataflow::operators::capture::event::link::EventLink::new(),
-                );
-
-                let closed_auctions = std::rc::Rc::new(
-                    timely::dataflow::operators::capture::event::link::EventLink::new(),
-                );
-                let closed_auctions_flex = std::rc::Rc::new(
-                    timely::dataflow::operators::capture::event::link::EventLink::new(),
-                );
-
-                let nexmark_input = NexmarkInput {
-                    //control: &control,
-                    bids: &bids,
-                    auctions: &auctions,
-                    people: &people,
-                    closed_auctions: &closed_auctions,
-                    closed_auctions_flex: &closed_auctions_flex,
-                };
-
-                let nexmark_timer = NexmarkTimer {
-                    time_dilation: TIME_DILATION,
-                };
-
-                worker.dataflow(
-                    |scope: &mut ::timely::dataflow::scopes::Child<_, usize, InMemoryBackend>,
-                     _| {
-                        use timely::dataflow::operators::generic::builder_rc::OperatorBuilder;
-                        let mut demux =
-                            OperatorBuilder::new("NEXMark demux".to_string(), scope.clone());
-
-                        let mut input = demux.new_input(&input.to_stream(scope), Pipeline);
-
-                        let (mut b_out, bids_stream) = demux.new_output();
-                        let (mut a_out, auctions_stream) = demux.new_output();
-                        let (mut p_out, people_stream) = demux.new_output();
-
-                        let mut demux_buffer = Vec::new();
-
-                        demux.build(move |_capability| {
-                            move |_frontiers| {
-                                let mut b_out = b_out.activate();
-                                let mut a_out = a_out.activate();
-                                let mut p_out = p_out.activate();
-
-                                input.for_each(|time, data| {
-                                    data.swap(&mut demux_buffer);
-                                    let mut b_session = b_out.session(&time);
-                                    let mut a_session = a_out.session(&time);
-                                    let mut p_session = p_out.session(&time);
-
-                                    for datum in demux_buffer.drain(..) {
-                                        match datum {
-                                            nexmark::event::Event::Bid(b) => b_session.give(b),
-                                            nexmark::event::Event::Auction(a) => a_session.give(a),
-                                            nexmark::event::Event::Person(p) => p_session.give(p),
-                                        }
-                                    }
-                                });
-                            }
-                        });
-
-                        bids_stream.capture_into(bids.clone());
-                        auctions_stream.capture_into(auctions.clone());
-                        people_stream.capture_into(people.clone());
-                    },
-                );
+    // Count the accesses to each state, sampled by the resource monitor
+    let enable_backend_stats = matches
+        .occurrences_of("backend-stats") > 0;
+    if enable_backend_stats {
+        nexmark::stats::enable();
+    }
 
-                // Q3: Join some auctions. FASTER.
-                if queries.iter().any(|x| *x == "q3_faster") {
-                    worker.dataflow::<_, _, _, FASTERBackend>(|scope, _| {
-                        ::nexmark::queries::nexmark::q3_managed(&nexmark_input, nexmark_timer, scope)
-                            .probe_with(&mut probe);
-                    });
-                }
+    // Time operators, their state calls and their notifications
+    let profile_operators = matches
+        .occurrences_of("profile-operators") > 0;
 
-                // Q3: Join some auctions. RocksDB.
-                if queries.iter().any(|x| *x == "q3_rocksdb") {
-                    worker.dataflow::<_, _, _, RocksDBBackend>(|scope, _| {
-                        ::nexmark::queries::nexmark::q3_managed(&nexmark_input, nexmark_timer, scope)
-                            .probe_with(&mut probe);
-                    });
-                }
+    let resource_monitor = match enable_rss || results_output.is_some() || enable_backend_stats {
+        // Sample resources and report RSS
+        true => Some(nexmark::tools::ResourceMonitor::start(enable_rss, state_dirs)),
+        _ => None
+    };
 
-                // Q4: Find average selling price per category
-                // Uses FASTER for the common part and an in-memory hash map for the final aggregation
-                if queries.iter().any(|x| *x == "q4_flex") {
-                    worker.dataflow::<_, _, _, FASTERBackend>(|scope, _| {
-                        ::nexmark::queries::nexmark::q4_q6_common_managed(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                        )
-                            .capture_into(nexmark_input.closed_auctions.clone());
-                        ::nexmark::queries::nexmark::q4(&nexmark_input, nexmark_timer, scope)
-                            .probe_with(&mut probe);
-                    });
+    // Number of workers of all processes, as reported by the workers
+    let workers = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+    // Runs `queries` once, on input generated with the settings of `recording`, and returns the
+    // timeline of each query on each local worker
+    let run = |queries: Vec<(&'static str, Backend)>, recording: nexmark::input::Recording| {
+        let trace_dir = trace_dir.clone();
+        let record_dir = record_dir.clone();
+        let replay_dir = replay_dir.clone();
+        let input_events = input_events.clone();
+        let query_params = query_params.clone();
+        let workers = workers.clone();
+        // define a new computational scope, in which to run NEXMark queries
+        timely::execute_from_args(
+            timely_args.clone().into_iter(),
+            move |worker, _node_state_handle| {
+                let peers = worker.peers();
+                let index = worker.index();
+                workers.store(peers, std::sync::atomic::Ordering::SeqCst);
+
+                if let Some(ref dir) = trace_dir {
+                    nexmark::trace::init(std::path::Path::new(dir), index);
                 }
-
-                // Q4: Find average selling price per category. FASTER.
-                if queries.iter().any(|x| *x == "q4_faster") {
-                    worker.dataflow::<_, _, _, FASTERBackend>(|scope, _| {
-                        ::nexmark::queries::nexmark::q4_q6_common_managed(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                        )
-                            .capture_into(nexmark_input.closed_auctions.clone());
-                        ::nexmark::queries::nexmark::q4_managed(&nexmark_input, nexmark_timer, scope)
-                            .probe_with(&mut probe);
-                    });
+                if profile_operators {
+                    nexmark::profile::init(worker);
                 }
 
-                // Q4: Find average selling price per category. RocksDB.
-                if queries.iter().any(|x| *x == "q4_rocksdb") {
-                    worker.dataflow::<_, _, _, RocksDBBackend>(|scope, _| {
-                        ::nexmark::queries::nexmark::q4_q6_common_managed(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                        )
-                            .capture_into(nexmark_input.closed_auctions.clone());
-                        ::nexmark::queries::nexmark::q4_managed(&nexmark_input, nexmark_timer, scope)
-                            //.inspect_batch(|t,xs| println!("@{}: {:?}", t, xs))
-                            .probe_with(&mut probe);
-                    });
-                }
-
-                // Q5. Hot Items. FASTER.
-                if queries.iter().any(|x| *x == "q5_faster") {
-                    // 60s windows, ticking in 1s intervals
-                    // NEXMark default is 60 minutes, ticking in one minute intervals
-                    let w_slice_count = 60;
-                    let w_slide_ns = 1_000_000_000;
-                    worker.dataflow::<_, _, _, FASTERBackend>(|scope, _| {
-                        ::nexmark::queries::nexmark::q5_managed(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            w_slice_count,
-                            w_slide_ns,
-                        )
-                        .probe_with(&mut probe);
-                    });
-                }
-
-                // Q5. Hot Items. FASTER.
-                if queries.iter().any(|x| *x == "q5_faster_index") {
-                    // 60s windows, ticking in 1s intervals
-                    // NEXMark default is 60 minutes, ticking in one minute intervals
-                    let w_slice_count = 60;
-                    let w_slide_ns = 1_000_000_000;
-                    worker.dataflow::<_, _, _, FASTERBackend>(|scope, _| {
-                        ::nexmark::queries::nexmark::q5_managed_index(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            w_slice_count,
-                            w_slide_ns,
-                        )
-                        .probe_with(&mut probe);
-                    });
-                }
+                let mut recorder = record_dir.as_ref().map(|dir| {
+                    let dir = std::path::Path::new(dir);
+                    if index == 0 {
+                        nexmark::input::Recording { peers: peers, ..recording.clone() }
+                            .write(dir)
+                            .expect("Cannot write input recording settings");
+                    }
+                    nexmark::input::EventWriter::create(&nexmark::input::input_file(dir, index))
+                });
+                // Events to send instead of generated ones, from an input file or a recording
+                let mut replayer = match (input_events.as_ref(), replay_dir.as_ref()) {
+                    (Some(events), _) => {
+                        let events = events.clone();
+                        Some(nexmark::input::TimedEvents::new(
+                            (index..events.len()).step_by(peers).map(move |i| events[i].clone()),
+                        ))
+                    }
+                    (None, Some(dir)) => {
+                        assert_eq!(
+                            recording.peers, peers,
+                            "the recording has {} workers, not {}",
+                            recording.peers, peers
+                        );
+                        Some(nexmark::input::TimedEvents::replay(
+                            &nexmark::input::input_file(std::path::Path::new(dir), index),
+                            replay_speed,
+                        ))
+                    }
+                    (None, None) => None,
+                };
 
-                // Q5. Hot Items. RocksDB.
-                if queries.iter().any(|x| *x == "q5_rocksdb") {
-                    // 60s windows, ticking in 1s intervals
-                    // NEXMark default is 60 minutes, ticking in one minute intervals
-                    let w_slice_count = 60;
-                    let w_slide_ns = 1_000_000_000;
-                    worker.dataflow::<_, _, _, RocksDBBackend>(|scope, _| {
-                        ::nexmark::queries::nexmark::q5_managed(
+                // Declare re-used input, control and probe handles.
+                let mut input = InputHandle::new();
+                //let mut control_input = InputHandle::new();
+                let mut probes: Vec<ProbeHandle<usize>> =
+                    queries.iter().map(|_| ProbeHandle::new()).collect();
+
+                {
+                    //let control = std::rc::Rc::new(timely::dataflow::operators::capture::event::link::EventLink::new());
+
+                    let bids = std::rc::Rc::new(
+                        timely::dataflow::operators::capture::event::link::EventLink::new(),
+                    );
+                    let auctions = std::rc::Rc::new(
+                        timely::dataflow::operators::capture::event::link::EventLink::new(),
+                    );
+                    let people = std::rc::Rc::new(
+                        timely::dataflow::operators::capture::event::link::EventLink::new(),
+                    );
+
+                    let closed_auctions = std::rc::Rc::new(
+                        timely::dataflow::operators::capture::event::link::EventLink::new(),
+                    );
+                    let closed_auctions_flex = std::rc::Rc::new(
+                        timely::dataflow::operators::capture::event::link::EventLink::new(),
+                    );
+
+                    let nexmark_input = NexmarkInput {
+                        //control: &control,
+                        bids: &bids,
+                        auctions: &auctions,
+                        people: &people,
+                        closed_auctions: &closed_auctions,
+                        closed_auctions_flex: &closed_auctions_flex,
+                    };
+
+                    let nexmark_timer = NexmarkTimer {
+                        time_dilation: TIME_DILATION,
+                    };
+
+                    worker.dataflow(
+                        |scope: &mut ::timely::dataflow::scopes::Child<_, usize, InMemoryBackend>,
+                         _| {
+                            nexmark::queries::demux(&input.to_stream(scope), &nexmark_input);
+                        },
+                    );
+
+                    // Each query gets its own probe, to measure its latency separately
+                    for ((query, backend), probe) in queries.iter().zip(probes.iter_mut()) {
+                        registry::build_dataflow(
+                            query,
+                            *backend,
+                            worker,
                             &nexmark_input,
                             nexmark_timer,
-                            scope,
-                            w_slice_count,
-                            w_slide_ns,
-                        )
-                            .probe_with(&mut probe);
-                    });
+                            &query_params,
+                            probe,
+                        );
+                    }
                 }
 
-                // Q5. Hot Items. RocksDB.
-                if queries.iter().any(|x| *x == "q5_rocksdb_index") {
-                    // 60s windows, ticking in 1s intervals
-                    // NEXMark default is 60 minutes, ticking in one minute intervals
-                    let w_slice_count = 60;
-                    let w_slide_ns = 1_000_000_000;
-                    worker.dataflow::<_, _, _, RocksDBBackend>(|scope, _| {
-                        ::nexmark::queries::nexmark::q5_managed_index(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            w_slice_count,
-                            w_slide_ns,
-                        )
-                            .probe_with(&mut probe);
-                    });
+                let mut config1 = nexmark::config::Config::new();
+                for (key, value) in recording.settings.iter() {
+                    config1.insert(key, value.clone());
                 }
+                config1.insert("first-event-number", format!("{}", index));
+                let mut config = nexmark::config::NEXMarkConfig::new(&config1);
 
-                // Q6. Avg selling price per seller. FASTER.
-                if queries.iter().any(|x| *x == "q6_faster") {
-                    worker.dataflow::<_, _, _, FASTERBackend>(|scope, _| {
-                        ::nexmark::queries::nexmark::q4_q6_common_managed(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                        )
-                            .capture_into(nexmark_input.closed_auctions.clone());
-                        ::nexmark::queries::nexmark::q6_managed(&nexmark_input, nexmark_timer, scope)
-                            .probe_with(&mut probe);
-                    });
+                let count = 1;
+                input.advance_to(count);
+                while probes.iter().any(|probe| probe.less_than(&count)) {
+                    worker.step();
                 }
 
-                // Q6. Avg selling price per seller. RocksDB.
-                if queries.iter().any(|x| *x == "q6_rocksdb") {
-                    worker.dataflow::<_, _, _, RocksDBBackend>(|scope, _| {
-                        ::nexmark::queries::nexmark::q4_q6_common_managed(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                        )
-                            .capture_into(nexmark_input.closed_auctions.clone());
-                        ::nexmark::queries::nexmark::q6_managed(&nexmark_input, nexmark_timer, scope)
-                            .probe_with(&mut probe);
-                    });
-                }
+                let timer = ::std::time::Instant::now();
+                nexmark::profile::start_clock();
 
-                // Q7. Highest Bid. FASTER.
-                if queries.iter().any(|x| *x == "q7_faster") {
-                    worker.dataflow::<_, _, _, FASTERBackend>(|scope, _| {
-                        // Window ticks every 10 seconds.
-                        // NEXMark default is different: ticks every 60s
-                        let window_size_ns = 10_000_000_000;
-                        ::nexmark::queries::nexmark::q7_managed(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            window_size_ns,
-                        )
-                        .probe_with(&mut probe);
-                    });
-                }
+                // Establish a start of the computation.
+                let elapsed_ns = timer.elapsed().to_nanos();
+                config.base_time_ns = elapsed_ns as usize;
+
+                use rand::rngs::SmallRng;
+                use rand::SeedableRng;
+                assert!(worker.peers() < 256);
+                let mut rng = SmallRng::from_seed([worker.peers() as u8; 16]);
+
+                let input_times = {
+                    let config = config.clone();
+                    let input_events = input_events.clone();
+                    move || {
+                        let times: Box<dyn Iterator<Item = u64>> = match input_events {
+                            // The times of the events this worker sends from the input file
+                            Some(ref events) => Box::new(
+                                events
+                                    .iter()
+                                    .skip(index)
+                                    .step_by(peers)
+                                    .map(|(t, _)| *t)
+                                    .collect::<Vec<_>>()
+                                    .into_iter(),
+                            ),
+                            None => Box::new(nexmark::config::NexMarkInputTimes::new(
+                                config.clone(),
+                                duration_ns,
+                                TIME_DILATION,
+                                peers,
+                            )),
+                        };
+                        nexmark::input::ScaledInputTimes::new(times, replay_speed)
+                    }
+                };
 
-                // Q7. Highest Bid. RocksDB.
-                if queries.iter().any(|x| *x == "q7_rocksdb") {
-                    worker.dataflow::<_, _, _, RocksDBBackend>(|scope, _| {
-                        // Window ticks every 10 seconds.
-                        // NEXMark default is different: ticks every 60s
-                        let window_size_ns = 10_000_000_000;
-                        ::nexmark::queries::nexmark::q7_managed(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            window_size_ns,
+                let mut output_metric_collectors: Vec<_> = queries
+                    .iter()
+                    .map(|_| {
+                        ::streaming_harness::output::default::hdrhist_timeline_collector(
+                            input_times(),
+                            0,
+                            2_000_000_000,
+                            run_ns - 2_000_000_000,
+                            run_ns,
+                            250_000_000,
                         )
-                            .probe_with(&mut probe);
-                    });
-                }
+                    })
+                    .collect();
+
+                let mut events_so_far = 0;
+
+                let mut input_times_gen =
+                    ::streaming_harness::input::SyntheticInputTimeGenerator::new(input_times());
+
+                let mut input = Some(input);
+
+                // Late events, ordered by the time they are delivered at
+                let mut late_events = BinaryHeap::new();
+                // The input epoch trails the generation time by the maximum lateness, so that
+                // late events are delivered at an epoch that is not after their event time. Events
+                // of an input file are late by at most their disorder in the file.
+                let max_lateness_ns =
+                    ::std::cmp::max(config.max_lateness_ns, input_disorder_ns as usize);
+                let max_lateness_ns = (max_lateness_ns as f64 / replay_speed) as usize;
+
+                let mut last_ns = 0;
+
+                loop {
+                    let elapsed_ns = timer.elapsed().to_nanos();
+                    let wait_ns = last_ns;
+                    let target_ns = (elapsed_ns + 1) / 1_000_000 * 1_000_000;
+                    last_ns = target_ns;
+
+                    for (output_metric_collector, probe) in
+                        output_metric_collectors.iter_mut().zip(probes.iter())
+                    {
+                        output_metric_collector
+                            .acknowledge_while(elapsed_ns, |t| !probe.less_than(&(t as usize + count)));
+                    }
 
-                // Q8. Monitor new users. FASTER.
-                if queries.iter().any(|x| *x == "q8_faster") {
-                    worker.dataflow::<_, _, _, FASTERBackend>(|scope, _| {
-                        let window_size_ns = 12 * 60 * 60 * 1_000_000_000;
-                        ::nexmark::queries::nexmark::q8_managed(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            window_size_ns,
-                        )
-                        .probe_with(&mut probe);
-                    });
-                }
+                    if input.is_none() {
+                        break;
+                    }
 
-                // Q8. Monitor new users. RocksDB.
-                if queries.iter().any(|x| *x == "q8_rocksdb") {
-                    worker.dataflow::<_, _, _, RocksDBBackend>(|scope, _| {
-                        let window_size_ns = 12 * 60 * 60 * 1_000_000_000;
-                        //let window_size_ns = 4 * 1_000_000_000;
-                        ::nexmark::queries::nexmark::q8_managed(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            window_size_ns,
-                        )
-                            .probe_with(&mut probe);
-                    });
-                }
+                    if let Some(it) = input_times_gen.iter_until(target_ns) {
+                        let input = input.as_mut().unwrap();
+                        if let Some(ref mut replayer) = replayer {
+                            for _t in it {}
+                            while let Some(event) = replayer.next_until(target_ns) {
+                                input.send(event);
+                            }
+                        } else {
+                            for t in it {
+                                let event = Event::create(events_so_far, &mut rng, &mut config);
+                                match config.lateness_ns(&mut rng) {
+                                    Some(lateness_ns) => {
+                                        late_events.push(Reverse((*event.time() + lateness_ns, event)))
+                                    }
+                                    None => {
+                                        if let Some(ref mut recorder) = recorder {
+                                            recorder.write(t, &event);
+                                        }
+                                        input.send(event)
+                                    }
+                                }
+                                events_so_far += worker.peers();
+                            }
+                        }
+                        while late_events
+                            .peek()
+                            .map_or(false, |Reverse((due_ns, _))| *due_ns <= target_ns as usize)
+                        {
+                            let Reverse((due_ns, event)) = late_events.pop().unwrap();
+                            if let Some(ref mut recorder) = recorder {
+                                recorder.write(due_ns as u64, &event);
+                            }
+                            input.send(event);
+                        }
+                        input.advance_to((target_ns as usize).saturating_sub(max_lateness_ns) + count);
+                    } else {
+                        // Deliver the remaining late and replayed events before closing the input
+                        for Reverse((due_ns, event)) in late_events.drain() {
+                            if let Some(ref mut recorder) = recorder {
+                                recorder.write(due_ns as u64, &event);
+                            }
+                            input.as_mut().unwrap().send(event);
+                        }
+                        for event in replayer.iter_mut().flatten() {
+                            input.as_mut().unwrap().send(event);
+                        }
+                        input.take().unwrap();
+                    }
 
-                // Q8. Monitor new users. FASTER.
-                if queries.iter().any(|x| *x == "q8_faster_map") {
-                    worker.dataflow::<_, _, _, FASTERBackend>(|scope, _| {
-                        let window_size_ns = 12 * 60 * 60 * 1_000_000_000;
-                        ::nexmark::queries::nexmark::q8_managed_map(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            window_size_ns,
-                        )
-                        .probe_with(&mut probe);
-                    });
+                    if input.is_some() {
+                        let wait_epoch = (wait_ns as usize).saturating_sub(max_lateness_ns) + count;
+                        while probes.iter().any(|probe| probe.less_than(&wait_epoch)) {
+                            worker.step();
+                        }
+                    } else {
+                        while worker.step() {}
+                    }
                 }
 
-                // Q8. Monitor new users. RocksDB.
-                if queries.iter().any(|x| *x == "q8_rocksdb_map") {
-                    worker.dataflow::<_, _, _, RocksDBBackend>(|scope, _| {
-                        let window_size_ns = 12 * 60 * 60 * 1_000_000_000;
-                        //let window_size_ns = 4 * 1_000_000_000;
-                        ::nexmark::queries::nexmark::q8_managed_map(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            window_size_ns,
-                        )
-                            .probe_with(&mut probe);
-                    });
+                nexmark::trace::finish();
+                nexmark::profile::finish();
+                if let Some(recorder) = recorder.take() {
+                    recorder.finish();
                 }
 
-                // 2nd window implementation with FASTER and COUNT aggregation
-                if queries.iter().any(|x| *x == "window_2_faster_count") {
-                    assert!(window_slice_count > 0);
-                    assert!(window_slide_ns > 0);
-                    worker.dataflow::<_, _, _, FASTERBackend>(|scope, _| {
-                        ::nexmark::queries::windows::global::window_2_faster_count(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            window_slice_count,
-                            window_slide_ns,
-                        )
-                            .probe_with(&mut probe);
-                    });
-                }
+                output_metric_collectors
+                    .into_iter()
+                    .map(|output_metric_collector| output_metric_collector.into_inner())
+                    .collect::<Vec<_>>()
+            },
+        )
+        .expect("unsuccessful execution")
+        .join()
+        .into_iter()
+        .map(|x| x.unwrap())
+        .collect::<Vec<_>>()
+    };
 
-                // 2nd window implementation with FASTER and RANK aggregation
-                if queries.iter().any(|x| *x == "window_2_faster_rank") {
-                    assert!(window_slice_count > 0);
-                    assert!(window_slide_ns > 0);
-                    worker.dataflow::<_, _, _, FASTERBackend>(|scope, _| {
-                        ::nexmark::queries::windows::global::window_2_faster_rank(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            window_slice_count,
-                            window_slide_ns,
-                        )
-                            .probe_with(&mut probe);
-                    });
-                }
+    // The metadata of the results file, for a run started at `start_time_ms` that ends now
+    let metadata = |local_workers: usize,
+                    generator_settings: Vec<(String, String)>,
+                    start_time_ms: u64| {
+        let mut backends: Vec<Backend> = Vec::new();
+        for (_, backend) in queries.iter() {
+            if !backends.contains(backend) {
+                backends.push(*backend);
+            }
+        }
+        nexmark::results::Metadata {
+            rate: rate,
+            next_rate: next_rate,
+            duration_ns: duration_ns,
+            workers: workers.load(std::sync::atomic::Ordering::SeqCst),
+            local_workers: local_workers,
+            params: query_params.clone(),
+            generator_settings: generator_settings.into_iter().collect(),
+            backend_config: nexmark::results::backend_config(&backends),
+            git_revision: nexmark::results::git_revision(),
+            command_line: std::env::args().collect(),
+            start_time_ms: start_time_ms,
+            end_time_ms: nexmark::results::unix_time_ms(),
+        }
+    };
 
-                // 2nd window implementation with RocksDB using put + get and COUNT
-                if queries.iter().any(|x| *x == "window_2a_rocksdb_count") {
-                    assert!(window_slice_count > 0);
-                    assert!(window_slide_ns > 0);
-                    worker.dataflow::<_, _, _, RocksDBBackend>(|scope, _| {
-                        ::nexmark::queries::windows::global::window_2a_rocksdb_count(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            window_slice_count,
-                            window_slide_ns,
-                        )
-                            .probe_with(&mut probe);
-                    });
-                }
+    if let Some(latency_bound_ns) = latency_bound_ns {
+        let start_time_ms = nexmark::results::unix_time_ms();
+        let mut local_workers = 0;
+        let mut searches = Vec::new();
+        // Search the highest rate each query sustains on its backend
+        for (query, backend) in queries.iter() {
+            let mut trials = Vec::new();
+            let max_rate = nexmark::tools::search_max_rate(rate, search_steps, |rate| {
+                let recording = nexmark::input::Recording {
+                    settings: generator_settings(rate, rate),
+                    duration_ns: duration_ns,
+                    peers: 0,
+                };
+                let timelines = run(vec![(*query, *backend)], recording);
+                local_workers = timelines.len();
+                let ::streaming_harness::timeline::Timeline {
+                    timeline,
+                    latency_metrics,
+                    ..
+                } = ::streaming_harness::output::combine_all(
+                    timelines
+                        .into_iter()
+                        .map(|mut timelines| timelines.remove(0))
+                        .collect(),
+                );
+                let p99 = nexmark::tools::ccdf_quantile(latency_metrics.into_inner().ccdf(), 0.99)
+                    .unwrap_or(0);
+                // The probe keeps up if latencies do not grow beyond the bound over the run
+                let max_interval_p99 = timeline
+                    .into_iter()
+                    .filter_map(|element| nexmark::tools::ccdf_quantile(element.metrics.ccdf(), 0.99))
+                    .max()
+                    .unwrap_or(0);
+                let sustainable = p99 <= latency_bound_ns && max_interval_p99 <= latency_bound_ns;
+                println!(
+                    "search_trial\t{}\t{}\t{}\t{}\t{}\t{}",
+                    query,
+                    backend.name(),
+                    rate,
+                    p99,
+                    max_interval_p99,
+                    sustainable
+                );
+                trials.push(nexmark::results::SearchTrial {
+                    rate: rate,
+                    p99: p99,
+                    max_interval_p99: max_interval_p99,
+                    sustainable: sustainable,
+                });
+                sustainable
+            });
+            println!("max_sustainable_rate\t{}\t{}\t{}", query, backend.name(), max_rate);
+            searches.push(nexmark::results::SearchResults {
+                name: query.to_string(),
+                backend: backend.name().to_string(),
+                latency_bound_ns: latency_bound_ns,
+                trials: trials,
+                max_sustainable_rate: max_rate,
+            });
+        }
+        let resources = match resource_monitor {
+            Some(resource_monitor) => resource_monitor.stop(),
+            _ => Vec::new(),
+        };
+        if let Some(output_file) = results_output {
+            let results = nexmark::results::Results {
+                metadata: metadata(local_workers, generator_settings(rate, rate), start_time_ms),
+                queries: Vec::new(),
+                resources: resources,
+                profile: Vec::new(),
+                search: searches,
+            };
+            results
+                .write(std::path::Path::new(output_file))
+                .expect("Cannot write results output file");
+        }
+        return;
+    }
 
-                // 2nd window implementation with RocksDB using put + get and RANK
-                if queries.iter().any(|x| *x == "window_2a_rocksdb_rank") {
-                    assert!(window_slice_count > 0);
-                    assert!(window_slide_ns > 0);
-                    worker.dataflow::<_, _, _, RocksDBBackend>(|scope, _| {
-                        ::nexmark::queries::windows::global::window_2a_rocksdb_rank(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            window_slice_count,
-                            window_slide_ns,
-                        )
-                            .probe_with(&mut probe);
-                    });
-                }
+    let start_time_ms = nexmark::results::unix_time_ms();
+    let generator_settings = recording.settings.clone();
+    let timelines = run(queries.clone(), recording);
+    let local_workers = timelines.len();
 
-                // 2nd window implementation with RocksDB using merge and COUNT
-                if queries.iter().any(|x| *x == "window_2b_rocksdb_count") {
-                    assert!(window_slice_count > 0);
-                    assert!(window_slide_ns > 0);
-                    worker.dataflow::<_, _, _, RocksDBMergeBackend2>(|scope, _| {
-                        ::nexmark::queries::windows::global::window_2b_rocksdb_count(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            window_slice_count,
-                            window_slide_ns,
-                        )
-                            .probe_with(&mut probe);
-                    });
-                }
+    let resources = match resource_monitor {
+        Some(resource_monitor) => resource_monitor.stop(),
+        _ => Vec::new(),
+    };
 
-                // 2nd window implementation with RocksDB using merge and RANK
-                if queries.iter().any(|x| *x == "window_2b_rocksdb_rank") {
-                    assert!(window_slice_count > 0);
-                    assert!(window_slide_ns > 0);
-                    worker.dataflow::<_, _, _, RocksDBMergeBackend>(|scope, _| {
-                        ::nexmark::queries::windows::global::window_2b_rocksdb_rank(
-                            &nexmark_input,
-                            nexmark_timer,
-                            scope,
-                            window_slice_count,
-                            window_slide_ns,
-                        )
-                            .probe_with(&mut probe);
-                    });
+    if enable_backend_stats {
+        if let Some(last) = resources.last() {
+            for state in last.states.iter() {
+                let name = format!("{}/{}\t{}", state.dataflow, state.operator, state.name);
+                for (op, count) in state.ops.iter() {
+                    println!("state_stats\t{}\tops_{}\t{}", name, op, count);
                 }
+                println!("state_stats\t{}\thits\t{}", name, state.hits);
+                println!("state_stats\t{}\tmisses\t{}", name, state.misses);
+                println!("state_stats\t{}\tbytes_read\t{}", name, state.bytes_read);
+                println!("state_stats\t{}\tbytes_written\t{}", name, state.bytes_written);
             }
-
-            let mut config1 = nexmark::config::Config::new();
-            // 0.06*60*60*12 = 0.06*60*60*12
-            // auction_proportion*sec_in_12h
-            config1.insert("in-flight-auctions", format!("{}", rate * 2592));
-            config1.insert("events-per-second", format!("{}", rate));
-            config1.insert("first-event-number", format!("{}", index));
-            let mut config = nexmark::config::NEXMarkConfig::new(&config1);
-
-            let count = 1;
-            input.advance_to(count);
-            while probe.less_than(&count) {
-                worker.step();
-            }
-
-            let timer = ::std::time::Instant::now();
-
-            // Establish a start of the computation.
-            let elapsed_ns = timer.elapsed().to_nanos();
-            config.base_time_ns = elapsed_ns as usize;
-
-            use rand::rngs::SmallRng;
-            use rand::SeedableRng;
-            assert!(worker.peers() < 256);
-            let mut rng = SmallRng::from_seed([worker.peers() as u8; 16]);
-
-            let input_times = {
-                let config = config.clone();
-                move || {
-                    nexmark::config::NexMarkInputTimes::new(
-                        config.clone(),
-                        duration_ns,
-                        TIME_DILATION,
-                        peers,
-                    )
-                }
-            };
-
-            let mut output_metric_collector =
-                ::streaming_harness::output::default::hdrhist_timeline_collector(
-                    input_times(),
-                    0,
-                    2_000_000_000,
-                    duration_ns - 2_000_000_000,
-                    duration_ns,
-                    250_000_000,
+            for backend in last.backends.iter() {
+                // Backends are named by their dataflow, and by their directory if it is unknown
+                let name = format!(
+                    "{}\t{}",
+                    backend.dataflow.as_ref().map_or("-", |d| d.as_str()),
+                    backend.directory
                 );
-
-            let mut events_so_far = 0;
-
-            let mut input_times_gen =
-                ::streaming_harness::input::SyntheticInputTimeGenerator::new(input_times());
-
-            let mut input = Some(input);
-
-            let mut last_ns = 0;
-
-            loop {
-                let elapsed_ns = timer.elapsed().to_nanos();
-                let wait_ns = last_ns;
-                let target_ns = (elapsed_ns + 1) / 1_000_000 * 1_000_000;
-                last_ns = target_ns;
-
-                output_metric_collector
-                    .acknowledge_while(elapsed_ns, |t| !probe.less_than(&(t as usize + count)));
-
-                if input.is_none() {
-                    break;
+                println!("backend_stats\t{}\tstates\t{}", name, backend.states.join(","));
+                println!("backend_stats\t{}\tsize_bytes\t{}", name, backend.size_bytes);
+                if let Some(ref faster) = backend.faster {
+                    println!("backend_stats\t{}\tlog_size_bytes\t{}", name, faster.log_size_bytes);
+                    println!("backend_stats\t{}\tindex_size_bytes\t{}", name, faster.index_size_bytes);
                 }
-
-                if let Some(it) = input_times_gen.iter_until(target_ns) {
-                    let input = input.as_mut().unwrap();
-                    for _t in it {
-                        let event = Event::create(events_so_far, &mut rng, &mut config);
-                        //println!("Event timestamp: {:?}, epoch: {}", event.time(), target_ns as usize + count);
-                        input.send(event);
-                        events_so_far += worker.peers();
+                if let Some(ref rocksdb) = backend.rocksdb {
+                    if let Some(memtable_bytes) = rocksdb.memtable_bytes {
+                        println!("backend_stats\t{}\tmemtable_bytes\t{}", name, memtable_bytes);
                     }
-                    //println!("Epoch: {}", target_ns as usize + count);
-                    input.advance_to(target_ns as usize + count);
-                } else {
-                    input.take().unwrap();
-                }
-
-                if input.is_some() {
-                    while probe.less_than(&(wait_ns as usize + count)) {
-                        worker.step();
+                    if let Some(estimated_keys) = rocksdb.estimated_keys {
+                        println!("backend_stats\t{}\testimated_keys\t{}", name, estimated_keys);
+                    }
+                    println!("backend_stats\t{}\tingest_bytes\t{}", name, rocksdb.ingest_bytes);
+                    println!("backend_stats\t{}\tcompaction_write_bytes\t{}", name, rocksdb.compaction_write_bytes);
+                    println!("backend_stats\t{}\twrite_amplification\t{}", name, rocksdb.write_amplification);
+                    println!("backend_stats\t{}\tstall_seconds\t{}", name, rocksdb.stall_seconds);
+                    match rocksdb.block_cache_hit_rate {
+                        Some(hit_rate) => {
+                            println!("backend_stats\t{}\tblock_cache_hit_rate\t{}", name, hit_rate)
+                        }
+                        // RocksDB only counts block cache hits and misses if statistics are enabled
+                        None => eprintln!(
+                            "Warning: no block cache hit rate for {}, as its RocksDB instance does \
+                             not collect statistics",
+                            backend.directory
+                        ),
                     }
-                } else {
-                    while worker.step() {}
                 }
             }
+        }
+    }
 
-            output_metric_collector.into_inner()
-        },
-    )
-    .expect("unsuccessful execution")
-    .join()
-    .into_iter()
-    .map(|x| x.unwrap())
-    .collect();
-
-    match statm_reporter_running {
-        Some(statm_reporter_running) => statm_reporter_running.store(false, ::std::sync::atomic::Ordering::SeqCst),
-        _ => {}
+    let operator_profiles = nexmark::profile::collect();
+    for profile in operator_profiles.iter() {
+        let operator = format!("{}/{}", profile.dataflow, profile.operator);
+        let mut histograms = vec![
+            ("schedule".to_string(), &profile.schedule),
+            ("notification_delay".to_string(), &profile.notification_delay),
+        ];
+        for (state, ops) in profile.state.iter() {
+            for (op, histogram) in ops.iter() {
+                histograms.push((format!("state:{}:{}", state, op), histogram));
+            }
+        }
+        for (metric, histogram) in histograms {
+            if histogram.count == 0 {
+                continue;
+            }
+            println!(
+                "operator_profile\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                operator,
+                metric,
+                histogram.count,
+                histogram.total_ns,
+                histogram.quantile(0.5),
+                histogram.quantile(0.99),
+                histogram.max_ns
+            );
+            for (upper_bound, count) in histogram.buckets() {
+                println!(
+                    "operator_histogram\t{}\t{}\t{}\t{}",
+                    operator, metric, upper_bound, count
+                );
+            }
+        }
+        println!(
+            "operator_breakdown\t{}\t{}\t{}\t{}",
+            operator,
+            profile.schedule.total_ns,
+            profile.state_ns(),
+            profile.compute_ns()
+        );
     }
 
-    let ::streaming_harness::timeline::Timeline {
-        timeline,
-        latency_metrics,
-        ..
-    } = ::streaming_harness::output::combine_all(timelines);
-
-    let latency_metrics = latency_metrics.into_inner();
-    /*
-    println!(
-        "DEBUG_summary\t{}",
-        latency_metrics
-            .summary_string()
-            .replace("\n", "\nDEBUG_summary\t")
-    );
-    println!(
-        "{}",
-        timeline
-            .clone()
-            .into_iter()
-            .map(
-                |::streaming_harness::timeline::TimelineElement {
-                     time,
-                     metrics,
-                     samples,
-                 }| format!(
-                    "DEBUG_timeline\t-- {} ({} samples) --\nDEBUG_timeline\t{}",
-                    time,
-                    samples,
-                    metrics.summary_string().replace("\n", "\nDEBUG_timeline\t")
-                )
-            )
-            .collect::<Vec<_>>()
-            .join("\n")
-    );
-    */
+    // Combine the timelines of each query over the local workers
+    let mut query_timelines: Vec<Vec<_>> = queries.iter().map(|_| Vec::new()).collect();
+    for worker_timelines in timelines {
+        for (query_timeline, timeline) in query_timelines.iter_mut().zip(worker_timelines) {
+            query_timeline.push(timeline);
+        }
+    }
 
-    if let Some(output_file) = latency_output {
-        let mut f = File::create(output_file).expect("Cannot open latency output file");
-        for (value, prob, count) in latency_metrics.ccdf() {
-            f.write(format!("latency_ccdf\t{}\t{}\t{}\n",value, prob, count).as_bytes()).ok();
+    let mut latency_file = latency_output
+        .map(|output_file| File::create(output_file).expect("Cannot open latency output file"));
+    let mut timeline_file = timeline_output
+        .map(|output_file| File::create(output_file).expect("Cannot open timeline output file"));
+    let mut query_results = Vec::new();
+
+    for ((query, backend), timelines) in queries.iter().zip(query_timelines) {
+        let ::streaming_harness::timeline::Timeline {
+            timeline,
+            latency_metrics,
+            ..
+        } = ::streaming_harness::output::combine_all(timelines);
+
+        let latency_metrics = latency_metrics.into_inner();
+
+        // With several queries, output lines are suffixed with the query and its backend
+        let suffix = if queries.len() > 1 {
+            format!("_{}_{}", query, backend.name())
+        } else {
+            String::new()
+        };
+
+        if let Some(ref mut f) = latency_file {
+            for (value, prob, count) in latency_metrics.ccdf() {
+                f.write(format!("latency_ccdf{}\t{}\t{}\t{}\n", suffix, value, prob, count).as_bytes()).ok();
+            }
+        } else {
+            for (value, prob, count) in latency_metrics.ccdf() {
+                println!("latency_ccdf{}\t{}\t{}\t{}", suffix, value, prob, count);
+            }
         }
-    } else {
-        for (value, prob, count) in latency_metrics.ccdf() {
-            println!("latency_ccdf\t{}\t{}\t{}", value, prob, count);
+
+        if let Some(ref mut f) = timeline_file {
+            f.write(::streaming_harness::format::format_summary_timeline(
+                    format!("summary_timeline{}", suffix),
+                    timeline.clone()
+                ).as_bytes()).ok();
+        } else {
+            println!(
+                "{}",
+                ::streaming_harness::format::format_summary_timeline(
+                    format!("summary_timeline{}", suffix),
+                    timeline.clone()
+                )
+            );
         }
+
+        query_results.push(nexmark::results::QueryResults {
+            name: query.to_string(),
+            backend: backend.name().to_string(),
+            latency_ccdf: nexmark::results::ccdf_points(&latency_metrics),
+            timeline: timeline
+                .iter()
+                .map(|element| {
+                    nexmark::results::TimelinePoint::new(
+                        element.time as u64,
+                        element.samples as u64,
+                        &element.metrics,
+                    )
+                })
+                .collect(),
+        });
     }
 
-    if let Some(output_file) = timeline_output {
-        let mut f = File::create(output_file).expect("Cannot open timeline output file");
-        f.write(::streaming_harness::format::format_summary_timeline(
-                "summary_timeline".to_string(),
-                timeline.clone()
-            ).as_bytes()).ok();
-    } else {
-        println!(
-            "{}",
-            ::streaming_harness::format::format_summary_timeline(
-                "summary_timeline".to_string(),
-                timeline.clone()
-            )
-        );
+    if let Some(output_file) = results_output {
+        let results = nexmark::results::Results {
+            metadata: metadata(local_workers, generator_settings, start_time_ms),
+            queries: query_results,
+            resources: resources,
+            profile: operator_profiles,
+            search: Vec::new(),
+        };
+        results
+            .write(std::path::Path::new(output_file))
+            .expect("Cannot write results output file");
     }
 }
@@ -0,0 +1,328 @@
+//! Opt-in profiling of where operators spend their time.
+//!
+//! When enabled on a worker with [`init`], before its dataflows are built, the worker records per
+//! operator, by its name and the dataflow it was built in:
+//!
+//! * `schedule`: the duration of every time the operator is scheduled, from timely's logging,
+//! * `state`: the duration of every call to managed state, by state and operation, from the
+//!   wrappers in [`crate::trace`],
+//! * `notification_delay`: how long after its time every notification is delivered, measured
+//!   against the clock of the run set with [`start_clock`].
+//!
+//! Compute time is the schedule time not spent in state calls. Dataflows are named with
+//! [`name_dataflow`] while they are built, and operators of dataflows without a name have an
+//! empty dataflow. The histograms of all workers are merged with [`finish`] and returned by
+//! [`collect`].
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde::Serializer;
+use streaming_harness::util::ToNanos;
+use timely::communication::Allocate;
+use timely::logging::{StartStop, TimelyEvent};
+use timely::worker::Worker;
+
+use crate::trace::{Op, StateOwner};
+
+// Buckets of a histogram: bucket `i` counts values below `2^i` nanoseconds.
+const BUCKETS: usize = 64;
+
+/// A histogram of durations in nanoseconds, with buckets of powers of two.
+#[derive(Clone, Debug, Serialize)]
+pub struct Histogram {
+    pub count: u64,
+    pub total_ns: u64,
+    pub max_ns: u64,
+    /// Non-empty buckets as `(upper_bound_ns, count)`
+    #[serde(rename = "buckets", serialize_with = "serialize_buckets")]
+    counts: Vec<u64>,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            count: 0,
+            total_ns: 0,
+            max_ns: 0,
+            counts: vec![0; BUCKETS],
+        }
+    }
+}
+
+fn serialize_buckets<S: Serializer>(counts: &[u64], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(nonempty_buckets(counts))
+}
+
+fn nonempty_buckets<'a>(counts: &'a [u64]) -> impl Iterator<Item = (u64, u64)> + 'a {
+    counts
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(bucket, count)| (1u64.checked_shl(bucket as u32).unwrap_or(u64::max_value()), *count))
+}
+
+impl Histogram {
+    #[inline]
+    pub fn add(&mut self, ns: u64) {
+        let bucket = (64 - ns.leading_zeros() as usize).min(BUCKETS - 1);
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.total_ns += ns;
+        self.max_ns = self.max_ns.max(ns);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+        self.count += other.count;
+        self.total_ns += other.total_ns;
+        self.max_ns = self.max_ns.max(other.max_ns);
+    }
+
+    /// Non-empty buckets as `(upper_bound_ns, count)`.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        nonempty_buckets(&self.counts)
+    }
+
+    /// The upper bound of the bucket that holds the `q`-quantile.
+    pub fn quantile(&self, q: f64) -> u64 {
+        let rank = (q * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (upper_bound, count) in self.buckets() {
+            seen += count;
+            if seen >= rank {
+                return upper_bound.min(self.max_ns);
+            }
+        }
+        self.max_ns
+    }
+}
+
+/// The time an operator spent scheduled, in state calls and waiting for notifications.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct OperatorProfile {
+    /// The dataflow the operator was built in, which is the query that built it
+    pub dataflow: String,
+    pub operator: String,
+    pub schedule: Histogram,
+    /// Calls to managed state, by state name and operation
+    pub state: BTreeMap<String, BTreeMap<String, Histogram>>,
+    pub notification_delay: Histogram,
+}
+
+impl OperatorProfile {
+    pub fn state_ns(&self) -> u64 {
+        self.state
+            .values()
+            .flat_map(|ops| ops.values())
+            .map(|histogram| histogram.total_ns)
+            .sum()
+    }
+
+    /// Schedule time not spent in state calls.
+    pub fn compute_ns(&self) -> u64 {
+        self.schedule.total_ns.saturating_sub(self.state_ns())
+    }
+
+    fn merge(&mut self, other: &OperatorProfile) {
+        self.schedule.merge(&other.schedule);
+        for (state, ops) in other.state.iter() {
+            let state = self.state.entry(state.clone()).or_insert_with(BTreeMap::new);
+            for (op, histogram) in ops.iter() {
+                state.entry(op.clone()).or_insert_with(Default::default).merge(histogram);
+            }
+        }
+        self.notification_delay.merge(&other.notification_delay);
+    }
+}
+
+/// Durations of the calls of a worker to a state, by operation.
+pub struct StateTimes {
+    dataflow: String,
+    operator: String,
+    name: String,
+    ops: RefCell<Vec<Histogram>>,
+}
+
+impl StateTimes {
+    #[inline]
+    pub fn add(&self, op: Op, start: Instant) {
+        self.ops.borrow_mut()[op as usize].add(start.elapsed().to_nanos());
+    }
+}
+
+// Histograms of operators are keyed by the dataflow and the name of the operator
+struct Profiler {
+    start: Instant,
+    dataflows: HashMap<usize, String>,
+    schedules: HashMap<(String, String), Histogram>,
+    notification_delays: HashMap<(String, String), Histogram>,
+    states: HashMap<(String, String, String), Rc<StateTimes>>,
+}
+
+thread_local! {
+    static PROFILER: RefCell<Option<Profiler>> = RefCell::new(None);
+}
+
+static PROFILES: Mutex<BTreeMap<(String, String), OperatorProfile>> = Mutex::new(BTreeMap::new());
+
+/// Enables profiling of the calling worker.
+pub fn init<A: Allocate>(worker: &mut Worker<A>) {
+    PROFILER.with(|p| {
+        *p.borrow_mut() = Some(Profiler {
+            start: Instant::now(),
+            dataflows: HashMap::new(),
+            schedules: HashMap::new(),
+            notification_delays: HashMap::new(),
+            states: HashMap::new(),
+        })
+    });
+    // Operator names and the index of their dataflow by id, and the start of the current
+    // scheduling of each operator
+    let mut names = HashMap::new();
+    let mut starts = HashMap::new();
+    worker
+        .log_register()
+        .insert::<TimelyEvent, _>("timely", move |_time, data| {
+            for (ts, _worker, event) in data.drain(..) {
+                match event {
+                    TimelyEvent::Operates(operates) => {
+                        names.insert(operates.id, (operates.addr[0], operates.name));
+                    }
+                    TimelyEvent::Schedule(schedule) => match schedule.start_stop {
+                        StartStop::Start => {
+                            starts.insert(schedule.id, ts);
+                        }
+                        StartStop::Stop => {
+                            if let (Some(start), Some((dataflow, name))) =
+                                (starts.remove(&schedule.id), names.get(&schedule.id))
+                            {
+                                let ns = (ts - start).to_nanos();
+                                PROFILER.with(|p| {
+                                    if let Some(profiler) = p.borrow_mut().as_mut() {
+                                        let dataflow = profiler
+                                            .dataflows
+                                            .get(dataflow)
+                                            .cloned()
+                                            .unwrap_or_default();
+                                        profiler
+                                            .schedules
+                                            .entry((dataflow, name.clone()))
+                                            .or_insert_with(Default::default)
+                                            .add(ns);
+                                    }
+                                });
+                            }
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        });
+}
+
+/// Sets the start of the clock that timestamps are measured against, to the current time.
+pub fn start_clock() {
+    PROFILER.with(|p| {
+        if let Some(profiler) = p.borrow_mut().as_mut() {
+            profiler.start = Instant::now();
+        }
+    });
+}
+
+/// Names the dataflow with index `index` on the calling worker, for the operators built in it.
+pub fn name_dataflow(index: usize, name: &str) {
+    PROFILER.with(|p| {
+        if let Some(profiler) = p.borrow_mut().as_mut() {
+            profiler.dataflows.insert(index, name.to_string());
+        }
+    });
+}
+
+/// Returns the call times of a state of the calling worker, if profiling is enabled.
+pub fn register(dataflow: &str, operator: &str, name: &str) -> Option<Rc<StateTimes>> {
+    PROFILER.with(|p| {
+        p.borrow_mut().as_mut().map(|profiler| {
+            profiler
+                .states
+                .entry((dataflow.to_string(), operator.to_string(), name.to_string()))
+                .or_insert_with(|| {
+                    Rc::new(StateTimes {
+                        dataflow: dataflow.to_string(),
+                        operator: operator.to_string(),
+                        name: name.to_string(),
+                        ops: RefCell::new(vec![Histogram::default(); Op::ALL.len()]),
+                    })
+                })
+                .clone()
+        })
+    })
+}
+
+/// Records that the operator of `owner` received the notification for `time`.
+pub fn notified(owner: &StateOwner, time: usize) {
+    PROFILER.with(|p| {
+        if let Some(profiler) = p.borrow_mut().as_mut() {
+            let delay_ns = profiler.start.elapsed().to_nanos().saturating_sub(time as u64);
+            profiler
+                .notification_delays
+                .entry((owner.dataflow().to_string(), owner.operator().to_string()))
+                .or_insert_with(Default::default)
+                .add(delay_ns);
+        }
+    });
+}
+
+/// Adds the profile of the calling worker to the profile of the process.
+pub fn finish() {
+    let profiler = match PROFILER.with(|p| p.borrow_mut().take()) {
+        Some(profiler) => profiler,
+        None => return,
+    };
+    let mut worker = BTreeMap::new();
+    for ((dataflow, operator), histogram) in profiler.schedules.iter() {
+        worker_profile(&mut worker, dataflow, operator).schedule = histogram.clone();
+    }
+    for ((dataflow, operator), histogram) in profiler.notification_delays.iter() {
+        worker_profile(&mut worker, dataflow, operator).notification_delay = histogram.clone();
+    }
+    for state in profiler.states.values() {
+        let ops = worker_profile(&mut worker, &state.dataflow, &state.operator)
+            .state
+            .entry(state.name.clone())
+            .or_insert_with(BTreeMap::new);
+        for (op, histogram) in Op::ALL.iter().zip(state.ops.borrow().iter()) {
+            if histogram.count > 0 {
+                ops.insert(op.name().to_string(), histogram.clone());
+            }
+        }
+    }
+    let mut profiles = PROFILES.lock().unwrap();
+    for ((dataflow, operator), profile) in worker {
+        worker_profile(&mut profiles, &dataflow, &operator).merge(&profile);
+    }
+}
+
+fn worker_profile<'a>(
+    profiles: &'a mut BTreeMap<(String, String), OperatorProfile>,
+    dataflow: &str,
+    operator: &str,
+) -> &'a mut OperatorProfile {
+    profiles
+        .entry((dataflow.to_string(), operator.to_string()))
+        .or_insert_with(|| OperatorProfile {
+            dataflow: dataflow.to_string(),
+            operator: operator.to_string(),
+            ..Default::default()
+        })
+}
+
+/// Returns the merged profiles of all workers that finished.
+pub fn collect() -> Vec<OperatorProfile> {
+    PROFILES.lock().unwrap().values().cloned().collect()
+}
@@ -1,15 +1,20 @@
 use std::rc::Rc;
 use timely::dataflow::operators::capture::event::link::EventLink;
+use timely::dataflow::channels::pact::Pipeline;
 use timely::dataflow::operators::capture::Replay;
+use timely::dataflow::operators::Capture;
 use timely::dataflow::{Scope, Stream};
 
-use crate::event::{Auction, Bid, Date, Person};
+use crate::event::{Auction, Bid, Date, Event, Person};
 
 pub mod nexmark;
+pub mod registry;
 pub mod windows;
 
 use faster_rs::FasterKv;
 
+use crate::stats::FasterStats;
+
 pub fn assign_windows(event_time: usize,
                       window_slide: usize,
                       window_size: usize
@@ -27,15 +32,21 @@ pub fn assign_windows(event_time: usize,
 }
 
 #[inline(always)]
-fn maybe_refresh_faster(faster: &FasterKv, monotonic_serial_number: &mut u64) {
+pub fn maybe_refresh_faster(
+    faster: &FasterKv,
+    monotonic_serial_number: &mut u64,
+    stats: &mut FasterStats,
+) {
     if *monotonic_serial_number % (1 << 4) == 0 {
         faster.refresh();
         if *monotonic_serial_number % (1 << 10) == 0 {
             faster.complete_pending(true);
+            stats.completed(faster);
         }
     }
     if *monotonic_serial_number % (1 << 20) == 0 {
-        println!("Size: {}", faster.size());
+        stats.log_size_bytes = faster.size();
+        println!("Size: {}", stats.log_size_bytes);
     }
     *monotonic_serial_number += 1;
 }
@@ -70,6 +81,47 @@ impl<'a> NexmarkInput<'a> {
     }
 }
 
+/// Splits a stream of events into bids, auctions and people, and captures them into `input`.
+pub fn demux<S: Scope<Timestamp = usize>>(events: &Stream<S, Event>, input: &NexmarkInput) {
+    use timely::dataflow::operators::generic::builder_rc::OperatorBuilder;
+    let mut demux = OperatorBuilder::new("NEXMark demux".to_string(), events.scope());
+
+    let mut input_handle = demux.new_input(events, Pipeline);
+
+    let (mut b_out, bids_stream) = demux.new_output();
+    let (mut a_out, auctions_stream) = demux.new_output();
+    let (mut p_out, people_stream) = demux.new_output();
+
+    let mut demux_buffer = Vec::new();
+
+    demux.build(move |_capability| {
+        move |_frontiers| {
+            let mut b_out = b_out.activate();
+            let mut a_out = a_out.activate();
+            let mut p_out = p_out.activate();
+
+            input_handle.for_each(|time, data| {
+                data.swap(&mut demux_buffer);
+                let mut b_session = b_out.session(&time);
+                let mut a_session = a_out.session(&time);
+                let mut p_session = p_out.session(&time);
+
+                for datum in demux_buffer.drain(..) {
+                    match datum {
+                        Event::Bid(b) => b_session.give(b),
+                        Event::Auction(a) => a_session.give(a),
+                        Event::Person(p) => p_session.give(p),
+                    }
+                }
+            });
+        }
+    });
+
+    bids_stream.capture_into(input.bids.clone());
+    auctions_stream.capture_into(input.auctions.clone());
+    people_stream.capture_into(input.people.clone());
+}
+
 #[derive(Copy, Clone)]
 pub struct NexmarkTimer {
     pub time_dilation: usize,
@@ -5,6 +5,8 @@ use timely::dataflow::{Scope, Stream};
 
 use crate::event::{Auction, Bid, Date, Person};
 
+mod q1;
+mod q2;
 mod q3_managed;
 mod q4;
 mod q4_managed;
@@ -15,7 +17,22 @@ mod q6_managed;
 mod q7_managed;
 mod q8_managed;
 mod q8_managed_map;
+mod q9_managed;
+mod q11_managed;
+mod q12_managed;
+mod q13_managed;
+mod q14;
+mod q15_managed;
+mod q16_managed;
+mod q17_managed;
+mod q18_managed;
+mod q19_managed;
+mod q20_managed;
+mod q21;
+mod q22;
 
+pub use self::q1::q1;
+pub use self::q2::q2;
 pub use self::q3_managed::q3_managed;
 pub use self::q4::q4;
 pub use self::q4_managed::q4_managed;
@@ -26,3 +43,16 @@ pub use self::q6_managed::q6_managed;
 pub use self::q7_managed::q7_managed;
 pub use self::q8_managed::q8_managed;
 pub use self::q8_managed_map::q8_managed_map;
+pub use self::q9_managed::q9_managed;
+pub use self::q11_managed::q11_managed;
+pub use self::q12_managed::q12_managed;
+pub use self::q13_managed::q13_managed;
+pub use self::q14::q14;
+pub use self::q15_managed::{q15_managed, BidStats};
+pub use self::q16_managed::q16_managed;
+pub use self::q17_managed::q17_managed;
+pub use self::q18_managed::q18_managed;
+pub use self::q19_managed::q19_managed;
+pub use self::q20_managed::q20_managed;
+pub use self::q21::q21;
+pub use self::q22::q22;
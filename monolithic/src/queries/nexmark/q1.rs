@@ -0,0 +1,17 @@
+use timely::dataflow::operators::Map;
+use timely::dataflow::{Scope, Stream};
+
+use crate::event::Bid;
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+
+pub fn q1<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+) -> Stream<S, Bid> {
+    // Convert bid prices from dollars to euros
+    input
+        .bids(scope)
+        .map_in_place(|b| b.price = (b.price * 89) / 100)
+}
@@ -0,0 +1,23 @@
+use timely::dataflow::operators::Map;
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::windows::{Count, SessionWindowOperator, StateLayout};
+use crate::queries::{NexmarkInput, NexmarkTimer};
+
+/// Q11: User sessions.
+///
+/// Counts the bids of each bidder per session, where a session closes after `session_gap_ns`
+/// of inactivity.
+pub fn q11_managed<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    nt: NexmarkTimer,
+    scope: &mut S,
+    session_gap_ns: usize,
+) -> Stream<S, (usize, usize)> {
+    let bids = input
+        .bids(scope)
+        .map(move |b| (b.bidder, nt.from_nexmark_time(b.date_time)));
+    SessionWindowOperator::new("Q11 Sessions", session_gap_ns, Count, StateLayout::Rmw)
+        .apply(&bids, |b: &(usize, usize)| b.0, |b: &(usize, usize)| b.1)
+        .map(|(bidder, (_session_end, count))| (bidder, count))
+}
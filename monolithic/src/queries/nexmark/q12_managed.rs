@@ -0,0 +1,81 @@
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::{Map, Operator};
+use timely::dataflow::{Scope, Stream};
+
+use crate::profile;
+use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
+
+// The name of the operator, which its states and profile are reported under
+const COUNT_OPERATOR: &str = "Q12 Count";
+
+/// Q12: Processing-time windows.
+///
+/// Counts the bids of each bidder in tumbling windows of `window_size_ns` processing time. The
+/// epochs of the dataflow are processing timestamps, so windows are assigned by epoch rather
+/// than by bid time. The count of each bidder is its own key, updated with a read-modify-write
+/// per bidder and batch, and the bidders of a window are appended to it once per batch and
+/// deduplicated when the window fires.
+pub fn q12_managed<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+    window_size_ns: usize,
+) -> Stream<S, (usize, usize, usize)> {
+    let owner = StateOwner::new(COUNT_OPERATOR);
+    input
+        .bids(scope)
+        .map(|b| b.bidder)
+        .unary_notify(
+            Exchange::new(|bidder: &usize| *bidder as u64),
+            COUNT_OPERATOR,
+            None,
+            move |input, output, notificator, state_handle| {
+                // window end -> bidders of the window
+                let mut bidders =
+                    TracedMap::new(&owner, "bidders", state_handle.get_managed_map("bidders"));
+                // (window end, bidder) -> count
+                let mut counts =
+                    TracedMap::new(&owner, "counts", state_handle.get_managed_map("counts"));
+                let mut buffer = Vec::new();
+                input.for_each(|time, data| {
+                    trace::set_time(*time.time());
+                    let window_end = ((time.time() / window_size_ns) + 1) * window_size_ns;
+                    notificator.notify_at(time.delayed(&window_end));
+                    data.swap(&mut buffer);
+                    buffer.sort_unstable();
+                    // Bids of the batch per bidder
+                    let mut batch: Vec<(usize, usize)> = Vec::new();
+                    for bidder in buffer.drain(..) {
+                        match batch.last_mut() {
+                            Some((last, count)) if *last == bidder => *count += 1,
+                            _ => batch.push((bidder, 1)),
+                        }
+                    }
+                    for &(bidder, count) in batch.iter() {
+                        counts.rmw((window_end, bidder), count);
+                    }
+                    if !batch.is_empty() {
+                        let batch_bidders: Vec<usize> = batch.into_iter().map(|(b, _)| b).collect();
+                        bidders.rmw(window_end, batch_bidders);
+                    }
+                });
+
+                notificator.for_each(|cap, _, _| {
+                    trace::set_time(*cap.time());
+                    profile::notified(&owner, *cap.time());
+                    if let Some(mut window_bidders) = bidders.remove(cap.time()) {
+                        // Bidders of several batches were appended once per batch
+                        window_bidders.sort_unstable();
+                        window_bidders.dedup();
+                        let mut session = output.session(&cap);
+                        for bidder in window_bidders {
+                            let count: usize =
+                                counts.remove(&(*cap.time(), bidder)).expect("Must exist");
+                            session.give((bidder, count, *cap.time()));
+                        }
+                    }
+                });
+            },
+        )
+}
@@ -0,0 +1,50 @@
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::Operator;
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
+
+/// Q13: Bounded side input join.
+///
+/// Joins each bid with a static side input of `side_input_size` entries keyed by
+/// `auction % side_input_size`. Every worker keeps its own copy of the side input in state.
+pub fn q13_managed<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+    side_input_size: usize,
+) -> Stream<S, (usize, usize, usize, usize, String)> {
+    input.bids(scope).unary(
+        Pipeline,
+        "Q13 Side Input Join",
+        |_capability, _info, state_handle| {
+            let owner = StateOwner::new("Q13 Side Input Join");
+            let mut side_input =
+                TracedMap::new(&owner, "side_input", state_handle.get_managed_map("side_input"));
+            for key in 0..side_input_size {
+                side_input.insert(key, format!("{}", key));
+            }
+
+            move |input, output| {
+                input.for_each(|time, data| {
+                    trace::set_time(*time.time());
+                    let mut session = output.session(&time);
+                    for bid in data.iter() {
+                        let value: Option<std::rc::Rc<String>> =
+                            side_input.get(&(bid.auction % side_input_size));
+                        if let Some(value) = value {
+                            session.give((
+                                bid.auction,
+                                bid.bidder,
+                                bid.price,
+                                *bid.date_time,
+                                (*value).clone(),
+                            ));
+                        }
+                    }
+                });
+            }
+        },
+    )
+}
@@ -0,0 +1,40 @@
+use timely::dataflow::operators::{Filter, Map};
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+
+const HOUR_NS: usize = 60 * 60 * 1_000_000_000;
+
+/// Q14: Filter and calculation.
+///
+/// Converts bid prices, keeps the bids in a price range, classifies their time of day and
+/// counts the occurrences of 'c' in their extra field.
+pub fn q14<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+) -> Stream<S, (usize, usize, usize, String, usize, usize)> {
+    input
+        .bids(scope)
+        .map(|b| (b.auction, b.bidder, (b.price * 908) / 1000, b.date_time, b.extra))
+        .filter(|b| b.2 > 1_000_000 && b.2 <= 50_000_000)
+        .map(|(auction, bidder, price, date_time, extra)| {
+            let hour = (*date_time / HOUR_NS) % 24;
+            let bid_time_type = if hour >= 8 && hour <= 18 {
+                "dayTime"
+            } else if hour <= 6 || hour >= 20 {
+                "nightTime"
+            } else {
+                "otherTime"
+            };
+            let c_counts = extra.chars().filter(|c| *c == 'c').count();
+            (
+                auction,
+                bidder,
+                price,
+                bid_time_type.to_string(),
+                *date_time,
+                c_counts,
+            )
+        })
+}
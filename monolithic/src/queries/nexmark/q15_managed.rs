@@ -0,0 +1,129 @@
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::{Map, Operator};
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
+use faster_rs::FasterRmw;
+
+/// Bid statistics of a day, split by price rank (<10000, <1000000 and the rest).
+#[derive(
+    Eq, PartialEq, Ord, PartialOrd, Clone, Serialize, Deserialize, Debug, Abomonation, Hash, Default,
+)]
+pub struct BidStats {
+    pub day: usize,
+    pub total_bids: usize,
+    pub rank1_bids: usize,
+    pub rank2_bids: usize,
+    pub rank3_bids: usize,
+    pub total_bidders: usize,
+    pub rank1_bidders: usize,
+    pub rank2_bidders: usize,
+    pub rank3_bidders: usize,
+    pub total_auctions: usize,
+    pub rank1_auctions: usize,
+    pub rank2_auctions: usize,
+    pub rank3_auctions: usize,
+}
+
+impl FasterRmw for BidStats {
+    fn rmw(&self, _modification: Self) -> Self {
+        panic!("RMW on BidStats not allowed!");
+    }
+}
+
+pub const DAY_NS: usize = 24 * 60 * 60 * 1_000_000_000;
+
+/// Returns the bit of the price rank of a bid. Bit 0 stands for "any rank".
+pub fn price_rank(price: usize) -> u8 {
+    if price < 10_000 {
+        0b0010
+    } else if price < 1_000_000 {
+        0b0100
+    } else {
+        0b1000
+    }
+}
+
+impl BidStats {
+    /// Accounts for a bid. `new_bidder` and `new_auction` hold the rank bits under which the
+    /// bidder and the auction have not been seen before on that day.
+    pub fn update(&mut self, rank: u8, new_bidder: u8, new_auction: u8) {
+        self.total_bids += 1;
+        match rank {
+            0b0010 => self.rank1_bids += 1,
+            0b0100 => self.rank2_bids += 1,
+            _ => self.rank3_bids += 1,
+        }
+        self.total_bidders += (new_bidder & 0b0001) as usize;
+        self.rank1_bidders += ((new_bidder & 0b0010) >> 1) as usize;
+        self.rank2_bidders += ((new_bidder & 0b0100) >> 2) as usize;
+        self.rank3_bidders += ((new_bidder & 0b1000) >> 3) as usize;
+        self.total_auctions += (new_auction & 0b0001) as usize;
+        self.rank1_auctions += ((new_auction & 0b0010) >> 1) as usize;
+        self.rank2_auctions += ((new_auction & 0b0100) >> 2) as usize;
+        self.rank3_auctions += ((new_auction & 0b1000) >> 3) as usize;
+    }
+}
+
+/// Q15: Bidding statistics report.
+///
+/// Maintains the number of bids, distinct bidders and distinct auctions per day, overall and
+/// per price rank. Distinct values are tracked with one state entry per (day, bidder) and
+/// (day, auction) holding the rank bits seen so far.
+pub fn q15_managed<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+) -> Stream<S, BidStats> {
+    input
+        .bids(scope)
+        .map(|b| (*b.date_time / DAY_NS, b.bidder, b.auction, b.price))
+        .unary(
+            Exchange::new(|b: &(usize, usize, usize, usize)| b.0 as u64),
+            "Q15 Statistics",
+            |_capability, _info, state_handle| {
+                let owner = StateOwner::new("Q15 Statistics");
+                // day -> statistics
+                let mut stats =
+                    TracedMap::new(&owner, "stats", state_handle.get_managed_map("stats"));
+                // (day, bidder) -> rank bits seen
+                let mut bidders =
+                    TracedMap::new(&owner, "bidders", state_handle.get_managed_map("bidders"));
+                // (day, auction) -> rank bits seen
+                let mut auctions =
+                    TracedMap::new(&owner, "auctions", state_handle.get_managed_map("auctions"));
+
+                move |input, output| {
+                    input.for_each(|time, data| {
+                        trace::set_time(*time.time());
+                        let mut session = output.session(&time);
+                        for &(day, bidder, auction, price) in data.iter() {
+                            let rank = price_rank(price) | 0b0001;
+                            let seen_bidder: u8 =
+                                bidders.get(&(day, bidder)).map_or(0, |bits| *bits);
+                            if seen_bidder | rank != seen_bidder {
+                                bidders.insert((day, bidder), seen_bidder | rank);
+                            }
+                            let seen_auction: u8 =
+                                auctions.get(&(day, auction)).map_or(0, |bits| *bits);
+                            if seen_auction | rank != seen_auction {
+                                auctions.insert((day, auction), seen_auction | rank);
+                            }
+                            let mut day_stats = stats.remove(&day).unwrap_or(BidStats {
+                                day: day,
+                                ..Default::default()
+                            });
+                            day_stats.update(
+                                rank & !0b0001,
+                                rank & !seen_bidder,
+                                rank & !seen_auction,
+                            );
+                            session.give(day_stats.clone());
+                            stats.insert(day, day_stats);
+                        }
+                    });
+                }
+            },
+        )
+}
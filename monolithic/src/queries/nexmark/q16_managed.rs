@@ -0,0 +1,76 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::{Map, Operator};
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
+
+use super::q15_managed::{price_rank, BidStats, DAY_NS};
+
+/// Q16: Channel statistics report.
+///
+/// Same statistics as Q15, maintained per channel and day.
+pub fn q16_managed<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+) -> Stream<S, (String, BidStats)> {
+    input
+        .bids(scope)
+        .map(|b| (b.channel, *b.date_time / DAY_NS, b.bidder, b.auction, b.price))
+        .unary(
+            Exchange::new(|b: &(String, usize, usize, usize, usize)| {
+                let mut hasher = DefaultHasher::new();
+                b.0.hash(&mut hasher);
+                hasher.finish()
+            }),
+            "Q16 Statistics",
+            |_capability, _info, state_handle| {
+                let owner = StateOwner::new("Q16 Statistics");
+                // (channel, day) -> statistics
+                let mut stats =
+                    TracedMap::new(&owner, "stats", state_handle.get_managed_map("stats"));
+                // (channel, day, bidder) -> rank bits seen
+                let mut bidders =
+                    TracedMap::new(&owner, "bidders", state_handle.get_managed_map("bidders"));
+                // (channel, day, auction) -> rank bits seen
+                let mut auctions =
+                    TracedMap::new(&owner, "auctions", state_handle.get_managed_map("auctions"));
+
+                move |input, output| {
+                    input.for_each(|time, data| {
+                        trace::set_time(*time.time());
+                        let mut session = output.session(&time);
+                        for (channel, day, bidder, auction, price) in data.iter().cloned() {
+                            let rank = price_rank(price) | 0b0001;
+                            let bidder_key = (channel.clone(), day, bidder);
+                            let seen_bidder: u8 = bidders.get(&bidder_key).map_or(0, |bits| *bits);
+                            if seen_bidder | rank != seen_bidder {
+                                bidders.insert(bidder_key, seen_bidder | rank);
+                            }
+                            let auction_key = (channel.clone(), day, auction);
+                            let seen_auction: u8 =
+                                auctions.get(&auction_key).map_or(0, |bits| *bits);
+                            if seen_auction | rank != seen_auction {
+                                auctions.insert(auction_key, seen_auction | rank);
+                            }
+                            let key = (channel, day);
+                            let mut channel_stats = stats.remove(&key).unwrap_or(BidStats {
+                                day: day,
+                                ..Default::default()
+                            });
+                            channel_stats.update(
+                                rank & !0b0001,
+                                rank & !seen_bidder,
+                                rank & !seen_auction,
+                            );
+                            session.give((key.0.clone(), channel_stats.clone()));
+                            stats.insert(key, channel_stats);
+                        }
+                    });
+                }
+            },
+        )
+}
@@ -0,0 +1,95 @@
+use std::cmp::{max, min};
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::{Map, Operator};
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
+use faster_rs::FasterRmw;
+
+use super::q15_managed::{price_rank, DAY_NS};
+
+// (bids, rank 1 bids, rank 2 bids, rank 3 bids, min price, max price, sum of prices)
+#[derive(Serialize, Deserialize)]
+struct AuctionStats(usize, usize, usize, usize, usize, usize, usize);
+
+impl FasterRmw for AuctionStats {
+    fn rmw(&self, _modification: Self) -> Self {
+        panic!("RMW on AuctionStats not allowed!");
+    }
+}
+
+/// Q17: Auction statistics report.
+///
+/// Maintains per auction and day the number of bids per price rank and the minimum, maximum,
+/// average and total bid price. Outputs
+/// (auction, day, bids, rank 1 bids, rank 2 bids, rank 3 bids, min, max, avg, sum).
+pub fn q17_managed<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+) -> Stream<
+    S,
+    (
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+    ),
+> {
+    input
+        .bids(scope)
+        .map(|b| (b.auction, *b.date_time / DAY_NS, b.price))
+        .unary(
+            Exchange::new(|b: &(usize, usize, usize)| b.0 as u64),
+            "Q17 Statistics",
+            |_capability, _info, state_handle| {
+                let owner = StateOwner::new("Q17 Statistics");
+                // (auction, day) -> statistics
+                let mut stats =
+                    TracedMap::new(&owner, "stats", state_handle.get_managed_map("stats"));
+
+                move |input, output| {
+                    input.for_each(|time, data| {
+                        trace::set_time(*time.time());
+                        let mut session = output.session(&time);
+                        for &(auction, day, price) in data.iter() {
+                            let AuctionStats(bids, rank1, rank2, rank3, min_price, max_price, sum) =
+                                stats
+                                    .remove(&(auction, day))
+                                    .unwrap_or(AuctionStats(0, 0, 0, 0, price, price, 0));
+                            let rank = price_rank(price);
+                            let updated = AuctionStats(
+                                bids + 1,
+                                rank1 + (rank == 0b0010) as usize,
+                                rank2 + (rank == 0b0100) as usize,
+                                rank3 + (rank == 0b1000) as usize,
+                                min(min_price, price),
+                                max(max_price, price),
+                                sum + price,
+                            );
+                            session.give((
+                                auction,
+                                day,
+                                updated.0,
+                                updated.1,
+                                updated.2,
+                                updated.3,
+                                updated.4,
+                                updated.5,
+                                updated.6 / updated.0,
+                                updated.6,
+                            ));
+                            stats.insert((auction, day), updated);
+                        }
+                    });
+                }
+            },
+        )
+}
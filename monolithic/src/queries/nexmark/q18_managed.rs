@@ -0,0 +1,47 @@
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::Operator;
+use timely::dataflow::{Scope, Stream};
+
+use crate::event::Bid;
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
+
+/// Q18: Find last bid.
+///
+/// Keeps the latest bid of each (bidder, auction) pair and outputs it whenever it changes.
+pub fn q18_managed<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+) -> Stream<S, Bid> {
+    input.bids(scope).unary(
+        Exchange::new(|b: &Bid| b.bidder as u64),
+        "Q18 Last Bid",
+        |_capability, _info, state_handle| {
+            let owner = StateOwner::new("Q18 Last Bid");
+            // (bidder, auction) -> latest bid
+            let mut last_bids =
+                TracedMap::new(&owner, "last_bids", state_handle.get_managed_map("last_bids"));
+            let mut buffer = Vec::new();
+
+            move |input, output| {
+                input.for_each(|time, data| {
+                    trace::set_time(*time.time());
+                    data.swap(&mut buffer);
+                    let mut session = output.session(&time);
+                    for bid in buffer.drain(..) {
+                        let key = (bid.bidder, bid.auction);
+                        let is_latest = last_bids
+                            .get(&key)
+                            .map_or(true, |last: std::rc::Rc<Bid>| last.date_time <= bid.date_time);
+                        if is_latest {
+                            session.give(bid.clone());
+                            last_bids.insert(key, bid);
+                        }
+                    }
+                });
+            }
+        },
+    )
+}
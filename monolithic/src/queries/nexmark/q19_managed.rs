@@ -0,0 +1,63 @@
+use faster_rs::FasterRmw;
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::{Map, Operator};
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
+
+const TOP_K: usize = 10;
+
+// (price, bidder) pairs in descending price order
+#[derive(Serialize, Deserialize)]
+struct TopBids(Vec<(usize, usize)>);
+
+impl FasterRmw for TopBids {
+    fn rmw(&self, _modification: Self) -> Self {
+        panic!("RMW on TopBids not allowed!");
+    }
+}
+
+/// Q19: Auction top-10 price.
+///
+/// Keeps the ten highest bids of each auction and outputs (auction, bidder, price, rank) for
+/// every bid that enters the top ten.
+pub fn q19_managed<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+) -> Stream<S, (usize, usize, usize, usize)> {
+    input
+        .bids(scope)
+        .map(|b| (b.auction, b.bidder, b.price))
+        .unary(
+            Exchange::new(|b: &(usize, usize, usize)| b.0 as u64),
+            "Q19 Top Bids",
+            |_capability, _info, state_handle| {
+                let owner = StateOwner::new("Q19 Top Bids");
+                // auction -> top bids
+                let mut top_bids =
+                    TracedMap::new(&owner, "top_bids", state_handle.get_managed_map("top_bids"));
+
+                move |input, output| {
+                    input.for_each(|time, data| {
+                        trace::set_time(*time.time());
+                        let mut session = output.session(&time);
+                        for &(auction, bidder, price) in data.iter() {
+                            let mut top = top_bids.remove(&auction).unwrap_or(TopBids(Vec::new())).0;
+                            let position = top
+                                .iter()
+                                .position(|&(p, _)| p < price)
+                                .unwrap_or(top.len());
+                            if position < TOP_K {
+                                top.insert(position, (price, bidder));
+                                top.truncate(TOP_K);
+                                session.give((auction, bidder, price, position + 1));
+                            }
+                            top_bids.insert(auction, TopBids(top));
+                        }
+                    });
+                }
+            },
+        )
+}
@@ -0,0 +1,74 @@
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::Operator;
+use timely::dataflow::{Scope, Stream};
+
+use crate::event::{Auction, Bid};
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
+
+/// Q20: Expand bid with auction.
+///
+/// Joins each bid with its auction and keeps the pairs whose auction is in category 10. Bids
+/// that arrive before their auction wait in state.
+pub fn q20_managed<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+) -> Stream<S, (Bid, Auction)> {
+    let bids = input.bids(scope);
+    let auctions = input.auctions(scope);
+
+    let mut bids_buffer = vec![];
+    let mut auctions_buffer = vec![];
+
+    bids.binary(
+        &auctions,
+        Exchange::new(|b: &Bid| b.auction as u64),
+        Exchange::new(|a: &Auction| a.id as u64),
+        "Q20 Join",
+        |_capability, _info, state_handle| {
+            let owner = StateOwner::new("Q20 Join");
+            let mut auctions_state: TracedMap<usize, Auction> =
+                TracedMap::new(&owner, "auctions", state_handle.get_managed_map("auctions"));
+            let mut pending_bids: TracedMap<usize, Vec<Bid>> =
+                TracedMap::new(&owner, "pending_bids", state_handle.get_managed_map("pending_bids"));
+
+            move |input1, input2, output| {
+                // Process each input bid.
+                input1.for_each(|time, data| {
+                    trace::set_time(*time.time());
+                    data.swap(&mut bids_buffer);
+                    let mut session = output.session(&time);
+                    for bid in bids_buffer.drain(..) {
+                        match auctions_state.get(&bid.auction) {
+                            Some(auction) => {
+                                if auction.category == 10 {
+                                    session.give((bid, (*auction).clone()));
+                                }
+                            }
+                            None => pending_bids.rmw(bid.auction, vec![bid]),
+                        }
+                    }
+                });
+
+                // Process each input auction.
+                input2.for_each(|time, data| {
+                    trace::set_time(*time.time());
+                    data.swap(&mut auctions_buffer);
+                    let mut session = output.session(&time);
+                    for auction in auctions_buffer.drain(..) {
+                        if let Some(bids) = pending_bids.remove(&auction.id) {
+                            if auction.category == 10 {
+                                for bid in bids {
+                                    session.give((bid, auction.clone()));
+                                }
+                            }
+                        }
+                        auctions_state.insert(auction.id, auction);
+                    }
+                });
+            }
+        },
+    )
+}
@@ -0,0 +1,29 @@
+use timely::dataflow::operators::FlatMap;
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+
+/// Q21: Add channel id.
+///
+/// Maps the name of each bid's channel to an id. Bids from other channels take the id from
+/// the `channel_id` parameter of their URL and are dropped if it is missing.
+pub fn q21<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+) -> Stream<S, (usize, usize, usize, String, String)> {
+    input.bids(scope).flat_map(|b| {
+        let channel_id = match b.channel.to_lowercase().as_str() {
+            "apple" => Some("0".to_string()),
+            "google" => Some("1".to_string()),
+            "facebook" => Some("2".to_string()),
+            "baidu" => Some("3".to_string()),
+            _ => b
+                .url
+                .split(|c: char| c == '?' || c == '&')
+                .find(|param| param.starts_with("channel_id="))
+                .map(|param| param["channel_id=".len()..].to_string()),
+        };
+        channel_id.map(|id| (b.auction, b.bidder, b.price, b.channel, id))
+    })
+}
@@ -0,0 +1,26 @@
+use timely::dataflow::operators::Map;
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+
+/// Q22: Get URL directories.
+///
+/// Splits the URL of each bid and outputs its first three directories.
+pub fn q22<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+) -> Stream<S, (usize, usize, usize, String, String, String, String)> {
+    input.bids(scope).map(|b| {
+        let (dir1, dir2, dir3) = {
+            // "https://www.nexmark.com/dir1/dir2/dir3/item.htm?query=1"
+            let mut dirs = b.url.split('/').skip(3).map(String::from);
+            (
+                dirs.next().unwrap_or_default(),
+                dirs.next().unwrap_or_default(),
+                dirs.next().unwrap_or_default(),
+            )
+        };
+        (b.auction, b.bidder, b.price, b.channel, dir1, dir2, dir3)
+    })
+}
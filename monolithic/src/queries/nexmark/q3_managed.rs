@@ -1,11 +1,11 @@
 use timely::dataflow::channels::pact::Exchange;
 use timely::dataflow::operators::{Filter, Operator};
 use timely::dataflow::{Scope, Stream};
-use timely::state::primitives::ManagedMap;
 
 use crate::event::{Auction, Person};
 
 use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
 
 pub fn q3_managed<S: Scope<Timestamp = usize>>(
     input: &NexmarkInput,
@@ -27,13 +27,16 @@ pub fn q3_managed<S: Scope<Timestamp = usize>>(
         Exchange::new(|p: &Person| p.id as u64 / 100),
         "Q3 Join",
         |_capability, _info, state_handle| {
-            let mut state1: Box<ManagedMap<usize, Vec<Auction>>> =
-                state_handle.get_managed_map("state1");
-            let mut state2: Box<ManagedMap<usize, Person>> = state_handle.get_managed_map("state2");
+            let owner = StateOwner::new("Q3 Join");
+            let mut state1: TracedMap<usize, Vec<Auction>> =
+                TracedMap::new(&owner, "state1", state_handle.get_managed_map("state1"));
+            let mut state2: TracedMap<usize, Person> =
+                TracedMap::new(&owner, "state2", state_handle.get_managed_map("state2"));
 
             move |input1, input2, output| {
                 // Process each input auction.
                 input1.for_each(|time, data| {
+                    trace::set_time(*time.time());
                     data.swap(&mut auctions_buffer);
                     let mut session = output.session(&time);
                     for auction in auctions_buffer.drain(..) {
@@ -55,6 +58,7 @@ pub fn q3_managed<S: Scope<Timestamp = usize>>(
 
                 // Process each input person.
                 input2.for_each(|time, data| {
+                    trace::set_time(*time.time());
                     data.swap(&mut people_buffer);
                     let mut session = output.session(&time);
                     for person in people_buffer.drain(..) {
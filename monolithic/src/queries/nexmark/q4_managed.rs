@@ -3,6 +3,7 @@ use timely::dataflow::operators::{Map, Operator};
 use timely::dataflow::{Scope, Stream};
 
 use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
 use faster_rs::FasterRmw;
 
 #[derive(Serialize, Deserialize)]
@@ -26,11 +27,14 @@ pub fn q4_managed<S: Scope<Timestamp = usize>>(
             Exchange::new(|x: &(usize, usize)| x.0 as u64),
             "Q4 Average",
             |_cap, _info, state_handle| {
+                let owner = StateOwner::new("Q4 Average");
                 // Stores category -> (total, count)
-                let mut state = state_handle.get_managed_map("categories");
+                let mut state =
+                    TracedMap::new(&owner, "categories", state_handle.get_managed_map("categories"));
 
                 move |input, output| {
                     input.for_each(|time, data| {
+                        trace::set_time(*time.time());
                         let mut session = output.session(&time);
                         for (category, price) in data.iter().cloned() {
                             let mut current_sum_count =
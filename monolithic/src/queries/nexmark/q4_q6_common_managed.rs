@@ -2,11 +2,12 @@ use std::collections::HashMap;
 use timely::dataflow::channels::pact::Exchange;
 use timely::dataflow::operators::{Capability, Operator};
 use timely::dataflow::{Scope, Stream};
-use timely::state::primitives::ManagedMap;
 
+use crate::profile;
 use crate::event::{Auction, Bid};
 
 use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
 use faster_rs::FasterRmw;
 
 fn is_valid_bid(bid: &Bid, auction: &Auction) -> bool {
@@ -25,11 +26,15 @@ impl FasterRmw for AuctionBids {
 }
 
 
+// The name of the operator, which its states and profile are reported under
+const AUCTION_CLOSE_OPERATOR: &str = "Q4 Auction close";
+
 pub fn q4_q6_common_managed<S: Scope<Timestamp = usize>>(
     input: &NexmarkInput,
     nt: NexmarkTimer,
     scope: &mut S,
 ) -> Stream<S, (Auction, Bid)> {
+    let owner = StateOwner::new(AUCTION_CLOSE_OPERATOR);
     let bids = input.bids(scope);
     let auctions = input.auctions(scope);
 
@@ -37,14 +42,17 @@ pub fn q4_q6_common_managed<S: Scope<Timestamp = usize>>(
         &auctions,
         Exchange::new(|b: &Bid| b.auction as u64),
         Exchange::new(|a: &Auction| a.id as u64),
-        "Q4 Auction close",
+        AUCTION_CLOSE_OPERATOR,
         None,
         move |input1, input2, output, notificator, state_handle| {
-            let mut state: Box<ManagedMap<usize, AuctionBids>> = state_handle.get_managed_map("state");
-            let mut expirations: Box<ManagedMap<usize, Vec<Auction>>> = state_handle.get_managed_map("expirations");
+            let mut state: TracedMap<usize, AuctionBids> =
+                TracedMap::new(&owner, "state", state_handle.get_managed_map("state"));
+            let mut expirations: TracedMap<usize, Vec<Auction>> =
+                TracedMap::new(&owner, "expirations", state_handle.get_managed_map("expirations"));
             // Record each bid.
             // NB: We don't summarize as the max, because we don't know which are valid.
             input1.for_each(|time, data| {
+                trace::set_time(*time.time());
                 for bid in data.iter().cloned() {
                     let auction_bids = state.remove(&bid.auction);
                     match auction_bids {
@@ -73,6 +81,7 @@ pub fn q4_q6_common_managed<S: Scope<Timestamp = usize>>(
 
             // Record each auction.
             input2.for_each(|time, data| {
+                trace::set_time(*time.time());
                 for auction in data.iter().cloned() {
                     notificator.notify_at(time.delayed(&nt.from_nexmark_time(auction.expires)));
                     let auction_id = auction.id;
@@ -87,6 +96,8 @@ pub fn q4_q6_common_managed<S: Scope<Timestamp = usize>>(
             });
 
             notificator.for_each(|cap, _, _| {
+                trace::set_time(*cap.time());
+                profile::notified(&owner, *cap.time());
                 let mut session = output.session(&cap);
                 for auction in expirations.remove(cap.time()).expect("Must exist") {
                     let auction_bids = state.remove(&auction.id);
@@ -2,7 +2,9 @@ use std::collections::HashMap;
 use timely::dataflow::channels::pact::Exchange;
 use timely::dataflow::{Scope, Stream};
 
+use crate::profile;
 use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
 use faster_rs::FasterRmw;
 use timely::dataflow::operators::generic::operator::Operator;
 use timely::dataflow::operators::map::Map;
@@ -25,6 +27,10 @@ impl FasterRmw for AuctionBids {
     }
 }
 
+// The names of the operators, which their states and profiles are reported under
+const PER_WORKER_OPERATOR: &str = "Q5 Accumulate Per Worker";
+const GLOBAL_OPERATOR: &str = "Q5 Accumulate Globally";
+
 pub fn q5_managed<S: Scope<Timestamp = usize>>(
     input: &NexmarkInput,
     _nt: NexmarkTimer,
@@ -32,6 +38,8 @@ pub fn q5_managed<S: Scope<Timestamp = usize>>(
     window_slice_count: usize,
     window_slide_ns: usize,
 ) -> Stream<S, usize> {
+    let per_worker = StateOwner::new(PER_WORKER_OPERATOR);
+    let globally = StateOwner::new(GLOBAL_OPERATOR);
     input
         .bids(scope)
         .map(move |b| {
@@ -44,12 +52,14 @@ pub fn q5_managed<S: Scope<Timestamp = usize>>(
         // TODO: Could pre-aggregate pre-exchange, if there was reason to do so.
         .unary_notify(
             Exchange::new(|b: &(usize, _)| b.0 as u64),
-            "Q5 Accumulate Per Worker",
+            PER_WORKER_OPERATOR,
             None,
             move |input, output, notificator, state_handle| {
-                let mut pre_reduce_state = state_handle.get_managed_map("state");
+                let mut pre_reduce_state =
+                    TracedMap::new(&per_worker, "state", state_handle.get_managed_map("state"));
                 let mut buffer = Vec::new();
                 input.for_each(|time, data| {
+                    trace::set_time(*time.time());
                     // Notify at end timestamp of the slide the current epoch corresponds to
                     let current_slide = ((time.time() / window_slide_ns) + 1) * window_slide_ns;
                     let window_end = current_slide + (window_slice_count - 1) * window_slide_ns;
@@ -78,6 +88,8 @@ pub fn q5_managed<S: Scope<Timestamp = usize>>(
                 });
 
                 notificator.for_each(|cap, _, _| {
+                    trace::set_time(*cap.time());
+                    profile::notified(&per_worker, *cap.time());
                     // Received notification for the end of window
                     let mut counts = HashMap::new();
                     for i in 0..window_slice_count {
@@ -100,12 +112,14 @@ pub fn q5_managed<S: Scope<Timestamp = usize>>(
         )
         .unary_notify(
             Exchange::new(|_| 0),
-            "Q5 Accumulate Globally",
+            GLOBAL_OPERATOR,
             None,
             move |input, output, notificator, state_handle| {
-                let mut all_reduce_state = state_handle.get_managed_map("state");
+                let mut all_reduce_state =
+                    TracedMap::new(&globally, "state", state_handle.get_managed_map("state"));
                 let mut buffer = Vec::new();
                 input.for_each(|time, data| {
+                    trace::set_time(*time.time());
                     // Ask notification at the end of the window to produce output and clean up state
                     notificator.notify_at(time.delayed(&(time.time())));
                     data.swap(&mut buffer);
@@ -124,6 +138,8 @@ pub fn q5_managed<S: Scope<Timestamp = usize>>(
                     }
                 });
                 notificator.for_each(|cap, _, _| {
+                    trace::set_time(*cap.time());
+                    profile::notified(&globally, *cap.time());
                     output
                         .session(&cap)
                         .give((all_reduce_state.remove(cap.time()).expect("Must exist").0).0)
@@ -5,6 +5,7 @@ use timely::dataflow::operators::{Map, Operator};
 use timely::dataflow::{Scope, Stream};
 
 use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
 
 #[derive(Serialize, Deserialize)]
 struct Prices(VecDeque<usize>);
@@ -27,11 +28,14 @@ pub fn q6_managed<S: Scope<Timestamp = usize>>(
             Exchange::new(|x: &(usize, usize)| x.0 as u64),
             "Q6 Average",
             |_cap, _info, state_handle| {
+                let owner = StateOwner::new("Q6 Average");
                 // Store bidder -> [prices; 10]
-                let mut state = state_handle.get_managed_map("state");
+                let mut state =
+                    TracedMap::new(&owner, "state", state_handle.get_managed_map("state"));
 
                 move |input, output| {
                     input.for_each(|time, data| {
+                        trace::set_time(*time.time());
                         let mut session = output.session(&time);
                         for (bidder, price) in data.iter().cloned() {
                             let mut entry =
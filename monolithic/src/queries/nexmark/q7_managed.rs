@@ -2,9 +2,15 @@ use timely::dataflow::channels::pact::{Exchange, Pipeline};
 use timely::dataflow::operators::{Map, Operator};
 use timely::dataflow::{Scope, Stream};
 
+use crate::profile;
 use crate::event::Date;
 
 use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap};
+
+// The names of the operators, which their states and profiles are reported under
+const PRE_REDUCE_OPERATOR: &str = "Q7 Pre-Reduce";
+const ALL_REDUCE_OPERATOR: &str = "Q7 All-Reduce";
 
 pub fn q7_managed<S: Scope<Timestamp = usize>>(
     input: &NexmarkInput,
@@ -12,6 +18,8 @@ pub fn q7_managed<S: Scope<Timestamp = usize>>(
     scope: &mut S,
     window_size_ns: usize,
 ) -> Stream<S, usize> {
+    let pre_reduce = StateOwner::new(PRE_REDUCE_OPERATOR);
+    let all_reduce = StateOwner::new(ALL_REDUCE_OPERATOR);
     input
         .bids(scope)
         .map(move |b| {
@@ -22,12 +30,14 @@ pub fn q7_managed<S: Scope<Timestamp = usize>>(
         })
         .unary_notify(
             Pipeline,
-            "Q7 Pre-Reduce",
+            PRE_REDUCE_OPERATOR,
             None,
             move |input, output, notificator, state_handle| {
-                let mut pre_reduce_state = state_handle.get_managed_map("pre-reduce");
+                let mut pre_reduce_state =
+                    TracedMap::new(&pre_reduce, "pre-reduce", state_handle.get_managed_map("pre-reduce"));
                 let mut buffer = Vec::new();
                 input.for_each(|time, data| {
+                    trace::set_time(*time.time());
                     // Notify at end of epoch
                     notificator.notify_at(
                         time.delayed(&(((time.time() / window_size_ns) + 1) * window_size_ns)),
@@ -43,6 +53,8 @@ pub fn q7_managed<S: Scope<Timestamp = usize>>(
                 });
 
                 notificator.for_each(|cap, _, _| {
+                    trace::set_time(*cap.time());
+                    profile::notified(&pre_reduce, *cap.time());
                     if let Some(max_price) = pre_reduce_state.remove(&cap.time()) {
                         output.session(&cap).give((*cap.time(), max_price));
                     }
@@ -51,12 +63,14 @@ pub fn q7_managed<S: Scope<Timestamp = usize>>(
         )
         .unary_notify(
             Exchange::new(move |x: &(usize, usize)| (x.0 / window_size_ns) as u64),
-            "Q7 All-Reduce",
+            ALL_REDUCE_OPERATOR,
             None,
             move |input, output, notificator, state_handle| {
-                let mut all_reduce_state = state_handle.get_managed_map("all-reduce");
+                let mut all_reduce_state =
+                    TracedMap::new(&all_reduce, "all-reduce", state_handle.get_managed_map("all-reduce"));
                 let mut buffer = Vec::new();
                 input.for_each(|time, data| {
+                    trace::set_time(*time.time());
                     // Notify at end of epoch
                     notificator.notify_at(time.retain());
                     data.swap(&mut buffer);
@@ -69,6 +83,8 @@ pub fn q7_managed<S: Scope<Timestamp = usize>>(
                 });
 
                 notificator.for_each(|cap, _, _| {
+                    trace::set_time(*cap.time());
+                    profile::notified(&all_reduce, *cap.time());
                     if let Some(max_price) = all_reduce_state.remove(&cap.time()) {
                         output.session(&cap).give(max_price);
                     }
@@ -4,6 +4,7 @@ use timely::dataflow::{Scope, Stream};
 use std::collections::HashMap;
 
 use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap, TracedValue};
 
 pub fn q8_managed<S: Scope<Timestamp = usize>>(
     input: &NexmarkInput,
@@ -24,12 +25,16 @@ pub fn q8_managed<S: Scope<Timestamp = usize>>(
         Exchange::new(|a: &(usize, _)| a.0 as u64),
         "Q8 join",
         |_capability, _info, state_handle| {
-            let mut new_people = state_handle.get_managed_map("new_people");
-            let mut auctions_state = state_handle.get_managed_value("auctions");
+            let owner = StateOwner::new("Q8 join");
+            let mut new_people =
+                TracedMap::new(&owner, "new_people", state_handle.get_managed_map("new_people"));
+            let mut auctions_state =
+                TracedValue::new(&owner, "auctions", state_handle.get_managed_value("auctions"));
 
             move |input1, input2, output| {
                 // Notice new people.
-                input1.for_each(|_time, data| {
+                input1.for_each(|time, data| {
+                    trace::set_time(*time.time());
                     for (person, p_time) in data.iter().cloned() {
                         new_people.insert(person, p_time);
                     }
@@ -37,6 +42,7 @@ pub fn q8_managed<S: Scope<Timestamp = usize>>(
 
                 // Notice new auctions.
                 input2.for_each(|time, data| {
+                    trace::set_time(*time.time());
                     let ts = *time.time();
                     let _ = capabilities.entry(ts).or_insert_with(|| time.retain());
                     let mut data_vec = vec![];
@@ -62,6 +68,11 @@ pub fn q8_managed<S: Scope<Timestamp = usize>>(
                     .cloned()
                     .unwrap_or(usize::max_value());
                 let complete = std::cmp::min(complete1, complete2);
+                // The auctions are read and written back at the frontier, which is the time of
+                // those accesses until both inputs are exhausted
+                if complete < usize::max_value() {
+                    trace::set_time(complete);
+                }
 
                 //notificator.for_each(|cap, _, _| {
                 let mut auctions_vec = auctions_state.take().unwrap_or(Vec::new());
@@ -2,7 +2,12 @@ use timely::dataflow::channels::pact::Exchange;
 use timely::dataflow::operators::{Map, Operator};
 use timely::dataflow::{Scope, Stream};
 
+use crate::profile;
 use crate::queries::{NexmarkInput, NexmarkTimer};
+use crate::trace::{self, StateOwner, TracedMap, TracedValue};
+
+// The name of the operator, which its states and profile are reported under
+const JOIN_OPERATOR: &str = "Q8 join";
 
 pub fn q8_managed_map<S: Scope<Timestamp = usize>>(
     input: &NexmarkInput,
@@ -10,6 +15,7 @@ pub fn q8_managed_map<S: Scope<Timestamp = usize>>(
     scope: &mut S,
     window_size_ns: usize,
 ) -> Stream<S, usize> {
+    let owner = StateOwner::new(JOIN_OPERATOR);
     let auctions = input.auctions(scope).map(|a| (a.seller, a.date_time));
 
     let people = input.people(scope).map(|p| (p.id, p.date_time));
@@ -18,15 +24,19 @@ pub fn q8_managed_map<S: Scope<Timestamp = usize>>(
         &auctions,
         Exchange::new(|p: &(usize, _)| p.0 as u64),
         Exchange::new(|a: &(usize, _)| a.0 as u64),
-        "Q8 join",
+        JOIN_OPERATOR,
         None,
         move |input1, input2, output, notificator, state_handle| {
-            let mut new_people = state_handle.get_managed_map("new_people");
-            let mut auctions_state = state_handle.get_managed_map("auctions");
-            let mut index_state = state_handle.get_managed_value("entries");
+            let mut new_people =
+                TracedMap::new(&owner, "new_people", state_handle.get_managed_map("new_people"));
+            let mut auctions_state =
+                TracedMap::new(&owner, "auctions", state_handle.get_managed_map("auctions"));
+            let mut index_state =
+                TracedValue::new(&owner, "entries", state_handle.get_managed_value("entries"));
 
             // Notice new people.
             input1.for_each(|time, data| {
+                trace::set_time(*time.time());
                 notificator.notify_at(time.retain());
                 for (person, p_time) in data.iter().cloned() {
                     new_people.insert(person, p_time);
@@ -35,6 +45,7 @@ pub fn q8_managed_map<S: Scope<Timestamp = usize>>(
 
             // Notice new auctions.
             input2.for_each(|time, data| {
+                trace::set_time(*time.time());
                 let ts = *time.time();
                 let mut data_vec = vec![];
                 data.swap(&mut data_vec);
@@ -45,6 +56,8 @@ pub fn q8_managed_map<S: Scope<Timestamp = usize>>(
             });
 
             notificator.for_each(|cap, _, _| {
+                trace::set_time(*cap.time());
+                profile::notified(&owner, *cap.time());
                 let capability_time = *cap.time();
                 let mut entries_to_check = index_state.take().unwrap_or(Vec::new());
                 entries_to_check.push(capability_time);
@@ -0,0 +1,19 @@
+use timely::dataflow::{Scope, Stream};
+
+use crate::event::{Auction, Bid};
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+
+use super::q4_q6_common_managed;
+
+/// Q9: Winning bids.
+///
+/// Outputs each closed auction together with its highest valid bid. This is the auction close
+/// operator Q4 and Q6 build on, run as a query of its own.
+pub fn q9_managed<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    nt: NexmarkTimer,
+    scope: &mut S,
+) -> Stream<S, (Auction, Bid)> {
+    q4_q6_common_managed(input, nt, scope)
+}
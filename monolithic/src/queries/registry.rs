@@ -0,0 +1,821 @@
+//! Maps query names to dataflow builders that are generic over the state backend.
+
+use std::str::FromStr;
+
+use faster_rs::{FasterRmw, FasterValue};
+use timely::communication::Allocate;
+use timely::dataflow::operators::{Capture, Probe};
+use timely::dataflow::{ProbeHandle, Scope, Stream};
+use timely::state::backends::{
+    FASTERBackend, InMemoryBackend, RocksDBBackend, RocksDBMergeBackend, RocksDBMergeBackend2,
+};
+use timely::state::StateBackend;
+use timely::worker::Worker;
+use timely::Data;
+
+use super::nexmark;
+use super::windows::global::BidRecord;
+use super::windows::{
+    global, keyed, Aggregation, Avg, Count, CountMinTopK, Distinct, Folded, HyperLogLog, Max,
+    Median, Min, Rank, SlidingWindows, StateLayout, Sum, TopK,
+};
+use super::{NexmarkInput, NexmarkTimer};
+use crate::event::Bid;
+use crate::profile;
+use crate::stats;
+use crate::trace;
+use crate::trace::TracedRmw;
+
+/// The state backends a query can run on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Backend {
+    Faster,
+    RocksDB,
+    /// RocksDB with a merge operator that appends to vectors
+    RocksDBMerge,
+    /// RocksDB with a merge operator that adds counts
+    RocksDBMerge2,
+    InMemory,
+}
+
+impl Backend {
+    pub const NAMES: &'static [&'static str] =
+        &["faster", "rocksdb", "rocksdb-merge", "rocksdb-merge2", "mem"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Faster => "faster",
+            Backend::RocksDB => "rocksdb",
+            Backend::RocksDBMerge => "rocksdb-merge",
+            Backend::RocksDBMerge2 => "rocksdb-merge2",
+            Backend::InMemory => "mem",
+        }
+    }
+
+    /// The configuration file the backend reads from the working directory.
+    pub fn config_file(&self) -> Option<&'static str> {
+        match self {
+            Backend::Faster => Some("faster.config"),
+            Backend::RocksDB => Some("rocksdb.config"),
+            Backend::RocksDBMerge => Some("rocksdbmerge.config"),
+            Backend::RocksDBMerge2 => Some("rocksdbmerge2.config"),
+            Backend::InMemory => None,
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "faster" => Ok(Backend::Faster),
+            "rocksdb" => Ok(Backend::RocksDB),
+            "rocksdb-merge" => Ok(Backend::RocksDBMerge),
+            "rocksdb-merge2" => Ok(Backend::RocksDBMerge2),
+            "mem" => Ok(Backend::InMemory),
+            _ => Err(format!(
+                "unknown backend '{}', expected one of {:?}",
+                s,
+                Backend::NAMES
+            )),
+        }
+    }
+}
+
+/// Names of all queries known to the registry.
+pub const QUERIES: &[&str] = &[
+    "q1", "q2", "q3", "q4", "q4_flex", "q5", "q5_index", "q6", "q7", "q8", "q8_map", "q9", "q11",
+    "q12", "q13", "q14", "q15", "q16", "q17", "q18", "q19", "q20", "q21", "q22",
+    "window_2_count", "window_2_rank", "window_2b_count", "window_2b_rank",
+    "window_2_sum", "window_2_min", "window_2_max", "window_2_avg", "window_2_distinct",
+    "window_2_median", "window_2_top_k", "window_2_hll_distinct", "window_2_cm_top_k",
+    "window_sliced_count", "window_sliced_rank", "window_sliced_sum", "window_sliced_min",
+    "window_sliced_max",
+    "window_tumbling_count", "window_tumbling_rank", "window_count_count", "window_count_rank",
+    "session_count", "session_b_count",
+];
+
+// Query names from before the `--backend` flag, which also fix the backend.
+const LEGACY_QUERIES: &[(&str, &str, Backend)] = &[
+    ("q5_faster_index", "q5_index", Backend::Faster),
+    ("q5_rocksdb_index", "q5_index", Backend::RocksDB),
+    ("q8_faster_map", "q8_map", Backend::Faster),
+    ("q8_rocksdb_map", "q8_map", Backend::RocksDB),
+    ("window_2_faster_count", "window_2_count", Backend::Faster),
+    ("window_2_faster_rank", "window_2_rank", Backend::Faster),
+    ("window_2a_rocksdb_count", "window_2_count", Backend::RocksDB),
+    ("window_2a_rocksdb_rank", "window_2_rank", Backend::RocksDB),
+    ("window_2b_rocksdb_count", "window_2b_count", Backend::RocksDBMerge2),
+    ("window_2b_rocksdb_rank", "window_2b_rank", Backend::RocksDBMerge),
+];
+
+/// How the read-modify-writes of a query merge modifications into state, which decides the
+/// RocksDB merge operator it can run on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Merge {
+    /// Appends to vectors, as the merge operator of `rocksdb-merge` does
+    Append,
+    /// Adds counts, as the merge operator of `rocksdb-merge2` does
+    Add,
+    /// Merges in more than one way, like `session_count`, which appends keys to timeouts and adds
+    /// counts
+    Unsupported,
+}
+
+// The queries with read-modify-writes and how they merge on the RocksDB merge backends. Other
+// queries do not use read-modify-writes and run on every backend. MIN, MAX, HyperLogLog and
+// Count-Min append the records of a window there and fold them when it fires, and AVG adds to a
+// sum and a count. `session_b_count` appends the bids of a session as well.
+const MERGES: &[(&str, Merge)] = &[
+    ("q4", Merge::Append),
+    ("q4_flex", Merge::Append),
+    ("q6", Merge::Append),
+    ("q9", Merge::Append),
+    ("q11", Merge::Unsupported),
+    ("q12", Merge::Unsupported),
+    ("q20", Merge::Append),
+    ("window_2_count", Merge::Add),
+    ("window_2_rank", Merge::Append),
+    ("window_2b_count", Merge::Add),
+    ("window_2b_rank", Merge::Append),
+    ("window_2_sum", Merge::Add),
+    ("window_2_min", Merge::Append),
+    ("window_2_max", Merge::Append),
+    ("window_2_avg", Merge::Add),
+    ("window_2_distinct", Merge::Append),
+    ("window_2_median", Merge::Append),
+    ("window_2_top_k", Merge::Append),
+    ("window_2_hll_distinct", Merge::Append),
+    ("window_2_cm_top_k", Merge::Append),
+    ("window_sliced_count", Merge::Add),
+    ("window_sliced_rank", Merge::Append),
+    ("window_sliced_sum", Merge::Add),
+    ("window_sliced_min", Merge::Append),
+    ("window_sliced_max", Merge::Append),
+    ("window_tumbling_count", Merge::Add),
+    ("window_tumbling_rank", Merge::Append),
+    ("window_count_count", Merge::Add),
+    ("window_count_rank", Merge::Append),
+    ("session_count", Merge::Unsupported),
+    ("session_b_count", Merge::Append),
+];
+
+impl Merge {
+    fn description(&self) -> &'static str {
+        match self {
+            Merge::Append => "append to vectors",
+            Merge::Add => "add counts",
+            Merge::Unsupported => "do not only append to vectors or only add counts",
+        }
+    }
+}
+
+impl Backend {
+    // How the merge operator of the backend merges, if it has one.
+    fn merge(&self) -> Option<Merge> {
+        match self {
+            Backend::RocksDBMerge => Some(Merge::Append),
+            Backend::RocksDBMerge2 => Some(Merge::Add),
+            _ => None,
+        }
+    }
+}
+
+/// Resolves a query name to a registered query and the backend it runs on.
+///
+/// Legacy names like `q3_faster` or `window_2a_rocksdb_count` select their own backend,
+/// all other names run on `backend`. Queries whose read-modify-writes do not merge the way the
+/// merge operator of a RocksDB merge backend does cannot run on that backend.
+pub fn resolve(name: &str, backend: Backend) -> Result<(&'static str, Backend), String> {
+    let (query, backend) = find(name, backend)?;
+    if let Some(merge) = backend.merge() {
+        if let Some((_, query_merge)) = MERGES.iter().find(|(q, _)| *q == query) {
+            if *query_merge != merge {
+                return Err(format!(
+                    "query '{}' cannot run on backend '{}', whose merge operator can only {}: its \
+                     read-modify-writes {}",
+                    name,
+                    backend.name(),
+                    merge.description(),
+                    query_merge.description()
+                ));
+            }
+        }
+    }
+    Ok((query, backend))
+}
+
+fn find(name: &str, backend: Backend) -> Result<(&'static str, Backend), String> {
+    if let Some(query) = QUERIES.iter().find(|q| **q == name) {
+        return Ok((query, backend));
+    }
+    if let Some((_, query, backend)) = LEGACY_QUERIES.iter().find(|(n, _, _)| *n == name) {
+        return Ok((query, *backend));
+    }
+    for (suffix, backend) in &[("_faster", Backend::Faster), ("_rocksdb", Backend::RocksDB)] {
+        if name.ends_with(suffix) {
+            let base = &name[..name.len() - suffix.len()];
+            if let Some(query) = QUERIES.iter().find(|q| **q == base) {
+                return Ok((query, *backend));
+            }
+        }
+    }
+    Err(format!("unknown query '{}', expected one of {:?}", name, QUERIES))
+}
+
+/// Parameters of the windowed queries.
+#[derive(Clone, Debug, Serialize)]
+pub struct QueryParams {
+    pub window_slice_count: usize,
+    pub window_slide_ns: usize,
+    /// Number of records of a count window
+    pub window_count: usize,
+    /// Number of values the top-k window queries emit per window
+    pub window_top_k: usize,
+    pub q5_window_slice_count: usize,
+    pub q5_window_slide_ns: usize,
+    pub q7_window_size_ns: usize,
+    pub q8_window_size_ns: usize,
+    /// Processing-time window of Q12
+    pub q12_window_size_ns: usize,
+    /// Number of entries of the side input of Q13
+    pub q13_side_input_size: usize,
+    /// Inactivity gap that closes a session, of Q11 and the session window queries
+    pub session_gap_ns: usize,
+}
+
+impl Default for QueryParams {
+    fn default() -> Self {
+        QueryParams {
+            window_slice_count: 0,
+            window_slide_ns: 0,
+            window_count: 0,
+            window_top_k: 0,
+            // 60s windows, ticking in 1s intervals
+            // NEXMark default is 60 minutes, ticking in one minute intervals
+            q5_window_slice_count: 60,
+            q5_window_slide_ns: 1_000_000_000,
+            // Window ticks every 10 seconds.
+            // NEXMark default is different: ticks every 60s
+            q7_window_size_ns: 10_000_000_000,
+            q8_window_size_ns: 12 * 60 * 60 * 1_000_000_000,
+            q12_window_size_ns: 10_000_000_000,
+            q13_side_input_size: 500,
+            session_gap_ns: 10_000_000_000,
+        }
+    }
+}
+
+/// Builds the dataflow of `query` on `backend`, probing its output with `probe`.
+pub fn build_dataflow<A: Allocate>(
+    query: &str,
+    backend: Backend,
+    worker: &mut Worker<A>,
+    input: &NexmarkInput,
+    nt: NexmarkTimer,
+    params: &QueryParams,
+    probe: &mut ProbeHandle<usize>,
+) {
+    match backend {
+        Backend::Faster => {
+            build::<A, FASTERBackend>(query, backend, worker, input, nt, params, probe)
+        }
+        Backend::RocksDB => {
+            build::<A, RocksDBBackend>(query, backend, worker, input, nt, params, probe)
+        }
+        Backend::RocksDBMerge => {
+            build::<A, RocksDBMergeBackend>(query, backend, worker, input, nt, params, probe)
+        }
+        Backend::RocksDBMerge2 => {
+            build::<A, RocksDBMergeBackend2>(query, backend, worker, input, nt, params, probe)
+        }
+        Backend::InMemory => {
+            build::<A, InMemoryBackend>(query, backend, worker, input, nt, params, probe)
+        }
+    }
+}
+
+fn build<A: Allocate, B: StateBackend>(
+    query: &str,
+    backend: Backend,
+    worker: &mut Worker<A>,
+    input: &NexmarkInput,
+    nt: NexmarkTimer,
+    params: &QueryParams,
+    probe: &mut ProbeHandle<usize>,
+) {
+    // Name the states of the dataflow after the query in state traces and statistics
+    trace::set_dataflow(query);
+    let build = stats::start_build(query);
+    worker.dataflow::<_, _, _, B>(|scope, _| {
+        // Name the operators of the dataflow after the query in operator profiles
+        profile::name_dataflow(scope.addr()[0], query);
+        match query {
+            // Q1: Currency conversion
+            "q1" => {
+                nexmark::q1(input, nt, scope).probe_with(probe);
+            }
+            // Q2: Selection
+            "q2" => {
+                nexmark::q2(input, nt, scope).probe_with(probe);
+            }
+            // Q3: Join some auctions
+            "q3" => {
+                nexmark::q3_managed(input, nt, scope).probe_with(probe);
+            }
+            // Q4: Find average selling price per category
+            "q4" => {
+                nexmark::q4_q6_common_managed(input, nt, scope)
+                    .capture_into(input.closed_auctions.clone());
+                nexmark::q4_managed(input, nt, scope).probe_with(probe);
+            }
+            // Q4 with an in-memory hash map for the final aggregation
+            "q4_flex" => {
+                nexmark::q4_q6_common_managed(input, nt, scope)
+                    .capture_into(input.closed_auctions.clone());
+                nexmark::q4(input, nt, scope).probe_with(probe);
+            }
+            // Q5: Hot items
+            "q5" => {
+                nexmark::q5_managed(
+                    input,
+                    nt,
+                    scope,
+                    params.q5_window_slice_count,
+                    params.q5_window_slide_ns,
+                )
+                .probe_with(probe);
+            }
+            "q5_index" => {
+                nexmark::q5_managed_index(
+                    input,
+                    nt,
+                    scope,
+                    params.q5_window_slice_count,
+                    params.q5_window_slide_ns,
+                )
+                .probe_with(probe);
+            }
+            // Q6: Average selling price by seller
+            "q6" => {
+                nexmark::q4_q6_common_managed(input, nt, scope)
+                    .capture_into(input.closed_auctions.clone());
+                nexmark::q6_managed(input, nt, scope).probe_with(probe);
+            }
+            // Q7: Highest bid
+            "q7" => {
+                nexmark::q7_managed(input, nt, scope, params.q7_window_size_ns).probe_with(probe);
+            }
+            // Q8: Monitor new users
+            "q8" => {
+                nexmark::q8_managed(input, nt, scope, params.q8_window_size_ns).probe_with(probe);
+            }
+            "q8_map" => {
+                nexmark::q8_managed_map(input, nt, scope, params.q8_window_size_ns)
+                    .probe_with(probe);
+            }
+            // Q9: Winning bids
+            "q9" => {
+                nexmark::q9_managed(input, nt, scope).probe_with(probe);
+            }
+            // Q11: User sessions, closed after `session_gap_ns` of inactivity
+            "q11" => {
+                nexmark::q11_managed(input, nt, scope, params.session_gap_ns).probe_with(probe);
+            }
+            // Q12: Tumbling windows of `q12_window_size_ns` processing time
+            "q12" => {
+                nexmark::q12_managed(input, nt, scope, params.q12_window_size_ns).probe_with(probe);
+            }
+            // Q13: Bounded side input join
+            "q13" => {
+                nexmark::q13_managed(input, nt, scope, params.q13_side_input_size)
+                    .probe_with(probe);
+            }
+            // Q14: Filter and calculation
+            "q14" => {
+                nexmark::q14(input, nt, scope).probe_with(probe);
+            }
+            // Q15: Bidding statistics report
+            "q15" => {
+                nexmark::q15_managed(input, nt, scope).probe_with(probe);
+            }
+            // Q16: Channel statistics report
+            "q16" => {
+                nexmark::q16_managed(input, nt, scope).probe_with(probe);
+            }
+            // Q17: Auction statistics report
+            "q17" => {
+                nexmark::q17_managed(input, nt, scope).probe_with(probe);
+            }
+            // Q18: Find last bid
+            "q18" => {
+                nexmark::q18_managed(input, nt, scope).probe_with(probe);
+            }
+            // Q19: Auction top-k
+            "q19" => {
+                nexmark::q19_managed(input, nt, scope).probe_with(probe);
+            }
+            // Q20: Expand bid with auction
+            "q20" => {
+                nexmark::q20_managed(input, nt, scope).probe_with(probe);
+            }
+            // Q21: Add channel id
+            "q21" => {
+                nexmark::q21(input, nt, scope).probe_with(probe);
+            }
+            // Q22: Get URL directories
+            "q22" => {
+                nexmark::q22(input, nt, scope).probe_with(probe);
+            }
+            // 2nd window implementation using put + get and COUNT aggregation
+            "window_2_count" => {
+                assert!(params.window_slice_count > 0);
+                assert!(params.window_slide_ns > 0);
+                global::window_2_faster_count(
+                    input,
+                    nt,
+                    scope,
+                    params.window_slice_count,
+                    params.window_slide_ns,
+                )
+                .probe_with(probe);
+            }
+            // 2nd window implementation using put + get and RANK aggregation
+            "window_2_rank" => {
+                assert!(params.window_slice_count > 0);
+                assert!(params.window_slide_ns > 0);
+                global::window_2_faster_rank(
+                    input,
+                    nt,
+                    scope,
+                    params.window_slice_count,
+                    params.window_slide_ns,
+                )
+                .probe_with(probe);
+            }
+            // 2nd window implementation using merge and COUNT aggregation
+            "window_2b_count" => {
+                assert!(params.window_slice_count > 0);
+                assert!(params.window_slide_ns > 0);
+                global::window_2b_rocksdb_count(
+                    input,
+                    nt,
+                    scope,
+                    params.window_slice_count,
+                    params.window_slide_ns,
+                )
+                .probe_with(probe);
+            }
+            // 2nd window implementation using merge and RANK aggregation
+            "window_2b_rank" => {
+                assert!(params.window_slice_count > 0);
+                assert!(params.window_slide_ns > 0);
+                global::window_2b_rocksdb_rank(
+                    input,
+                    nt,
+                    scope,
+                    params.window_slice_count,
+                    params.window_slide_ns,
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows merging each bid into every window and SUM of prices
+            "window_2_sum" => {
+                sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Duplicated,
+                    |b| (b.auction, b.price, *b.date_time),
+                    Sum(|b: &(usize, usize, usize)| b.1),
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows merging each bid into every window and MIN of prices, folded from
+            // the prices of a window on RocksDB merge backends
+            "window_2_min" => {
+                folded_sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Duplicated,
+                    |b| (b.auction, b.price, *b.date_time),
+                    Min(|b: &(usize, usize, usize)| b.1),
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows merging each bid into every window and MAX of prices, folded from
+            // the prices of a window on RocksDB merge backends
+            "window_2_max" => {
+                folded_sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Duplicated,
+                    |b| (b.auction, b.price, *b.date_time),
+                    Max(|b: &(usize, usize, usize)| b.1),
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows merging each bid into every window and AVG of prices, from a sum
+            // and a count that the merge operator adds to on RocksDB merge backends
+            "window_2_avg" => match backend.merge() {
+                Some(_) => {
+                    global::window_2_avg_counters(input, scope, sliding_windows(params))
+                        .probe_with(probe);
+                }
+                None => {
+                    sliding_window(
+                        input,
+                        scope,
+                        params,
+                        backend,
+                        Windows::Duplicated,
+                        |b| (b.auction, b.price, *b.date_time),
+                        Avg(|b: &(usize, usize, usize)| b.1),
+                    )
+                    .probe_with(probe);
+                }
+            },
+            // Sliding windows merging each bid into every window and exact COUNT DISTINCT of
+            // bidders
+            "window_2_distinct" => {
+                sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Duplicated,
+                    |b| (b.auction, b.bidder, *b.date_time),
+                    Distinct(|b: &(usize, usize, usize)| b.1),
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows merging each bid into every window and MEDIAN of prices
+            "window_2_median" => {
+                sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Duplicated,
+                    |b| (b.auction, b.price, *b.date_time),
+                    Median(|b: &(usize, usize, usize)| b.1),
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows merging each bid into every window and TOP-K auctions by bids
+            "window_2_top_k" => {
+                sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Duplicated,
+                    |b| (b.auction, *b.date_time),
+                    TopK::new(params.window_top_k, |b: &(usize, usize)| b.0),
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows merging each bid into every window and HyperLogLog COUNT DISTINCT of
+            // bidders, folded from the bidders of a window on RocksDB merge backends
+            "window_2_hll_distinct" => {
+                folded_sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Duplicated,
+                    |b| (b.auction, b.bidder, *b.date_time),
+                    HyperLogLog::new(HLL_PRECISION, |b: &(usize, usize, usize)| b.1),
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows merging each bid into every window and Count-Min TOP-K auctions by
+            // bids, folded from the auctions of a window on RocksDB merge backends
+            "window_2_cm_top_k" => {
+                folded_sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Duplicated,
+                    |b| (b.auction, *b.date_time),
+                    CountMinTopK::new(
+                        params.window_top_k,
+                        CM_DEPTH,
+                        CM_WIDTH,
+                        |b: &(usize, usize)| b.0,
+                    ),
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows using slices and COUNT aggregation
+            "window_sliced_count" => {
+                sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Sliced,
+                    |b| (b.auction, *b.date_time),
+                    Count,
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows using slices and RANK aggregation
+            "window_sliced_rank" => {
+                sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Sliced,
+                    |b| (b.auction, *b.date_time),
+                    Rank,
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows using slices and SUM of prices
+            "window_sliced_sum" => {
+                sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Sliced,
+                    |b| (b.auction, b.price, *b.date_time),
+                    Sum(|b: &(usize, usize, usize)| b.1),
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows using slices and MIN of prices, folded from the prices of a window on
+            // RocksDB merge backends
+            "window_sliced_min" => {
+                folded_sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Sliced,
+                    |b| (b.auction, b.price, *b.date_time),
+                    Min(|b: &(usize, usize, usize)| b.1),
+                )
+                .probe_with(probe);
+            }
+            // Sliding windows using slices and MAX of prices, folded from the prices of a window on
+            // RocksDB merge backends
+            "window_sliced_max" => {
+                folded_sliding_window(
+                    input,
+                    scope,
+                    params,
+                    backend,
+                    Windows::Sliced,
+                    |b| (b.auction, b.price, *b.date_time),
+                    Max(|b: &(usize, usize, usize)| b.1),
+                )
+                .probe_with(probe);
+            }
+            // Tumbling windows of `window_slice_count * window_slide_ns` and COUNT aggregation
+            "window_tumbling_count" => {
+                assert!(params.window_slice_count > 0);
+                assert!(params.window_slide_ns > 0);
+                global::window_tumbling_count(
+                    input,
+                    nt,
+                    scope,
+                    params.window_slice_count * params.window_slide_ns,
+                )
+                .probe_with(probe);
+            }
+            // Tumbling windows of `window_slice_count * window_slide_ns` and RANK aggregation
+            "window_tumbling_rank" => {
+                assert!(params.window_slice_count > 0);
+                assert!(params.window_slide_ns > 0);
+                global::window_tumbling_rank(
+                    input,
+                    nt,
+                    scope,
+                    params.window_slice_count * params.window_slide_ns,
+                )
+                .probe_with(probe);
+            }
+            // Count windows of `window_count` bids per bidder and COUNT aggregation
+            "window_count_count" => {
+                assert!(params.window_count > 0);
+                global::window_count_count(input, nt, scope, params.window_count)
+                    .probe_with(probe);
+            }
+            // Count windows of `window_count` bids per bidder and RANK aggregation
+            "window_count_rank" => {
+                assert!(params.window_count > 0);
+                global::window_count_rank(input, nt, scope, params.window_count)
+                    .probe_with(probe);
+            }
+            // Session windows of bidders using rmw and COUNT aggregation
+            "session_count" => {
+                keyed::session_count(input, nt, scope, params.session_gap_ns).probe_with(probe);
+            }
+            // Session windows of bidders using merge and COUNT aggregation, which folds appended bids
+            "session_b_count" => {
+                keyed::session_b_count(input, nt, scope, params.session_gap_ns).probe_with(probe);
+            }
+            _ => panic!("unknown query '{}'", query),
+        }
+    });
+    build.finish();
+    trace::set_dataflow("");
+}
+
+// 1024 registers of `window_2_hll_distinct`, with a standard error of about 3%
+const HLL_PRECISION: u32 = 10;
+// 4 rows of 256 counters of `window_2_cm_top_k`
+const CM_DEPTH: usize = 4;
+const CM_WIDTH: usize = 256;
+
+// The sliding windows of `window_slice_count` slides of `window_slide_ns`.
+fn sliding_windows(params: &QueryParams) -> SlidingWindows {
+    assert!(params.window_slice_count > 0);
+    assert!(params.window_slide_ns > 0);
+    SlidingWindows::new(
+        params.window_slice_count * params.window_slide_ns,
+        params.window_slide_ns,
+    )
+}
+
+// How a sliding window query stores the records of its windows.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Windows {
+    /// Merges each record into every window it belongs to
+    Duplicated,
+    /// Merges each record into the slice of its slide, and combines slices when a window fires
+    Sliced,
+}
+
+// Builds a sliding window query over the bids of `input`, mapped to records by `record`, that
+// aggregates each window with `aggregation`. The windows are initialized before records are
+// merged into them on RocksDB merge backends, as their merge operators need a first put.
+fn sliding_window<S, D, A>(
+    input: &NexmarkInput,
+    scope: &mut S,
+    params: &QueryParams,
+    backend: Backend,
+    windows: Windows,
+    record: fn(Bid) -> D,
+    aggregation: A,
+) -> Stream<S, A::Output>
+where
+    S: Scope<Timestamp = usize>,
+    D: BidRecord,
+    A: Aggregation<D>,
+    A::State: FasterValue + FasterRmw + TracedRmw,
+    A::Output: Data,
+{
+    let sliding_windows = sliding_windows(params);
+    let layout = match backend.merge() {
+        Some(_) => StateLayout::MergeInit,
+        None => StateLayout::Rmw,
+    };
+    match windows {
+        Windows::Duplicated => {
+            global::window_2(input, scope, sliding_windows, layout, record, aggregation)
+        }
+        Windows::Sliced => {
+            global::window_sliced(input, scope, sliding_windows, layout, record, aggregation)
+        }
+    }
+}
+
+// Builds a sliding window query like `sliding_window`. On RocksDB merge backends, whose merge
+// operators cannot merge the states of `aggregation`, the records of a window are appended to a
+// vector instead, and aggregated with `aggregation` when the window fires.
+fn folded_sliding_window<S, D, A>(
+    input: &NexmarkInput,
+    scope: &mut S,
+    params: &QueryParams,
+    backend: Backend,
+    windows: Windows,
+    record: fn(Bid) -> D,
+    aggregation: A,
+) -> Stream<S, A::Output>
+where
+    S: Scope<Timestamp = usize>,
+    D: BidRecord,
+    A: Aggregation<D>,
+    A::State: FasterValue + FasterRmw + TracedRmw,
+    A::Output: Data,
+    Vec<D>: FasterValue + FasterRmw,
+{
+    match backend.merge() {
+        Some(_) => {
+            let aggregation = Folded(aggregation);
+            sliding_window(input, scope, params, backend, windows, record, aggregation)
+        }
+        None => sliding_window(input, scope, params, backend, windows, record, aggregation),
+    }
+}
@@ -0,0 +1,722 @@
+//! Aggregations of the records of a window.
+
+use std::cmp::{max, min};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use faster_rs::FasterRmw;
+
+use crate::trace::{RmwKind, TracedRmw};
+
+/// Aggregates the records `D` of a window into a state that the backend merges with `rmw`.
+///
+/// Merging `lift(a)` into `lift(b)` must give the state of a window that holds `a` and `b`, and
+/// merging anything into `empty()` must give it back unchanged. `combine` must merge two states
+/// the same way, as sliced windows combine the states of their slices outside the backend.
+pub trait Aggregation<D>: 'static {
+    /// The state of a window
+    type State;
+    type Output;
+
+    /// The state of a window without records.
+    fn empty(&self) -> Self::State;
+
+    /// The state of a window that only holds `record`.
+    fn lift(&self, record: &D) -> Self::State;
+
+    /// The state of a window that holds the records of `state` and `other`.
+    fn combine(&self, state: Self::State, other: &Self::State) -> Self::State;
+
+    /// The outputs of the window that ends at `window_end`.
+    fn finish(&self, window_end: usize, state: Self::State) -> Vec<Self::Output>;
+}
+
+/// Counts the records of a window, and emits `(window_end, count)`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Count;
+
+impl<D> Aggregation<D> for Count {
+    type State = usize;
+    type Output = (usize, usize);
+
+    fn empty(&self) -> usize {
+        0
+    }
+
+    fn lift(&self, _record: &D) -> usize {
+        1
+    }
+
+    fn combine(&self, count: usize, other: &usize) -> usize {
+        count + other
+    }
+
+    fn finish(&self, window_end: usize, count: usize) -> Vec<(usize, usize)> {
+        vec![(window_end, count)]
+    }
+}
+
+/// Ranks the `(auction, date_time)` records of a window by auction id, and emits
+/// `(window_end, auction, rank)` for each record in the order of their ranks. Records with the
+/// same auction share a rank, and the next auction's rank counts them all.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Rank;
+
+impl Aggregation<(usize, usize)> for Rank {
+    type State = Vec<(usize, usize)>;
+    type Output = (usize, usize, usize);
+
+    fn empty(&self) -> Vec<(usize, usize)> {
+        Vec::new()
+    }
+
+    fn lift(&self, record: &(usize, usize)) -> Vec<(usize, usize)> {
+        vec![*record]
+    }
+
+    fn combine(
+        &self,
+        mut records: Vec<(usize, usize)>,
+        other: &Vec<(usize, usize)>,
+    ) -> Vec<(usize, usize)> {
+        records.extend_from_slice(other);
+        records
+    }
+
+    fn finish(&self, window_end: usize, mut records: Vec<(usize, usize)>) -> Vec<(usize, usize, usize)> {
+        records.sort_unstable_by(|a, b| a.0.cmp(&b.0)); // Sort auctions by id
+        let mut ranks = Vec::with_capacity(records.len());
+        let mut rank = 1;
+        let mut count = 0;
+        let mut current_auction = records.first().map_or(0, |record| record.0);
+        for (auction, _) in records {
+            if auction != current_auction {
+                // increase rank and update current
+                rank += count;
+                count = 0;
+                current_auction = auction;
+            }
+            count += 1;
+            ranks.push((window_end, auction, rank));
+        }
+        ranks
+    }
+}
+
+/// Sums the values that `F` extracts from the records of a window, and emits `(window_end, sum)`.
+pub struct Sum<F>(pub F);
+
+impl<D, F: Fn(&D) -> usize + 'static> Aggregation<D> for Sum<F> {
+    type State = usize;
+    type Output = (usize, usize);
+
+    fn empty(&self) -> usize {
+        0
+    }
+
+    fn lift(&self, record: &D) -> usize {
+        (self.0)(record)
+    }
+
+    fn combine(&self, sum: usize, other: &usize) -> usize {
+        sum + other
+    }
+
+    fn finish(&self, window_end: usize, sum: usize) -> Vec<(usize, usize)> {
+        vec![(window_end, sum)]
+    }
+}
+
+/// The smallest value of a window, which `rmw` keeps.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct MinValue(pub usize);
+
+impl FasterRmw for MinValue {
+    fn rmw(&self, modification: Self) -> Self {
+        MinValue(min(self.0, modification.0))
+    }
+}
+
+impl TracedRmw for MinValue {
+    const RMW_KIND: RmwKind = RmwKind::Combine;
+}
+
+/// The largest value of a window, which `rmw` keeps.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct MaxValue(pub usize);
+
+impl FasterRmw for MaxValue {
+    fn rmw(&self, modification: Self) -> Self {
+        MaxValue(max(self.0, modification.0))
+    }
+}
+
+impl TracedRmw for MaxValue {
+    const RMW_KIND: RmwKind = RmwKind::Combine;
+}
+
+/// Finds the smallest value that `F` extracts from the records of a window, and emits
+/// `(window_end, min)`.
+pub struct Min<F>(pub F);
+
+impl<D, F: Fn(&D) -> usize + 'static> Aggregation<D> for Min<F> {
+    type State = MinValue;
+    type Output = (usize, usize);
+
+    fn empty(&self) -> MinValue {
+        MinValue(usize::max_value())
+    }
+
+    fn lift(&self, record: &D) -> MinValue {
+        MinValue((self.0)(record))
+    }
+
+    fn combine(&self, state: MinValue, other: &MinValue) -> MinValue {
+        state.rmw(*other)
+    }
+
+    fn finish(&self, window_end: usize, state: MinValue) -> Vec<(usize, usize)> {
+        vec![(window_end, state.0)]
+    }
+}
+
+/// Finds the largest value that `F` extracts from the records of a window, and emits
+/// `(window_end, max)`.
+pub struct Max<F>(pub F);
+
+impl<D, F: Fn(&D) -> usize + 'static> Aggregation<D> for Max<F> {
+    type State = MaxValue;
+    type Output = (usize, usize);
+
+    fn empty(&self) -> MaxValue {
+        MaxValue(0)
+    }
+
+    fn lift(&self, record: &D) -> MaxValue {
+        MaxValue((self.0)(record))
+    }
+
+    fn combine(&self, state: MaxValue, other: &MaxValue) -> MaxValue {
+        state.rmw(*other)
+    }
+
+    fn finish(&self, window_end: usize, state: MaxValue) -> Vec<(usize, usize)> {
+        vec![(window_end, state.0)]
+    }
+}
+
+/// The sum and the number of the values of a window, which `rmw` adds up.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AvgState {
+    pub sum: usize,
+    pub count: usize,
+}
+
+impl FasterRmw for AvgState {
+    fn rmw(&self, modification: Self) -> Self {
+        AvgState {
+            sum: self.sum + modification.sum,
+            count: self.count + modification.count,
+        }
+    }
+}
+
+impl TracedRmw for AvgState {
+    const RMW_KIND: RmwKind = RmwKind::Combine;
+}
+
+/// Averages the values that `F` extracts from the records of a window, and emits
+/// `(window_end, average)` rounded down.
+pub struct Avg<F>(pub F);
+
+impl<D, F: Fn(&D) -> usize + 'static> Aggregation<D> for Avg<F> {
+    type State = AvgState;
+    type Output = (usize, usize);
+
+    fn empty(&self) -> AvgState {
+        AvgState::default()
+    }
+
+    fn lift(&self, record: &D) -> AvgState {
+        AvgState {
+            sum: (self.0)(record),
+            count: 1,
+        }
+    }
+
+    fn combine(&self, state: AvgState, other: &AvgState) -> AvgState {
+        state.rmw(*other)
+    }
+
+    fn finish(&self, window_end: usize, state: AvgState) -> Vec<(usize, usize)> {
+        if state.count == 0 {
+            return Vec::new();
+        }
+        vec![(window_end, state.sum / state.count)]
+    }
+}
+
+/// Counts the distinct values that `F` extracts from the records of a window, and emits
+/// `(window_end, distinct_count)`. The state holds every value of the window.
+pub struct Distinct<F>(pub F);
+
+impl<D, F: Fn(&D) -> usize + 'static> Aggregation<D> for Distinct<F> {
+    type State = Vec<usize>;
+    type Output = (usize, usize);
+
+    fn empty(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    fn lift(&self, record: &D) -> Vec<usize> {
+        vec![(self.0)(record)]
+    }
+
+    fn combine(&self, mut values: Vec<usize>, other: &Vec<usize>) -> Vec<usize> {
+        values.extend_from_slice(other);
+        values
+    }
+
+    fn finish(&self, window_end: usize, mut values: Vec<usize>) -> Vec<(usize, usize)> {
+        values.sort_unstable();
+        values.dedup();
+        vec![(window_end, values.len())]
+    }
+}
+
+/// Finds the median of the values that `F` extracts from the records of a window, and emits
+/// `(window_end, median)`, the lower of the two middle values for an even number of records. The
+/// state holds every value of the window.
+pub struct Median<F>(pub F);
+
+impl<D, F: Fn(&D) -> usize + 'static> Aggregation<D> for Median<F> {
+    type State = Vec<usize>;
+    type Output = (usize, usize);
+
+    fn empty(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    fn lift(&self, record: &D) -> Vec<usize> {
+        vec![(self.0)(record)]
+    }
+
+    fn combine(&self, mut values: Vec<usize>, other: &Vec<usize>) -> Vec<usize> {
+        values.extend_from_slice(other);
+        values
+    }
+
+    fn finish(&self, window_end: usize, mut values: Vec<usize>) -> Vec<(usize, usize)> {
+        if values.is_empty() {
+            return Vec::new();
+        }
+        values.sort_unstable();
+        vec![(window_end, values[(values.len() - 1) / 2])]
+    }
+}
+
+/// Finds the `k` most frequent values that `F` extracts from the records of a window, and emits
+/// `(window_end, value, count)` for each in decreasing order of counts, breaking ties by value.
+/// The state holds every value of the window.
+pub struct TopK<F> {
+    k: usize,
+    value: F,
+}
+
+impl<F> TopK<F> {
+    pub fn new(k: usize, value: F) -> Self {
+        assert!(k > 0, "top-k needs a positive k");
+        TopK { k: k, value: value }
+    }
+}
+
+impl<D, F: Fn(&D) -> usize + 'static> Aggregation<D> for TopK<F> {
+    type State = Vec<usize>;
+    type Output = (usize, usize, usize);
+
+    fn empty(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    fn lift(&self, record: &D) -> Vec<usize> {
+        vec![(self.value)(record)]
+    }
+
+    fn combine(&self, mut values: Vec<usize>, other: &Vec<usize>) -> Vec<usize> {
+        values.extend_from_slice(other);
+        values
+    }
+
+    fn finish(&self, window_end: usize, mut values: Vec<usize>) -> Vec<(usize, usize, usize)> {
+        values.sort_unstable();
+        let mut counts: Vec<(usize, usize)> = Vec::new();
+        for value in values {
+            match counts.last_mut() {
+                Some((last, count)) if *last == value => *count += 1,
+                _ => counts.push((value, 1)),
+            }
+        }
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts
+            .into_iter()
+            .take(self.k)
+            .map(|(value, count)| (window_end, value, count))
+            .collect()
+    }
+}
+
+// Hashes `value` the same way on every worker and backend, after `seed`.
+fn hash(seed: usize, value: usize) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The registers of a HyperLogLog sketch, which `rmw` merges by keeping the largest of each.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HllSketch(pub Vec<u8>);
+
+impl HllSketch {
+    fn merge(&mut self, other: &HllSketch) {
+        for (register, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *register = max(*register, *other);
+        }
+    }
+
+    /// The estimated number of distinct values added to the sketch.
+    pub fn estimate(&self) -> usize {
+        let m = self.0.len() as f64;
+        let alpha = match self.0.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self.0.iter().map(|r| 2f64.powi(-i32::from(*r))).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.0.iter().filter(|r| **r == 0).count();
+        // Linear counting is more accurate for small cardinalities
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+}
+
+impl FasterRmw for HllSketch {
+    fn rmw(&self, modification: Self) -> Self {
+        let mut sketch = self.clone();
+        sketch.merge(&modification);
+        sketch
+    }
+}
+
+impl TracedRmw for HllSketch {
+    const RMW_KIND: RmwKind = RmwKind::Combine;
+}
+
+/// Estimates the number of distinct values that `F` extracts from the records of a window with a
+/// HyperLogLog sketch of `2^precision` one-byte registers, and emits `(window_end, estimate)`.
+/// Every state, including the one a record is lifted to, has the size of the sketch.
+pub struct HyperLogLog<F> {
+    precision: u32,
+    value: F,
+}
+
+impl<F> HyperLogLog<F> {
+    pub fn new(precision: u32, value: F) -> Self {
+        assert!(precision >= 4 && precision <= 16, "HyperLogLog precision must be in [4, 16]");
+        HyperLogLog {
+            precision: precision,
+            value: value,
+        }
+    }
+}
+
+impl<D, F: Fn(&D) -> usize + 'static> Aggregation<D> for HyperLogLog<F> {
+    type State = HllSketch;
+    type Output = (usize, usize);
+
+    fn empty(&self) -> HllSketch {
+        HllSketch(vec![0; 1 << self.precision])
+    }
+
+    fn lift(&self, record: &D) -> HllSketch {
+        let mut sketch = self.empty();
+        let hashed = hash(0, (self.value)(record));
+        // The first `precision` bits pick the register, the rest its rank
+        let register = (hashed >> (64 - self.precision)) as usize;
+        let rank = min((hashed << self.precision).leading_zeros(), 64 - self.precision) + 1;
+        sketch.0[register] = rank as u8;
+        sketch
+    }
+
+    fn combine(&self, mut sketch: HllSketch, other: &HllSketch) -> HllSketch {
+        sketch.merge(other);
+        sketch
+    }
+
+    fn finish(&self, window_end: usize, sketch: HllSketch) -> Vec<(usize, usize)> {
+        vec![(window_end, sketch.estimate())]
+    }
+}
+
+/// A Count-Min sketch of `depth` rows of counters, with the values that have the `k` largest
+/// estimates among the values added to it. `rmw` adds up the counters and keeps the `k` candidates
+/// of both sketches with the largest estimates.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CountMinSketch {
+    depth: usize,
+    k: usize,
+    counters: Vec<u32>,
+    candidates: Vec<usize>,
+}
+
+impl CountMinSketch {
+    fn new(depth: usize, width: usize, k: usize) -> Self {
+        CountMinSketch {
+            depth: depth,
+            k: k,
+            counters: vec![0; depth * width],
+            candidates: Vec::new(),
+        }
+    }
+
+    fn counter(&self, row: usize, value: usize) -> usize {
+        let width = self.counters.len() / self.depth;
+        row * width + hash(row, value) as usize % width
+    }
+
+    /// An upper bound of the number of times `value` was added to the sketch.
+    pub fn estimate(&self, value: usize) -> usize {
+        (0..self.depth)
+            .map(|row| self.counters[self.counter(row, value)] as usize)
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn merge(&mut self, other: &CountMinSketch) {
+        for (counter, other) in self.counters.iter_mut().zip(other.counters.iter()) {
+            *counter += other;
+        }
+        self.candidates.extend_from_slice(&other.candidates);
+        self.candidates.sort_unstable();
+        self.candidates.dedup();
+        let mut estimates: Vec<(usize, usize)> = self
+            .candidates
+            .iter()
+            .map(|value| (self.estimate(*value), *value))
+            .collect();
+        estimates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        self.candidates = estimates.into_iter().take(self.k).map(|(_, value)| value).collect();
+    }
+}
+
+impl FasterRmw for CountMinSketch {
+    fn rmw(&self, modification: Self) -> Self {
+        let mut sketch = self.clone();
+        sketch.merge(&modification);
+        sketch
+    }
+}
+
+impl TracedRmw for CountMinSketch {
+    const RMW_KIND: RmwKind = RmwKind::Combine;
+}
+
+/// Finds the `k` most frequent values that `F` extracts from the records of a window with a
+/// Count-Min sketch of `depth` rows of `width` counters, and emits `(window_end, value, estimate)`
+/// for each in decreasing order of estimates, breaking ties by value. Every state, including the
+/// one a record is lifted to, has the size of the sketch.
+pub struct CountMinTopK<F> {
+    k: usize,
+    depth: usize,
+    width: usize,
+    value: F,
+}
+
+impl<F> CountMinTopK<F> {
+    pub fn new(k: usize, depth: usize, width: usize, value: F) -> Self {
+        assert!(k > 0, "top-k needs a positive k");
+        assert!(depth > 0 && width > 0, "Count-Min sketch dimensions must be positive");
+        CountMinTopK {
+            k: k,
+            depth: depth,
+            width: width,
+            value: value,
+        }
+    }
+}
+
+impl<D, F: Fn(&D) -> usize + 'static> Aggregation<D> for CountMinTopK<F> {
+    type State = CountMinSketch;
+    type Output = (usize, usize, usize);
+
+    fn empty(&self) -> CountMinSketch {
+        CountMinSketch::new(self.depth, self.width, self.k)
+    }
+
+    fn lift(&self, record: &D) -> CountMinSketch {
+        let mut sketch = self.empty();
+        let value = (self.value)(record);
+        for row in 0..self.depth {
+            let counter = sketch.counter(row, value);
+            sketch.counters[counter] = 1;
+        }
+        sketch.candidates.push(value);
+        sketch
+    }
+
+    fn combine(&self, mut sketch: CountMinSketch, other: &CountMinSketch) -> CountMinSketch {
+        sketch.merge(other);
+        sketch
+    }
+
+    fn finish(&self, window_end: usize, sketch: CountMinSketch) -> Vec<(usize, usize, usize)> {
+        sketch
+            .candidates
+            .iter()
+            .map(|value| (window_end, *value, sketch.estimate(*value)))
+            .collect()
+    }
+}
+
+/// Keeps the records of a window in a vector, and aggregates them with `A` when the window fires.
+///
+/// The merge operator of `RocksDBMergeBackend` only appends to vectors, so aggregations whose
+/// states it cannot merge, like minima, maxima and sketches, run on it in this form. Emits the
+/// same outputs as `A`.
+pub struct Folded<A>(pub A);
+
+impl<D: Clone, A: Aggregation<D>> Aggregation<D> for Folded<A> {
+    type State = Vec<D>;
+    type Output = A::Output;
+
+    fn empty(&self) -> Vec<D> {
+        Vec::new()
+    }
+
+    fn lift(&self, record: &D) -> Vec<D> {
+        vec![record.clone()]
+    }
+
+    fn combine(&self, mut records: Vec<D>, other: &Vec<D>) -> Vec<D> {
+        records.extend_from_slice(other);
+        records
+    }
+
+    fn finish(&self, window_end: usize, records: Vec<D>) -> Vec<A::Output> {
+        let Folded(ref aggregation) = *self;
+        let state = records.iter().fold(aggregation.empty(), |state, record| {
+            aggregation.combine(state, &aggregation.lift(record))
+        });
+        aggregation.finish(window_end, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The state of a window that holds `records`, lifted and combined one by one
+    fn window<D, A: Aggregation<D>>(aggregation: &A, records: &[D]) -> A::State {
+        records.iter().fold(aggregation.empty(), |state, record| {
+            aggregation.combine(state, &aggregation.lift(record))
+        })
+    }
+
+    // The outputs of the window that ends at 10 and holds `records`
+    fn outputs<D, A: Aggregation<D>>(aggregation: &A, records: &[D]) -> Vec<A::Output> {
+        aggregation.finish(10, window(aggregation, records))
+    }
+
+    #[test]
+    fn median_picks_lower_middle() {
+        let median = Median(|v: &usize| *v);
+        assert_eq!(outputs(&median, &[4, 1, 3, 2]), vec![(10, 2)]);
+        assert_eq!(outputs(&median, &[5, 1, 3]), vec![(10, 3)]);
+        assert!(outputs::<usize, _>(&median, &[]).is_empty());
+    }
+
+    #[test]
+    fn top_k_breaks_ties_by_value() {
+        let top_k = TopK::new(2, |v: &usize| *v);
+        assert_eq!(outputs(&top_k, &[3, 5, 1, 3, 2, 1, 2]), vec![(10, 1, 2), (10, 2, 2)]);
+        assert_eq!(outputs(&top_k, &[3, 5, 3]), vec![(10, 3, 2), (10, 5, 1)]);
+    }
+
+    #[test]
+    fn folded_matches_its_aggregation() {
+        let values = [4, 1, 3, 2, 5, 1];
+        let min = || Min(|v: &usize| *v);
+        let hll = || HyperLogLog::new(10, |v: &usize| *v);
+        assert_eq!(outputs(&Folded(min()), &values), outputs(&min(), &values));
+        assert_eq!(outputs(&Folded(hll()), &values), outputs(&hll(), &values));
+        // Combining the records of two slices folds all of them
+        let folded = Folded(Max(|v: &usize| *v));
+        let state = folded.combine(window(&folded, &values[..3]), &window(&folded, &values[3..]));
+        assert_eq!(folded.finish(10, state), vec![(10, 5)]);
+    }
+
+    #[test]
+    fn avg_rounds_down_and_skips_empty_windows() {
+        let avg = Avg(|v: &usize| *v);
+        assert_eq!(outputs(&avg, &[1, 2, 4]), vec![(10, 2)]);
+        assert!(outputs::<usize, _>(&avg, &[]).is_empty());
+    }
+
+    #[test]
+    fn hll_estimates_distinct_counts() {
+        let hll = HyperLogLog::new(10, |v: &usize| *v);
+        // Linear counting for small cardinalities, and about 3% standard error for large ones
+        for &distinct in [100, 10_000].iter() {
+            let values: Vec<usize> = (0..distinct).chain(0..distinct).collect();
+            let estimate = window(&hll, &values).estimate();
+            assert!(
+                (distinct * 9 / 10..=distinct * 11 / 10).contains(&estimate),
+                "estimated {} distinct values of {}",
+                estimate,
+                distinct
+            );
+        }
+        // Merging sketches of overlapping values estimates their union
+        let values: Vec<usize> = (0..10_000).collect();
+        let mut sketch = window(&hll, &values[..6_000]);
+        sketch.merge(&window(&hll, &values[4_000..]));
+        let estimate = sketch.estimate();
+        assert!((9_000..=11_000).contains(&estimate), "estimated {} distinct values", estimate);
+    }
+
+    #[test]
+    fn count_min_merge_keeps_heavy_hitters() {
+        let cm = CountMinTopK::new(3, 4, 256, |v: &usize| *v);
+        // 200 values once, and 7, 8 and 9 50, 30 and 20 times
+        let mut values: Vec<usize> = (1_000..1_200).collect();
+        for i in 0..50 {
+            values.push(7);
+            if i < 30 {
+                values.push(8);
+            }
+            if i < 20 {
+                values.push(9);
+            }
+        }
+        let (first, second) = values.split_at(150);
+        let mut sketch = window(&cm, first);
+        sketch.merge(&window(&cm, second));
+
+        // Estimates are upper bounds of the exact counts
+        for value in (1_000..1_200).chain(7..10) {
+            let exact = values.iter().filter(|v| **v == value).count();
+            assert!(sketch.estimate(value) >= exact, "underestimated {}", value);
+        }
+        let top_k = Aggregation::<usize>::finish(&cm, 10, sketch);
+        let heavy: Vec<usize> = top_k.iter().map(|(_, value, _)| *value).collect();
+        assert_eq!(heavy, vec![7, 8, 9]);
+        for ((_, value, estimate), exact) in top_k.iter().zip([50, 30, 20].iter()) {
+            assert!(*estimate < exact + 10, "estimated {} bids of {}", estimate, value);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Count windows over managed state.
+//!
+//! A count window of a key holds its next `size` records and fires as soon as the last of them
+//! arrives, so the state of a key lives as long as it takes the key to receive `size` records.
+//! Popular keys turn their state over quickly, while rare keys keep theirs until the end of the
+//! input.
+
+use faster_rs::{FasterRmw, FasterValue};
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::generic::operator::Operator;
+use timely::dataflow::{Scope, Stream};
+use timely::{Data, ExchangeData};
+
+use crate::queries::windows::{Aggregation, StateLayout};
+use crate::trace::{self, StateOwner, TracedMap, TracedRmw};
+
+/// Aggregates the records of each key in windows of `size` records, and emits `(key, output)`
+/// for the outputs of a window when it fills up, at the time of its last record.
+pub struct CountWindowOperator<A> {
+    name: &'static str,
+    size: usize,
+    aggregation: A,
+    layout: StateLayout,
+}
+
+impl<A> CountWindowOperator<A> {
+    pub fn new(name: &'static str, size: usize, aggregation: A, layout: StateLayout) -> Self {
+        assert!(size > 0, "count window size must be positive");
+        CountWindowOperator {
+            name: name,
+            size: size,
+            aggregation: aggregation,
+            layout: layout,
+        }
+    }
+
+    /// Windows `stream` per `key`.
+    pub fn apply<S, D, K>(self, stream: &Stream<S, D>, key: K) -> Stream<S, (usize, A::Output)>
+    where
+        S: Scope<Timestamp = usize>,
+        D: ExchangeData,
+        A: Aggregation<D>,
+        A::State: FasterValue + FasterRmw + TracedRmw,
+        A::Output: Data,
+        K: Fn(&D) -> usize + Clone + 'static,
+    {
+        let CountWindowOperator {
+            name,
+            size,
+            aggregation,
+            layout,
+        } = self;
+        let exchange_key = key.clone();
+        stream.unary(
+            Exchange::new(move |record: &D| exchange_key(record) as u64),
+            name,
+            move |_capability, _info, state_handle| {
+                let owner = StateOwner::new(name);
+                // key -> number of records in its current window
+                let mut counts: TracedMap<usize, usize> =
+                    TracedMap::new(&owner, "window_counts", state_handle.get_managed_map("window_counts"));
+                // key -> contents of its current window
+                let mut contents: TracedMap<usize, A::State> =
+                    TracedMap::new(&owner, "window_contents", state_handle.get_managed_map("window_contents"));
+                let mut buffer = Vec::new();
+
+                move |input, output| {
+                    input.for_each(|time, data| {
+                        trace::set_time(*time.time());
+                        data.swap(&mut buffer);
+                        let mut session = output.session(&time);
+                        for record in buffer.iter() {
+                            let record_key = key(record);
+                            let count = counts.remove(&record_key).unwrap_or(0) + 1;
+                            if count == 1 && layout == StateLayout::MergeInit {
+                                contents.insert(record_key, aggregation.empty());
+                            }
+                            contents.rmw(record_key, aggregation.lift(record));
+                            if count == size {
+                                let state = contents.remove(&record_key).expect("Must exist");
+                                for result in aggregation.finish(*time.time(), state) {
+                                    session.give((record_key, result));
+                                }
+                            } else {
+                                counts.insert(record_key, count);
+                            }
+                        }
+                    });
+                }
+            },
+        )
+    }
+}
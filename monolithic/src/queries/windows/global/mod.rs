@@ -2,19 +2,63 @@ use std::rc::Rc;
 use timely::dataflow::operators::capture::event::link::EventLink;
 use timely::dataflow::operators::capture::Replay;
 use timely::dataflow::{Scope, Stream};
+use timely::ExchangeData;
 
 use crate::event::{Auction, Bid, Date, Person};
 
+mod window_2;
+mod window_2_avg_counters;
 mod window_2a_rocksdb_count;
 mod window_2a_rocksdb_rank;
 mod window_2b_rocksdb_count;
 mod window_2b_rocksdb_rank;
 mod window_2_faster_count;
 mod window_2_faster_rank;
+mod window_count_count;
+mod window_count_rank;
+mod window_sliced;
+mod window_tumbling_count;
+mod window_tumbling_rank;
 
+pub use self::window_2::window_2;
+pub use self::window_2_avg_counters::window_2_avg_counters;
 pub use self::window_2a_rocksdb_count::window_2a_rocksdb_count;
 pub use self::window_2a_rocksdb_rank::window_2a_rocksdb_rank;
 pub use self::window_2b_rocksdb_count::window_2b_rocksdb_count;
 pub use self::window_2b_rocksdb_rank::window_2b_rocksdb_rank;
 pub use self::window_2_faster_count::window_2_faster_count;
 pub use self::window_2_faster_rank::window_2_faster_rank;
+pub use self::window_count_count::window_count_count;
+pub use self::window_count_rank::window_count_rank;
+pub use self::window_sliced::window_sliced;
+pub use self::window_tumbling_count::window_tumbling_count;
+pub use self::window_tumbling_rank::window_tumbling_rank;
+
+/// The records that `window_2` and `window_sliced` map bids to, which are exchanged by auction
+/// and windowed by the date time of their bid.
+pub trait BidRecord: ExchangeData {
+    fn auction(&self) -> usize;
+    fn date_time(&self) -> usize;
+}
+
+/// `(auction, date_time)`
+impl BidRecord for (usize, usize) {
+    fn auction(&self) -> usize {
+        self.0
+    }
+
+    fn date_time(&self) -> usize {
+        self.1
+    }
+}
+
+/// `(auction, value, date_time)`, where the value is the field of the bid that is aggregated
+impl BidRecord for (usize, usize, usize) {
+    fn auction(&self) -> usize {
+        self.0
+    }
+
+    fn date_time(&self) -> usize {
+        self.2
+    }
+}
@@ -0,0 +1,37 @@
+use faster_rs::{FasterRmw, FasterValue};
+use timely::dataflow::operators::map::Map;
+use timely::dataflow::{Scope, Stream};
+use timely::Data;
+
+use super::BidRecord;
+use crate::event::Bid;
+use crate::queries::windows::{Aggregation, SlidingWindows, StateLayout, WindowOperator};
+use crate::queries::NexmarkInput;
+use crate::trace::TracedRmw;
+
+// Sliding `windows` that merge each bid, mapped to a record by `record`, into every window it
+// belongs to, and aggregate the records of a window with `aggregation`
+pub fn window_2<S, D, A>(
+    input: &NexmarkInput,
+    scope: &mut S,
+    windows: SlidingWindows,
+    layout: StateLayout,
+    record: fn(Bid) -> D,
+    aggregation: A,
+) -> Stream<S, A::Output>
+where
+    S: Scope<Timestamp = usize>,
+    D: BidRecord,
+    A: Aggregation<D>,
+    A::State: FasterValue + FasterRmw + TracedRmw,
+    A::Output: Data,
+{
+    let bids = input.bids(scope).map(record);
+    WindowOperator::new(
+        "Accumulate records",
+        windows,
+        aggregation,
+        layout,
+    )
+    .apply(&bids, |b: &D| b.auction() as u64, |b: &D| b.date_time())
+}
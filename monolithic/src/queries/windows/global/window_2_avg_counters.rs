@@ -0,0 +1,82 @@
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::generic::operator::Operator;
+use timely::dataflow::operators::map::Map;
+use timely::dataflow::{Scope, Stream};
+
+use crate::profile;
+use crate::queries::assign_windows;
+use crate::queries::windows::{Aggregation, Avg, AvgState, SlidingWindows};
+use crate::queries::NexmarkInput;
+use crate::trace::{self, StateOwner, TracedMap};
+
+// Sliding `windows` that average the prices of bids like `window_2` with `Avg`, but keep the sum
+// and the number of the prices of a window in two counters, which the merge operator of
+// `RocksDBMergeBackend2` adds to. The counters of every window are put as the stream passes its
+// start, and combined into the average when the window fires.
+pub fn window_2_avg_counters<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    scope: &mut S,
+    windows: SlidingWindows,
+) -> Stream<S, (usize, usize)> {
+    let SlidingWindows { size, slide } = windows;
+    let avg = Avg(|b: &(usize, usize, usize)| b.1);
+    // Start of the first window whose counters are not initialized yet
+    let mut max_window_seen = 0;
+    let owner = StateOwner::new("Accumulate records");
+    input
+        .bids(scope)
+        .map(|b| (b.auction, b.price, *b.date_time))
+        .unary_notify(
+            Exchange::new(|b: &(usize, usize, usize)| b.0 as u64),
+            "Accumulate records",
+            None,
+            move |input, output, notificator, state_handle| {
+                // window_start_timestamp -> sum and number of the prices of the window
+                let mut sums: TracedMap<usize, usize> = TracedMap::new(
+                    &owner,
+                    "window_sums",
+                    state_handle.get_managed_map("window_sums"),
+                );
+                let mut counts: TracedMap<usize, usize> = TracedMap::new(
+                    &owner,
+                    "window_counts",
+                    state_handle.get_managed_map("window_counts"),
+                );
+                let mut buffer = Vec::new();
+                input.for_each(|time, data| {
+                    trace::set_time(*time.time());
+                    data.swap(&mut buffer);
+                    // The end timestamp of the slide the current epoch corresponds to
+                    let next_window = ((time.time() / slide) + 1) * slide;
+                    if max_window_seen < next_window {
+                        for window_start in (max_window_seen..next_window).step_by(slide) {
+                            sums.insert(window_start, 0);
+                            counts.insert(window_start, 0);
+                        }
+                        max_window_seen = next_window;
+                    }
+                    for &(_, price, date_time) in buffer.iter() {
+                        for window_start in assign_windows(date_time, slide, size) {
+                            // Notify at end of this window
+                            notificator.notify_at(time.delayed(&(window_start + size)));
+                            sums.rmw(window_start, price);
+                            counts.rmw(window_start, 1);
+                        }
+                    }
+                });
+
+                notificator.for_each(|cap, _, _| {
+                    trace::set_time(*cap.time());
+                    profile::notified(&owner, *cap.time());
+                    let window_start = *cap.time() - size;
+                    let state = AvgState {
+                        sum: sums.remove(&window_start).expect("Must exist"),
+                        count: counts.remove(&window_start).expect("Must exist"),
+                    };
+                    let outputs =
+                        Aggregation::<(usize, usize, usize)>::finish(&avg, *cap.time(), state);
+                    output.session(&cap).give_iterator(outputs.into_iter());
+                });
+            },
+        )
+}
@@ -1,9 +1,8 @@
-use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::map::Map;
 use timely::dataflow::{Scope, Stream};
 
-use crate::queries::{NexmarkInput, NexmarkTimer, assign_windows};
-use timely::dataflow::operators::generic::operator::Operator;
-use timely::dataflow::operators::map::Map;
+use crate::queries::windows::{Rank, SlidingWindows, StateLayout, WindowOperator};
+use crate::queries::{NexmarkInput, NexmarkTimer};
 
 pub fn window_2_faster_rank<S: Scope<Timestamp = usize>>(
     input: &NexmarkInput,
@@ -12,62 +11,14 @@ pub fn window_2_faster_rank<S: Scope<Timestamp = usize>>(
     window_slice_count: usize,
     window_slide_ns: usize,
 ) -> Stream<S, (usize, usize, usize)> {
-    input
+    let bids = input
         .bids(scope)
-        .map(move |b| {
-            (
-                b.auction,
-                *b.date_time
-            )
-        })
-        .unary_notify(
-            Exchange::new(|b: &(usize, _)| b.0 as u64),
-            "Accumulate records",
-            None,
-            move |input, output, notificator, state_handle| {
-                let window_size = window_slice_count * window_slide_ns;
-                // window_start_timestamp -> window_contents
-                let mut window_buckets = state_handle.get_managed_map("window_buckets");
-                let mut buffer = Vec::new();
-                input.for_each(|time, data| {
-                    data.swap(&mut buffer);
-                    for record in buffer.iter() {
-                        let windows = assign_windows(record.1, window_slide_ns, window_size);
-                        for win in windows {
-                            // Notify at end of this window
-                            notificator.notify_at(time.delayed(&(win + window_size)));
-                            // println!("Asking notification for end of window: {:?}", win + window_size);
-                            window_buckets.rmw(win, vec![*record]);
-                            // println!("Appending record with timestamp {} to window with start timestamp {}.", record.1, win);
-                        }
-                    }
-                });
-
-                notificator.for_each(|cap, _, _| {
-                    // println!("Firing and cleaning window with start timestamp {}.", cap.time() - window_size);
-                    let records = window_buckets.remove(&(cap.time() - window_size)).expect("Must exist");
-                    let mut auctions = Vec::new();
-                    for record in records.iter() {
-                        auctions.push(record.0);
-                    }
-                    // println!("*** Window start: {}, contents {:?}.", cap.time() - window_size, records);
-                    auctions.sort_unstable();
-                    let mut rank = 1;
-                    let mut count = 0;
-                    let mut current_record = auctions[0];
-                    for auction in &auctions {
-                        // output (timestamp, auctionID, rank)
-                        if *auction != current_record {
-                            // increase rank and update current
-                            rank+=count;
-                            count = 0;
-                            current_record = *auction;
-                        }
-                        count+=1;
-                        output.session(&cap).give((*cap.time(), *auction, rank));
-                        // println!("*** Start of window: {:?}, Auction: {:?}, Rank: {:?}", cap.time(), auction, rank);
-                    }
-                });
-            },
-        )
+        .map(|b| (b.auction, *b.date_time));
+    WindowOperator::new(
+        "Accumulate records",
+        SlidingWindows::new(window_slice_count * window_slide_ns, window_slide_ns),
+        Rank,
+        StateLayout::Rmw,
+    )
+    .apply(&bids, |b: &(usize, usize)| b.0 as u64, |b: &(usize, usize)| b.1)
 }
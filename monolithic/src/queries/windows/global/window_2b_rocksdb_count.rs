@@ -1,9 +1,8 @@
-use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::map::Map;
 use timely::dataflow::{Scope, Stream};
 
-use crate::queries::{NexmarkInput, NexmarkTimer, assign_windows};
-use timely::dataflow::operators::generic::operator::Operator;
-use timely::dataflow::operators::map::Map;
+use crate::queries::windows::{Count, SlidingWindows, StateLayout, WindowOperator};
+use crate::queries::{NexmarkInput, NexmarkTimer};
 
 // 2nd window implementation using merge
 pub fn window_2b_rocksdb_count<S: Scope<Timestamp = usize>>(
@@ -13,57 +12,14 @@ pub fn window_2b_rocksdb_count<S: Scope<Timestamp = usize>>(
     window_slice_count: usize,
     window_slide_ns: usize,
 ) -> Stream<S, (usize, usize)> {
-
-    let mut max_window_seen = 0;
-
-    input
+    let bids = input
         .bids(scope)
-        .map(move |b| {
-            (
-                b.auction,
-                *b.date_time
-            )
-        })
-        .unary_notify(
-            Exchange::new(|b: &(usize, _)| b.0 as u64),
-            "Accumulate records",
-            None,
-            move |input, output, notificator, state_handle| {
-                let window_size = window_slice_count * window_slide_ns;
-                // window_start_timestamp -> window_contents
-                let mut window_buckets = state_handle.get_managed_map("window_buckets");
-                let mut buffer = Vec::new();
-                input.for_each(|time, data| {
-                    data.swap(&mut buffer);
-                    // The end timestamp of the slide the current epoch corresponds to
-                    let slide = ((time.time() / window_slide_ns) + 1) * window_slide_ns;
-                    if max_window_seen < slide {
-                        for window_start in (max_window_seen..slide).step_by(window_slide_ns) {
-                            // Merging in RocksDB needs a first 'put' operation to work properly
-                            // println!("First PUT operation for window start: {:?}", window_start);
-                            window_buckets.insert(window_start, 0);  // Initialize window state
-                        }
-                        max_window_seen = slide;
-                    }
-                    for record in buffer.iter() {
-                        let windows = assign_windows(record.1, window_slide_ns, window_size);
-                        for win in windows {
-                            // Notify at end of this window
-                            notificator.notify_at(time.delayed(&(win + window_size)));
-                            // println!("Asking notification for end of window: {:?}", win + window_size);
-                            window_buckets.rmw(win, 1);
-                            // println!("Appending record with timestamp {} to window with start timestamp {}.", record.1, win);
-                        }
-                    }
-                });
-
-                notificator.for_each(|cap, _, _| {
-                    // println!("Firing and cleaning window with start timestamp {}.", cap.time() - window_size);
-                    let start_timestamp = cap.time() - window_size;
-                    let count = window_buckets.remove(&start_timestamp).expect("Must exist");
-                    // println!("*** Window start: {}, count {}.", cap.time() - window_size, count);
-                    output.session(&cap).give((*cap.time(), count));
-                });
-            },
-        )
+        .map(|b| (b.auction, *b.date_time));
+    WindowOperator::new(
+        "Accumulate records",
+        SlidingWindows::new(window_slice_count * window_slide_ns, window_slide_ns),
+        Count,
+        StateLayout::MergeInit,
+    )
+    .apply(&bids, |b: &(usize, usize)| b.0 as u64, |b: &(usize, usize)| b.1)
 }
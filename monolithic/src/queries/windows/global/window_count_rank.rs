@@ -0,0 +1,19 @@
+use timely::dataflow::operators::map::Map;
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::windows::{Rank, CountWindowOperator, StateLayout};
+use crate::queries::{NexmarkInput, NexmarkTimer};
+
+// Count windows of the last `window_count` bids of each bidder, as `(auction, bidder)`
+pub fn window_count_rank<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+    window_count: usize,
+) -> Stream<S, (usize, (usize, usize, usize))> {
+    let bids = input
+        .bids(scope)
+        .map(|b| (b.auction, b.bidder));
+    CountWindowOperator::new("Accumulate records", window_count, Rank, StateLayout::Rmw)
+        .apply(&bids, |b: &(usize, usize)| b.1)
+}
@@ -0,0 +1,24 @@
+use timely::dataflow::operators::map::Map;
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::windows::{Rank, StateLayout, TumblingWindows, WindowOperator};
+use crate::queries::{NexmarkInput, NexmarkTimer};
+
+// Tumbling windows of `window_size_ns`, where every record belongs to exactly one window
+pub fn window_tumbling_rank<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+    window_size_ns: usize,
+) -> Stream<S, (usize, usize, usize)> {
+    let bids = input
+        .bids(scope)
+        .map(|b| (b.auction, *b.date_time));
+    WindowOperator::new(
+        "Accumulate records",
+        TumblingWindows::new(window_size_ns),
+        Rank,
+        StateLayout::Rmw,
+    )
+    .apply(&bids, |b: &(usize, usize)| b.0 as u64, |b: &(usize, usize)| b.1)
+}
@@ -0,0 +1,5 @@
+mod session_b_count;
+mod session_count;
+
+pub use self::session_b_count::session_b_count;
+pub use self::session_count::session_count;
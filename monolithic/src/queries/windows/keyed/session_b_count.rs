@@ -0,0 +1,23 @@
+use timely::dataflow::operators::map::Map;
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::windows::{Count, Folded, SessionWindowOperator, StateLayout};
+use crate::queries::{NexmarkInput, NexmarkTimer};
+
+// Session windows of bidders using merge, after a first put of the state of a session. The bids
+// of a session are appended and counted when it closes, so that, with the keys appended to
+// timeouts, every read-modify-write appends to a vector, as the merge operator of
+// `RocksDBMergeBackend` does.
+pub fn session_b_count<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    nt: NexmarkTimer,
+    scope: &mut S,
+    session_gap_ns: usize,
+) -> Stream<S, (usize, (usize, usize))> {
+    let bids = input
+        .bids(scope)
+        .map(move |b| (b.bidder, nt.from_nexmark_time(b.date_time)));
+    let count = Folded(Count);
+    SessionWindowOperator::new("Accumulate sessions", session_gap_ns, count, StateLayout::MergeInit)
+        .apply(&bids, |b: &(usize, usize)| b.0, |b: &(usize, usize)| b.1)
+}
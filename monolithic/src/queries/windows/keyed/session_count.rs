@@ -0,0 +1,19 @@
+use timely::dataflow::operators::map::Map;
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::windows::{Count, SessionWindowOperator, StateLayout};
+use crate::queries::{NexmarkInput, NexmarkTimer};
+
+// Session windows of bidders using rmw, which creates the state of a session
+pub fn session_count<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    nt: NexmarkTimer,
+    scope: &mut S,
+    session_gap_ns: usize,
+) -> Stream<S, (usize, (usize, usize))> {
+    let bids = input
+        .bids(scope)
+        .map(move |b| (b.bidder, nt.from_nexmark_time(b.date_time)));
+    SessionWindowOperator::new("Accumulate sessions", session_gap_ns, Count, StateLayout::Rmw)
+        .apply(&bids, |b: &(usize, usize)| b.0, |b: &(usize, usize)| b.1)
+}
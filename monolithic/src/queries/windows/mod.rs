@@ -5,4 +5,21 @@ use timely::dataflow::{Scope, Stream};
 
 use crate::event::{Auction, Bid, Date, Person};
 
+mod aggregation;
+mod count;
+mod operator;
+mod session;
+mod sliced;
 pub mod global;
+pub mod keyed;
+
+pub use self::aggregation::{
+    Aggregation, Avg, AvgState, Count, CountMinSketch, CountMinTopK, Distinct, Folded, HllSketch,
+    HyperLogLog, Max, MaxValue, Median, Min, MinValue, Rank, Sum, TopK,
+};
+pub use self::count::CountWindowOperator;
+pub use self::operator::{
+    SlidingWindows, StateLayout, TumblingWindows, WindowAssigner, WindowOperator,
+};
+pub use self::session::SessionWindowOperator;
+pub use self::sliced::SlicedWindowOperator;
@@ -0,0 +1,199 @@
+//! A window operator over managed state, generic in how records are assigned to windows, how a
+//! window is aggregated and how its state is laid out in the backend.
+
+use faster_rs::{FasterRmw, FasterValue};
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::generic::operator::Operator;
+use timely::dataflow::{Scope, Stream};
+use timely::{Data, ExchangeData};
+
+use crate::profile;
+use crate::queries::assign_windows;
+use crate::queries::windows::Aggregation;
+use crate::trace::{self, StateOwner, TracedMap, TracedRmw};
+
+/// Assigns records to the windows they belong to, by event time.
+pub trait WindowAssigner: Copy + 'static {
+    /// Appends the starts of the windows that contain `time` to `windows`.
+    fn assign(&self, time: usize, windows: &mut Vec<usize>);
+
+    /// The end of the window that starts at `start`.
+    fn end(&self, start: usize) -> usize;
+
+    /// The start of the window that ends at `end`.
+    fn start(&self, end: usize) -> usize;
+
+    /// The distance between the starts of consecutive windows.
+    fn slide(&self) -> usize;
+}
+
+/// Windows of `size` nanoseconds that start every `slide` nanoseconds.
+#[derive(Copy, Clone, Debug)]
+pub struct SlidingWindows {
+    pub size: usize,
+    pub slide: usize,
+}
+
+impl SlidingWindows {
+    pub fn new(size: usize, slide: usize) -> Self {
+        assert!(slide > 0 && size >= slide, "window slide must be positive and at most the window size");
+        SlidingWindows {
+            size: size,
+            slide: slide,
+        }
+    }
+}
+
+impl WindowAssigner for SlidingWindows {
+    fn assign(&self, time: usize, windows: &mut Vec<usize>) {
+        windows.extend(assign_windows(time, self.slide, self.size))
+    }
+
+    fn end(&self, start: usize) -> usize {
+        start + self.size
+    }
+
+    fn start(&self, end: usize) -> usize {
+        end - self.size
+    }
+
+    fn slide(&self) -> usize {
+        self.slide
+    }
+}
+
+/// Windows of `size` nanoseconds that do not overlap, so that every record belongs to exactly one
+/// window.
+#[derive(Copy, Clone, Debug)]
+pub struct TumblingWindows {
+    pub size: usize,
+}
+
+impl TumblingWindows {
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0, "window size must be positive");
+        TumblingWindows { size: size }
+    }
+}
+
+impl WindowAssigner for TumblingWindows {
+    fn assign(&self, time: usize, windows: &mut Vec<usize>) {
+        windows.push(time - time % self.size)
+    }
+
+    fn end(&self, start: usize) -> usize {
+        start + self.size
+    }
+
+    fn start(&self, end: usize) -> usize {
+        end - self.size
+    }
+
+    fn slide(&self) -> usize {
+        self.size
+    }
+}
+
+/// How the state of a window is kept in the backend.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StateLayout {
+    /// Records are merged into their windows with `rmw`, which creates a window's state on its
+    /// first record. Used with FASTER and RocksDB put + merge.
+    Rmw,
+    /// The state of every window is put with the empty aggregate as the stream passes its start,
+    /// before records are merged into it. RocksDB's merge operator needs a first put to work
+    /// properly.
+    MergeInit,
+}
+
+/// Aggregates the records of each window in a `ManagedMap` from window start to window state,
+/// and emits the outputs of a window when its end is complete.
+pub struct WindowOperator<W, A> {
+    name: &'static str,
+    assigner: W,
+    aggregation: A,
+    layout: StateLayout,
+}
+
+impl<W: WindowAssigner, A> WindowOperator<W, A> {
+    pub fn new(name: &'static str, assigner: W, aggregation: A, layout: StateLayout) -> Self {
+        WindowOperator {
+            name: name,
+            assigner: assigner,
+            aggregation: aggregation,
+            layout: layout,
+        }
+    }
+
+    /// Windows `stream` by the event time `event_time` of each record, after exchanging records
+    /// by `key`.
+    pub fn apply<S, D, K, T>(
+        self,
+        stream: &Stream<S, D>,
+        key: K,
+        event_time: T,
+    ) -> Stream<S, A::Output>
+    where
+        S: Scope<Timestamp = usize>,
+        D: ExchangeData,
+        A: Aggregation<D>,
+        A::State: FasterValue + FasterRmw + TracedRmw,
+        A::Output: Data,
+        K: Fn(&D) -> u64 + 'static,
+        T: Fn(&D) -> usize + 'static,
+    {
+        let WindowOperator {
+            name,
+            assigner,
+            aggregation,
+            layout,
+        } = self;
+        // Start of the first window whose state is not initialized yet, with `MergeInit`
+        let mut max_window_seen = 0;
+        let owner = StateOwner::new(name);
+        stream.unary_notify(
+            Exchange::new(key),
+            name,
+            None,
+            move |input, output, notificator, state_handle| {
+                // window_start_timestamp -> window_contents
+                let mut window_buckets: TracedMap<usize, A::State> =
+                    TracedMap::new(&owner, "window_buckets", state_handle.get_managed_map("window_buckets"));
+                let mut buffer = Vec::new();
+                let mut windows = Vec::new();
+                input.for_each(|time, data| {
+                    trace::set_time(*time.time());
+                    data.swap(&mut buffer);
+                    if layout == StateLayout::MergeInit {
+                        // The end timestamp of the slide the current epoch corresponds to
+                        let slide = ((time.time() / assigner.slide()) + 1) * assigner.slide();
+                        if max_window_seen < slide {
+                            for window_start in (max_window_seen..slide).step_by(assigner.slide()) {
+                                window_buckets.insert(window_start, aggregation.empty());
+                            }
+                            max_window_seen = slide;
+                        }
+                    }
+                    for record in buffer.iter() {
+                        assigner.assign(event_time(record), &mut windows);
+                        for win in windows.drain(..) {
+                            // Notify at end of this window
+                            notificator.notify_at(time.delayed(&assigner.end(win)));
+                            window_buckets.rmw(win, aggregation.lift(record));
+                        }
+                    }
+                });
+
+                notificator.for_each(|cap, _, _| {
+                    trace::set_time(*cap.time());
+                    profile::notified(&owner, *cap.time());
+                    let start_timestamp = assigner.start(*cap.time());
+                    let state = window_buckets.remove(&start_timestamp).expect("Must exist");
+                    output
+                        .session(&cap)
+                        .give_iterator(aggregation.finish(*cap.time(), state).into_iter());
+                });
+            },
+        )
+    }
+}
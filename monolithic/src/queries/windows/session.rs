@@ -0,0 +1,157 @@
+//! Session windows over managed state.
+//!
+//! A session of a key holds records that are less than `gap` nanoseconds apart, and closes when
+//! no record arrives for `gap` nanoseconds. A record opens the window `[time, time + gap)` and
+//! merges with every window of its key it overlaps, so a late record can bridge two sessions.
+//!
+//! The operator keeps three `ManagedMap`s: the windows of each key as sorted `(start, end)`
+//! pairs, the aggregate of each window under `(key, start)`, and the keys with a window that may
+//! close at each notification time. Merging windows moves the aggregates of the merged windows to
+//! the key of the window that covers them. Keys are appended to their notification time with a
+//! read-modify-write for every record, and deduplicated when the notification fires.
+
+use std::cmp::{max, min};
+
+use faster_rs::{FasterRmw, FasterValue};
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::generic::operator::Operator;
+use timely::dataflow::{Scope, Stream};
+use timely::{Data, ExchangeData};
+
+use crate::profile;
+use crate::queries::windows::{Aggregation, StateLayout};
+use crate::trace::{self, StateOwner, TracedMap, TracedRmw};
+
+/// Aggregates the records of each session of each key, and emits `(key, output)` for the
+/// outputs of a session when it closes.
+pub struct SessionWindowOperator<A> {
+    name: &'static str,
+    gap: usize,
+    aggregation: A,
+    layout: StateLayout,
+}
+
+impl<A> SessionWindowOperator<A> {
+    pub fn new(name: &'static str, gap: usize, aggregation: A, layout: StateLayout) -> Self {
+        assert!(gap > 0, "session gap must be positive");
+        SessionWindowOperator {
+            name: name,
+            gap: gap,
+            aggregation: aggregation,
+            layout: layout,
+        }
+    }
+
+    /// Windows `stream` per `key` by the time `event_time` of each record, which must be
+    /// comparable to the timestamps of the stream.
+    pub fn apply<S, D, K, T>(
+        self,
+        stream: &Stream<S, D>,
+        key: K,
+        event_time: T,
+    ) -> Stream<S, (usize, A::Output)>
+    where
+        S: Scope<Timestamp = usize>,
+        D: ExchangeData,
+        A: Aggregation<D>,
+        A::State: FasterValue + FasterRmw + TracedRmw,
+        A::Output: Data,
+        K: Fn(&D) -> usize + Clone + 'static,
+        T: Fn(&D) -> usize + 'static,
+    {
+        let SessionWindowOperator {
+            name,
+            gap,
+            aggregation,
+            layout,
+        } = self;
+        let exchange_key = key.clone();
+        let owner = StateOwner::new(name);
+        stream.unary_notify(
+            Exchange::new(move |record: &D| exchange_key(record) as u64),
+            name,
+            None,
+            move |input, output, notificator, state_handle| {
+                // key -> sorted (start, end) of its open windows
+                let mut windows: TracedMap<usize, Vec<(usize, usize)>> =
+                    TracedMap::new(&owner, "session_windows", state_handle.get_managed_map("session_windows"));
+                // (key, window start) -> window contents
+                let mut contents: TracedMap<(usize, usize), A::State> =
+                    TracedMap::new(&owner, "session_contents", state_handle.get_managed_map("session_contents"));
+                // notification time -> keys with a window that may close at that time
+                let mut timeouts: TracedMap<usize, Vec<usize>> =
+                    TracedMap::new(&owner, "session_timeouts", state_handle.get_managed_map("session_timeouts"));
+                let mut buffer = Vec::new();
+                input.for_each(|time, data| {
+                    trace::set_time(*time.time());
+                    data.swap(&mut buffer);
+                    for record in buffer.iter() {
+                        let record_key = key(record);
+                        let record_time = event_time(record);
+                        let mut key_windows = windows.remove(&record_key).unwrap_or_else(Vec::new);
+                        // Merge the window of the record with the windows it overlaps
+                        let (mut start, mut end) = (record_time, record_time + gap);
+                        let mut merged = Vec::new();
+                        key_windows.retain(|&(s, e)| {
+                            if s < record_time + gap && record_time < e {
+                                merged.push(s);
+                                start = min(start, s);
+                                end = max(end, e);
+                                false
+                            } else {
+                                true
+                            }
+                        });
+                        if !merged.contains(&start) && layout == StateLayout::MergeInit {
+                            contents.insert((record_key, start), aggregation.empty());
+                        }
+                        for s in merged.into_iter().filter(|s| *s != start) {
+                            let state = contents.remove(&(record_key, s)).expect("Must exist");
+                            contents.rmw((record_key, start), state);
+                        }
+                        contents.rmw((record_key, start), aggregation.lift(record));
+                        let position = key_windows.binary_search(&(start, end)).unwrap_or_else(|p| p);
+                        key_windows.insert(position, (start, end));
+                        windows.insert(record_key, key_windows);
+
+                        // A late record might close its window before the current time
+                        let timeout = max(end, *time.time());
+                        notificator.notify_at(time.delayed(&timeout));
+                        timeouts.rmw(timeout, vec![record_key]);
+                    }
+                });
+
+                notificator.for_each(|cap, _, _| {
+                    trace::set_time(*cap.time());
+                    profile::notified(&owner, *cap.time());
+                    if let Some(mut keys) = timeouts.remove(cap.time()) {
+                        // Keys are listed once for every record that moved a window to this time
+                        keys.sort_unstable();
+                        keys.dedup();
+                        let mut session = output.session(&cap);
+                        for key in keys {
+                            // The windows of the key might have been extended by later records
+                            let mut key_windows = match windows.remove(&key) {
+                                Some(key_windows) => key_windows,
+                                None => continue,
+                            };
+                            key_windows.retain(|&(start, end)| {
+                                if end > *cap.time() {
+                                    return true;
+                                }
+                                let state = contents.remove(&(key, start)).expect("Must exist");
+                                for result in aggregation.finish(end, state) {
+                                    session.give((key, result));
+                                }
+                                false
+                            });
+                            if !key_windows.is_empty() {
+                                windows.insert(key, key_windows);
+                            }
+                        }
+                    }
+                });
+            },
+        )
+    }
+}
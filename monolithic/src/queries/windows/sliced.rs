@@ -0,0 +1,126 @@
+//! Sliding windows over managed state that store each slide once.
+//!
+//! Instead of merging a record into each of the `size / slide` windows it belongs to, the
+//! operator merges it into the slice of its slide only, and combines the slices of a window with
+//! `Aggregation::combine` when the window fires. Each record costs one `rmw`, and each window
+//! `size / slide` reads. A slice is removed when the last window it belongs to fires, which is
+//! the window that starts at the slice, as the windows that fire later start after it.
+
+use faster_rs::{FasterRmw, FasterValue};
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::generic::operator::Operator;
+use timely::dataflow::{Scope, Stream};
+use timely::{Data, ExchangeData};
+
+use crate::profile;
+use crate::queries::windows::{Aggregation, SlidingWindows, StateLayout};
+use crate::trace::{self, StateOwner, TracedMap, TracedRmw};
+
+/// Aggregates the records of each slide in a `ManagedMap` from slice start to slice state, and
+/// emits the outputs of a window when its end is complete, with the same semantics as a
+/// `WindowOperator` with `SlidingWindows`.
+pub struct SlicedWindowOperator<A> {
+    name: &'static str,
+    windows: SlidingWindows,
+    aggregation: A,
+    layout: StateLayout,
+}
+
+impl<A> SlicedWindowOperator<A> {
+    pub fn new(
+        name: &'static str,
+        windows: SlidingWindows,
+        aggregation: A,
+        layout: StateLayout,
+    ) -> Self {
+        assert_eq!(windows.size % windows.slide, 0, "window size must be a multiple of its slide");
+        SlicedWindowOperator {
+            name: name,
+            windows: windows,
+            aggregation: aggregation,
+            layout: layout,
+        }
+    }
+
+    /// Windows `stream` by the event time `event_time` of each record, after exchanging records
+    /// by `key`.
+    pub fn apply<S, D, K, T>(
+        self,
+        stream: &Stream<S, D>,
+        key: K,
+        event_time: T,
+    ) -> Stream<S, A::Output>
+    where
+        S: Scope<Timestamp = usize>,
+        D: ExchangeData,
+        A: Aggregation<D>,
+        A::State: FasterValue + FasterRmw + TracedRmw,
+        A::Output: Data,
+        K: Fn(&D) -> u64 + 'static,
+        T: Fn(&D) -> usize + 'static,
+    {
+        let SlicedWindowOperator {
+            name,
+            windows,
+            aggregation,
+            layout,
+        } = self;
+        let SlidingWindows { size, slide } = windows;
+        // Start of the first slice whose state is not initialized yet, with `MergeInit`
+        let mut max_slice_seen = 0;
+        let owner = StateOwner::new(name);
+        stream.unary_notify(
+            Exchange::new(key),
+            name,
+            None,
+            move |input, output, notificator, state_handle| {
+                // slice_start_timestamp -> slice_contents
+                let mut slices: TracedMap<usize, A::State> =
+                    TracedMap::new(&owner, "window_slices", state_handle.get_managed_map("window_slices"));
+                let mut buffer = Vec::new();
+                input.for_each(|time, data| {
+                    trace::set_time(*time.time());
+                    data.swap(&mut buffer);
+                    if layout == StateLayout::MergeInit {
+                        // The end timestamp of the slide the current epoch corresponds to
+                        let next_slice = ((time.time() / slide) + 1) * slide;
+                        if max_slice_seen < next_slice {
+                            for slice_start in (max_slice_seen..next_slice).step_by(slide) {
+                                slices.insert(slice_start, aggregation.empty());
+                            }
+                            max_slice_seen = next_slice;
+                        }
+                    }
+                    for record in buffer.iter() {
+                        let slice_start = event_time(record) / slide * slide;
+                        // Notify at the end of every window that contains the slice
+                        for window_end in (slice_start + slide..=slice_start + size).step_by(slide) {
+                            if window_end >= size {
+                                notificator.notify_at(time.delayed(&window_end));
+                            }
+                        }
+                        slices.rmw(slice_start, aggregation.lift(record));
+                    }
+                });
+
+                notificator.for_each(|cap, _, _| {
+                    trace::set_time(*cap.time());
+                    profile::notified(&owner, *cap.time());
+                    let window_start = *cap.time() - size;
+                    // No later window contains the first slice of this one
+                    let mut state = slices
+                        .remove(&window_start)
+                        .unwrap_or_else(|| aggregation.empty());
+                    for slice_start in (window_start + slide..*cap.time()).step_by(slide) {
+                        if let Some(slice) = slices.get(&slice_start) {
+                            state = aggregation.combine(state, &slice);
+                        }
+                    }
+                    output
+                        .session(&cap)
+                        .give_iterator(aggregation.finish(*cap.time(), state).into_iter());
+                });
+            },
+        )
+    }
+}
@@ -0,0 +1,154 @@
+//! Machine-readable results of a run.
+//!
+//! With `--results-output`, the driver writes a [`Results`] object as JSON: the metadata of the
+//! run, the latency CCDF and the percentiles of the latency timeline of each query, the
+//! resources used by the process over time, and the profile of each operator if enabled.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hdrhist::HDRHist;
+
+use crate::profile::OperatorProfile;
+use crate::queries::registry::{Backend, QueryParams};
+use crate::tools::{ccdf_quantile, read_backend_config, ResourceSample};
+
+#[derive(Serialize, Debug)]
+pub struct Results {
+    pub metadata: Metadata,
+    pub queries: Vec<QueryResults>,
+    pub resources: Vec<ResourceSample>,
+    /// Time breakdown of each operator, with `--profile-operators`
+    pub profile: Vec<OperatorProfile>,
+    /// Throughput search of each query, with `--search-throughput`
+    pub search: Vec<SearchResults>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Metadata {
+    pub rate: u64,
+    pub next_rate: u64,
+    pub duration_ns: u64,
+    /// Number of workers of all processes
+    pub workers: usize,
+    /// Number of workers of this process, whose latencies are reported
+    pub local_workers: usize,
+    pub params: QueryParams,
+    /// Settings of the event generator, as passed to `Config`
+    pub generator_settings: BTreeMap<String, String>,
+    /// Contents of the configuration files of the backends, by file name
+    pub backend_config: BTreeMap<String, BTreeMap<String, String>>,
+    pub git_revision: Option<String>,
+    pub command_line: Vec<String>,
+    /// Milliseconds since the Unix epoch
+    pub start_time_ms: u64,
+    pub end_time_ms: u64,
+}
+
+/// Latencies of a query, measured with its own probe.
+#[derive(Serialize, Debug)]
+pub struct QueryResults {
+    pub name: String,
+    pub backend: String,
+    pub latency_ccdf: Vec<CcdfPoint>,
+    pub timeline: Vec<TimelinePoint>,
+}
+
+/// The trials of the search of the highest rate a query sustains on its backend.
+#[derive(Serialize, Debug)]
+pub struct SearchResults {
+    pub name: String,
+    pub backend: String,
+    pub latency_bound_ns: u64,
+    pub trials: Vec<SearchTrial>,
+    /// The highest sustainable rate of the trials, or 0 if none was sustainable
+    pub max_sustainable_rate: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchTrial {
+    pub rate: u64,
+    pub p99: u64,
+    /// The highest p99 latency of the intervals of the latency timeline
+    pub max_interval_p99: u64,
+    pub sustainable: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CcdfPoint {
+    pub value: u64,
+    pub prob: f64,
+    pub count: u64,
+}
+
+/// Latency percentiles of an interval of the run, in nanoseconds.
+#[derive(Serialize, Debug)]
+pub struct TimelinePoint {
+    pub time: u64,
+    pub samples: u64,
+    pub p25: u64,
+    pub p50: u64,
+    pub p75: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl Results {
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let f = File::create(path)?;
+        serde_json::to_writer_pretty(f, self).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+}
+
+pub fn ccdf_points(hist: &HDRHist) -> Vec<CcdfPoint> {
+    hist.ccdf()
+        .map(|(value, prob, count)| CcdfPoint {
+            value: value,
+            prob: prob,
+            count: count,
+        })
+        .collect()
+}
+
+impl TimelinePoint {
+    pub fn new(time: u64, samples: u64, hist: &HDRHist) -> Self {
+        let quantile = |q| ccdf_quantile(hist.ccdf(), q).unwrap_or(0);
+        TimelinePoint {
+            time: time,
+            samples: samples,
+            p25: quantile(0.25),
+            p50: quantile(0.5),
+            p75: quantile(0.75),
+            p99: quantile(0.99),
+            max: quantile(1.0),
+        }
+    }
+}
+
+/// Reads the configuration files of `backends` that exist in the working directory.
+pub fn backend_config(backends: &[Backend]) -> BTreeMap<String, BTreeMap<String, String>> {
+    backends
+        .iter()
+        .filter_map(|backend| backend.config_file())
+        .filter(|file| Path::new(file).exists())
+        .map(|file| {
+            let config = read_backend_config(Path::new(file));
+            (file.to_string(), config.into_iter().collect())
+        })
+        .collect()
+}
+
+/// Returns the git revision the driver was built from, if it was built in a git repository.
+pub fn git_revision() -> Option<String> {
+    option_env!("NEXMARK_GIT_REVISION").map(String::from)
+}
+
+pub fn unix_time_ms() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before the Unix epoch");
+    now.as_secs() * 1_000 + u64::from(now.subsec_millis())
+}
@@ -0,0 +1,465 @@
+//! Statistics of managed state and of the state backends, sampled during a run.
+//!
+//! When enabled with [`enable`] before the dataflows are built, the wrappers in [`crate::trace`]
+//! count the operations, hits, misses and bytes of every state in shared counters, which the
+//! resource monitor samples together with the statistics RocksDB writes to its `LOG` files.
+//!
+//! The backends of the dataflows are owned by Timely. A backend that registers its FASTER or
+//! RocksDB instance with [`register_faster`] or [`register_rocksdb`] while a dataflow is built has
+//! the internals of the instance, like the FASTER log size or the RocksDB memtable size, sampled
+//! with its state directory. The state directories of other backends are attributed to the
+//! dataflow whose build created them, so that the statistics of each backend instance are
+//! reported with the states it holds. Users of their own `FasterKv` keep its statistics in a
+//! [`FasterStats`] as well.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use faster_rs::{status, FasterKv};
+use rocksdb::DB;
+
+use crate::tools::temporary_dirs;
+use crate::trace::{Op, ABSENT};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STATES: Mutex<Vec<Arc<StateStats>>> = Mutex::new(Vec::new());
+// The dataflow whose backend created each state directory
+static BACKEND_DIRS: Mutex<BTreeMap<PathBuf, String>> = Mutex::new(BTreeMap::new());
+// Held while a dataflow is built, so that the directories created meanwhile belong to it
+static BUILD: Mutex<()> = Mutex::new(());
+// The instances registered by backends, by their state directory
+static INSTANCES: Mutex<BTreeMap<PathBuf, Instance>> = Mutex::new(BTreeMap::new());
+
+/// Enables the collection of state statistics.
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Counters of the accesses of a worker to a state.
+#[derive(Debug, Default)]
+pub struct StateStats {
+    dataflow: String,
+    operator: String,
+    name: String,
+    ops: [AtomicU64; 6],
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+thread_local! {
+    // The counters of the states of this worker, as some operators get their state repeatedly
+    static WORKER_STATES: RefCell<HashMap<(String, String, String), Arc<StateStats>>> =
+        RefCell::new(HashMap::new());
+    // The dataflow this worker is building, which the instances registered meanwhile belong to
+    static BUILDING: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Returns the counters of a state of the calling worker, if statistics are enabled.
+pub fn register(dataflow: &str, operator: &str, name: &str) -> Option<Arc<StateStats>> {
+    if !is_enabled() {
+        return None;
+    }
+    let stats = WORKER_STATES.with(|states| {
+        states
+            .borrow_mut()
+            .entry((dataflow.to_string(), operator.to_string(), name.to_string()))
+            .or_insert_with(|| {
+                let stats = Arc::new(StateStats {
+                    dataflow: dataflow.to_string(),
+                    operator: operator.to_string(),
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+                STATES.lock().unwrap().push(stats.clone());
+                stats
+            })
+            .clone()
+    });
+    Some(stats)
+}
+
+impl StateStats {
+    /// Counts an access with the given value size, or `ABSENT` if it found no value.
+    #[inline]
+    pub fn access(&self, op: Op, value_size: u32) {
+        self.ops[op as usize].fetch_add(1, Ordering::Relaxed);
+        if value_size == ABSENT {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        match op {
+            Op::Get | Op::Remove | Op::Take => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                self.bytes_read.fetch_add(value_size as u64, Ordering::Relaxed);
+            }
+            Op::Insert | Op::Rmw | Op::Set => {
+                self.bytes_written.fetch_add(value_size as u64, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Totals of the accesses of all workers of the process to a state.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StateSample {
+    /// The dataflow of the state, named after its query
+    pub dataflow: String,
+    pub operator: String,
+    pub name: String,
+    /// Number of operations by name
+    pub ops: BTreeMap<String, u64>,
+    /// Reads and removes that found a value
+    pub hits: u64,
+    /// Reads and removes that found no value
+    pub misses: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+/// Sums the counters of every state over the workers.
+pub fn snapshot() -> Vec<StateSample> {
+    let mut samples: BTreeMap<(String, String, String), StateSample> = BTreeMap::new();
+    for stats in STATES.lock().unwrap().iter() {
+        let key = (stats.dataflow.clone(), stats.operator.clone(), stats.name.clone());
+        let sample = samples
+            .entry(key)
+            .or_insert_with(|| StateSample {
+                dataflow: stats.dataflow.clone(),
+                operator: stats.operator.clone(),
+                name: stats.name.clone(),
+                ..Default::default()
+            });
+        for op in Op::ALL.iter() {
+            *sample.ops.entry(op.name().to_string()).or_insert(0) +=
+                stats.ops[*op as usize].load(Ordering::Relaxed);
+        }
+        sample.hits += stats.hits.load(Ordering::Relaxed);
+        sample.misses += stats.misses.load(Ordering::Relaxed);
+        sample.bytes_read += stats.bytes_read.load(Ordering::Relaxed);
+        sample.bytes_written += stats.bytes_written.load(Ordering::Relaxed);
+    }
+    samples.into_iter().map(|(_, sample)| sample).collect()
+}
+
+/// The build of a dataflow, which the state directories created in the working directory until
+/// it finishes are attributed to, if statistics are enabled.
+///
+/// Builds are serialized across the workers of the process, so that no other dataflow creates
+/// directories meanwhile.
+pub struct DataflowBuild {
+    dataflow: String,
+    before: Vec<PathBuf>,
+    lock: Option<MutexGuard<'static, ()>>,
+}
+
+pub fn start_build(dataflow: &str) -> DataflowBuild {
+    let lock = if is_enabled() { Some(BUILD.lock().unwrap()) } else { None };
+    if lock.is_some() {
+        BUILDING.with(|building| *building.borrow_mut() = Some(dataflow.to_string()));
+    }
+    DataflowBuild {
+        dataflow: dataflow.to_string(),
+        before: if lock.is_some() { temporary_dirs() } else { Vec::new() },
+        lock: lock,
+    }
+}
+
+impl DataflowBuild {
+    pub fn finish(self) {
+        if self.lock.is_none() {
+            return;
+        }
+        BUILDING.with(|building| *building.borrow_mut() = None);
+        let mut dirs = BACKEND_DIRS.lock().unwrap();
+        for dir in temporary_dirs() {
+            if !self.before.contains(&dir) {
+                dirs.insert(canonical(&dir), self.dataflow.clone());
+            }
+        }
+    }
+}
+
+// Directories are compared by their canonical paths, as backends may open them by other paths
+fn canonical(dir: &Path) -> PathBuf {
+    fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
+}
+
+/// The FASTER or RocksDB instance of a backend.
+enum Instance {
+    Faster {
+        faster: Arc<FasterKv>,
+        table_size: u64,
+    },
+    RocksDB(Arc<DB>),
+}
+
+/// Registers the FASTER instance that a backend keeps in `dir`, with a hash index of
+/// `table_size` buckets, so that its log size is sampled. Does nothing unless statistics are
+/// enabled.
+pub fn register_faster(dir: &Path, faster: Arc<FasterKv>, table_size: u64) {
+    register(
+        dir,
+        Instance::Faster {
+            faster: faster,
+            table_size: table_size,
+        },
+    );
+}
+
+/// Registers the RocksDB instance of a backend, so that its memtables and statistics are
+/// sampled. Does nothing unless statistics are enabled.
+pub fn register_rocksdb(db: Arc<DB>) {
+    let dir = db.path().to_path_buf();
+    register(&dir, Instance::RocksDB(db));
+}
+
+fn register(dir: &Path, instance: Instance) {
+    if !is_enabled() {
+        return;
+    }
+    let dir = canonical(dir);
+    if let Some(dataflow) = BUILDING.with(|building| building.borrow().clone()) {
+        BACKEND_DIRS.lock().unwrap().insert(dir.clone(), dataflow);
+    }
+    INSTANCES.lock().unwrap().insert(dir, instance);
+}
+
+/// Statistics of the backend instance in a state directory.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BackendSample {
+    pub directory: String,
+    /// The dataflow whose backend created the directory, if it was created while it was built
+    pub dataflow: Option<String>,
+    /// The states of the dataflow, as `<operator>/<state>`
+    pub states: Vec<String>,
+    /// On-disk size of the directory, which for FASTER is the size of the spilled log
+    pub size_bytes: u64,
+    /// Statistics of the FASTER instance, if its backend registered it
+    pub faster: Option<FasterStats>,
+    /// Statistics of the RocksDB instance, if the directory holds one
+    pub rocksdb: Option<RocksDBStats>,
+}
+
+/// Samples the backend instances in `dirs` and the registered instances, with the states of
+/// their dataflows in `states`.
+pub fn backend_samples(dirs: &[PathBuf], states: &[StateSample]) -> Vec<BackendSample> {
+    let backend_dirs = BACKEND_DIRS.lock().unwrap();
+    let instances = INSTANCES.lock().unwrap();
+    let mut dirs: Vec<(PathBuf, PathBuf)> =
+        dirs.iter().map(|dir| (dir.clone(), canonical(dir))).collect();
+    for dir in instances.keys() {
+        if !dirs.iter().any(|&(_, ref canonical)| canonical == dir) {
+            dirs.push((dir.clone(), dir.clone()));
+        }
+    }
+    dirs.into_iter()
+        .map(|(dir, canonical)| {
+            let dataflow = backend_dirs.get(&canonical).cloned();
+            let instance = instances.get(&canonical);
+            BackendSample {
+                directory: dir.to_string_lossy().into_owned(),
+                states: states
+                    .iter()
+                    .filter(|state| Some(&state.dataflow) == dataflow.as_ref())
+                    .map(|state| format!("{}/{}", state.operator, state.name))
+                    .collect(),
+                dataflow: dataflow,
+                size_bytes: crate::tools::dir_size(&dir),
+                faster: match instance {
+                    Some(&Instance::Faster {
+                        ref faster,
+                        table_size,
+                    }) => Some(FasterStats {
+                        log_size_bytes: faster.size(),
+                        ..FasterStats::new(table_size)
+                    }),
+                    _ => None,
+                },
+                rocksdb: match instance {
+                    Some(&Instance::RocksDB(ref db)) => Some(rocksdb_instance_stats(db)),
+                    _ => rocksdb_stats(&dir),
+                },
+            }
+        })
+        .collect()
+}
+
+/// Statistics of a FASTER instance, kept up to date by its user with [`FasterStats::count`] and
+/// `maybe_refresh_faster`. The operations that went pending are not counted for instances
+/// registered with [`register_faster`], whose log size is sampled instead.
+///
+/// FASTER only reports the overflow buckets of its hash index in the distribution it prints with
+/// `FasterKv::dump_distribution`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FasterStats {
+    /// Bytes of the hybrid log, in memory and on disk, as of the last refresh
+    pub log_size_bytes: u64,
+    /// Bytes of the hash index, which has `tablesize` buckets of 64 bytes
+    pub index_size_bytes: u64,
+    /// Operations that went pending and were not completed yet
+    pub pending_ops: u64,
+    /// Operations that went pending since the instance was created
+    pub total_pending_ops: u64,
+}
+
+impl FasterStats {
+    pub fn new(table_size: u64) -> Self {
+        FasterStats {
+            index_size_bytes: table_size * 64,
+            ..Default::default()
+        }
+    }
+
+    /// Counts an operation that returned `status`.
+    #[inline]
+    pub fn count(&mut self, status: u8) {
+        if status == status::PENDING {
+            self.pending_ops += 1;
+            self.total_pending_ops += 1;
+        }
+    }
+
+    /// Records that the pending operations of `faster` were completed.
+    pub fn completed(&mut self, faster: &FasterKv) {
+        self.pending_ops = 0;
+        self.log_size_bytes = faster.size();
+    }
+}
+
+/// Statistics of a RocksDB instance, from the last dump in its `LOG` file, or read from the
+/// instance itself if it is open.
+///
+/// RocksDB dumps them every `stats_dump_period_sec` (10 minutes by default). Block cache hits and
+/// misses are only known if the instance collects statistics, which RocksDB does not by default.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RocksDBStats {
+    /// Bytes written by the application
+    pub ingest_bytes: u64,
+    pub compaction_read_bytes: u64,
+    pub compaction_write_bytes: u64,
+    pub compaction_seconds: f64,
+    /// Bytes written by compactions per byte written by the application
+    pub write_amplification: f64,
+    pub stall_seconds: f64,
+    pub block_cache_hits: Option<u64>,
+    pub block_cache_misses: Option<u64>,
+    pub block_cache_hit_rate: Option<f64>,
+    /// Bytes of the active and immutable memtables, if the instance is open
+    pub memtable_bytes: Option<u64>,
+    /// Estimated number of keys, if the instance is open
+    pub estimated_keys: Option<u64>,
+}
+
+const GB: f64 = (1u64 << 30) as f64;
+
+// Parses the number before `unit` in a line like "Cumulative compaction: 1.20 GB write, ...".
+fn number_before(line: &str, unit: &str) -> Option<f64> {
+    let end = line.find(unit)?;
+    line[..end].split_whitespace().last()?.parse().ok()
+}
+
+// Parses a duration like "00:01:2.345".
+fn hms_seconds(hms: &str) -> Option<f64> {
+    let parts: Vec<&str> = hms.split(':').collect();
+    match parts.as_slice() {
+        [h, m, s] => {
+            let hours = h.parse::<f64>().ok()?;
+            let minutes = m.parse::<f64>().ok()?;
+            Some(hours * 3600.0 + minutes * 60.0 + s.parse::<f64>().ok()?)
+        }
+        _ => None,
+    }
+}
+
+// Parses the count of a statistic like "rocksdb.block.cache.hit COUNT : 123".
+fn statistic_count(line: &str, name: &str) -> Option<u64> {
+    let rest = line.trim().strip_prefix(name)?.trim();
+    rest.strip_prefix("COUNT :")?.trim().parse().ok()
+}
+
+/// Reads the statistics of the RocksDB instance in `dir`, if it has a `LOG` file.
+pub fn rocksdb_stats(dir: &Path) -> Option<RocksDBStats> {
+    let log = fs::read_to_string(dir.join("LOG")).ok()?;
+    let mut stats = RocksDBStats::default();
+    for line in log.lines() {
+        let line = line.trim();
+        if line.starts_with("Cumulative writes:") {
+            if let Some(gb) = line.split("ingest:").nth(1).and_then(|s| number_before(s, "GB")) {
+                stats.ingest_bytes = (gb * GB) as u64;
+            }
+        } else if line.starts_with("Cumulative compaction:") {
+            if let Some(gb) = number_before(line, "GB write") {
+                stats.compaction_write_bytes = (gb * GB) as u64;
+            }
+            if let Some(gb) = number_before(line, "GB read") {
+                stats.compaction_read_bytes = (gb * GB) as u64;
+            }
+            if let Some(seconds) = number_before(line, "seconds") {
+                stats.compaction_seconds = seconds;
+            }
+        } else if line.starts_with("Cumulative stall:") {
+            let hms = line["Cumulative stall:".len()..].split_whitespace().next();
+            if let Some(seconds) = hms.and_then(hms_seconds) {
+                stats.stall_seconds = seconds;
+            }
+        } else {
+            stats.read_statistic(line);
+        }
+    }
+    if stats.ingest_bytes > 0 {
+        stats.write_amplification = stats.compaction_write_bytes as f64 / stats.ingest_bytes as f64;
+    }
+    stats.update_hit_rate();
+    Some(stats)
+}
+
+/// Reads the statistics of the open RocksDB instance `db`: those of its `LOG` file, the size of
+/// its memtables, its estimated number of keys and, if it collects statistics, its current block
+/// cache hits and misses.
+pub fn rocksdb_instance_stats(db: &DB) -> RocksDBStats {
+    let mut stats = rocksdb_stats(db.path()).unwrap_or_default();
+    let property = |name: &str| {
+        db.property_value(name)
+            .ok()
+            .and_then(|value| value.and_then(|v| v.parse::<u64>().ok()))
+    };
+    stats.memtable_bytes = property("rocksdb.cur-size-all-mem-tables");
+    stats.estimated_keys = property("rocksdb.estimate-num-keys");
+    // Empty unless the instance collects statistics
+    if let Ok(Some(statistics)) = db.property_value("rocksdb.options-statistics") {
+        for line in statistics.lines() {
+            stats.read_statistic(line);
+        }
+        stats.update_hit_rate();
+    }
+    stats
+}
+
+impl RocksDBStats {
+    // Reads the block cache hits or misses from a line of the dump of the statistics.
+    fn read_statistic(&mut self, line: &str) {
+        if let Some(hits) = statistic_count(line, "rocksdb.block.cache.hit") {
+            self.block_cache_hits = Some(hits);
+        } else if let Some(misses) = statistic_count(line, "rocksdb.block.cache.miss") {
+            self.block_cache_misses = Some(misses);
+        }
+    }
+
+    fn update_hit_rate(&mut self) {
+        if let (Some(hits), Some(misses)) = (self.block_cache_hits, self.block_cache_misses) {
+            if hits + misses > 0 {
+                self.block_cache_hit_rate = Some(hits as f64 / (hits + misses) as f64);
+            }
+        }
+    }
+}
@@ -1,36 +1,345 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
 use streaming_harness::util::ToNanos;
 
-pub fn statm_reporter() -> ::std::sync::Arc<::std::sync::atomic::AtomicBool> {
-    // Read and report RSS every 100ms
-    let statm_reporter_running = ::std::sync::Arc::new(::std::sync::atomic::AtomicBool::new(true));
-    {
-        let statm_reporter_running = statm_reporter_running.clone();
-        ::std::thread::spawn(move || {
-            use std::io::Read;
-            let timer = ::std::time::Instant::now();
-            let mut iteration = 0;
-            while statm_reporter_running.load(::std::sync::atomic::Ordering::SeqCst) {
-                let mut stat_s = String::new();
-                let mut statm_f =
-                    ::std::fs::File::open("/proc/self/statm").expect("can't open /proc/self/statm");
-                statm_f
-                    .read_to_string(&mut stat_s)
-                    .expect("can't read /proc/self/statm");
-                let pages: u64 = stat_s
-                    .split_whitespace()
-                    .nth(1)
-                    .expect("wooo")
-                    .parse()
-                    .expect("not a number");
-                let rss = pages * 4096;
-
-                let elapsed_ns = timer.elapsed().to_nanos();
-                println!("statm_RSS\t{}\t{}", elapsed_ns, rss);
-                #[allow(deprecated)]
-                ::std::thread::sleep_ms(500 - (elapsed_ns / 1_000_000 - iteration * 500) as u32);
-                iteration += 1;
+use crate::stats::{self, BackendSample, StateSample};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::{Scope, Stream};
+use timely::ExchangeData;
+
+/// Resources used by the process at some point of a run.
+#[derive(Clone, Debug, Serialize)]
+pub struct ResourceSample {
+    pub elapsed_ns: u64,
+    pub rss_bytes: u64,
+    /// CPU time spent in user mode, from `/proc/self/stat`
+    pub user_cpu_ns: u64,
+    /// CPU time spent in kernel mode, from `/proc/self/stat`
+    pub system_cpu_ns: u64,
+    /// Bytes read from storage, from `/proc/self/io`
+    pub read_bytes: u64,
+    /// Bytes written to storage, from `/proc/self/io`
+    pub write_bytes: u64,
+    /// On-disk size of each state directory
+    pub state_dir_bytes: BTreeMap<String, u64>,
+    /// Accesses to each state so far, if state statistics are enabled
+    pub states: Vec<StateSample>,
+    /// Statistics of the backend instance in each state directory, with the dataflow and the
+    /// states it holds, if state statistics are enabled
+    pub backends: Vec<BackendSample>,
+}
+
+/// Samples the resources used by the process every 500ms on a background thread.
+pub struct ResourceMonitor {
+    running: Arc<AtomicBool>,
+    samples: Arc<Mutex<Vec<ResourceSample>>>,
+    thread: thread::JoinHandle<()>,
+}
+
+const SAMPLE_INTERVAL_NS: u64 = 500_000_000;
+
+// Returns the value of a system configuration variable, such as the clock ticks per second of
+// the times in /proc/self/stat (USER_HZ) or the size of the pages counted in /proc/self/statm.
+fn sysconf(name: libc::c_int) -> u64 {
+    let value = unsafe { libc::sysconf(name) };
+    assert!(value > 0, "sysconf({}) failed", name);
+    value as u64
+}
+
+impl ResourceMonitor {
+    /// Starts sampling, printing the RSS as `statm_RSS` lines if `print_rss` is set.
+    ///
+    /// The size of each of `state_dirs` is sampled as well. Without state directories, the
+    /// temporary directories `.tmp*` that backends create in the working directory are sampled.
+    pub fn start(print_rss: bool, state_dirs: Vec<PathBuf>) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let thread = {
+            let running = running.clone();
+            let samples = samples.clone();
+            thread::spawn(move || {
+                let timer = Instant::now();
+                let mut iteration = 0;
+                while running.load(Ordering::SeqCst) {
+                    let elapsed_ns = timer.elapsed().to_nanos();
+                    let sample = ResourceSample::read(elapsed_ns, &state_dirs);
+                    if print_rss {
+                        println!("statm_RSS\t{}\t{}", elapsed_ns, sample.rss_bytes);
+                    }
+                    samples.lock().unwrap().push(sample);
+                    iteration += 1;
+                    let next_ns = iteration * SAMPLE_INTERVAL_NS;
+                    let elapsed_ns = timer.elapsed().to_nanos();
+                    if next_ns > elapsed_ns {
+                        thread::sleep(Duration::from_nanos(next_ns - elapsed_ns));
+                    }
+                }
+                // A last sample when the run is over
+                let sample = ResourceSample::read(timer.elapsed().to_nanos(), &state_dirs);
+                samples.lock().unwrap().push(sample);
+            })
+        };
+        ResourceMonitor {
+            running: running,
+            samples: samples,
+            thread: thread,
+        }
+    }
+
+    /// Stops sampling and returns the samples.
+    pub fn stop(self) -> Vec<ResourceSample> {
+        self.running.store(false, Ordering::SeqCst);
+        self.thread.join().expect("resource monitor panicked");
+        let samples = self.samples.lock().unwrap();
+        samples.clone()
+    }
+}
+
+impl ResourceSample {
+    fn read(elapsed_ns: u64, state_dirs: &[PathBuf]) -> Self {
+        let clock_ticks_per_second = sysconf(libc::_SC_CLK_TCK);
+        let page_size = sysconf(libc::_SC_PAGESIZE);
+
+        let statm = fs::read_to_string("/proc/self/statm").expect("can't read /proc/self/statm");
+        let pages: u64 = statm
+            .split_whitespace()
+            .nth(1)
+            .expect("wooo")
+            .parse()
+            .expect("not a number");
+
+        // The command name in parentheses may contain spaces, fields are counted after it
+        let stat = fs::read_to_string("/proc/self/stat").expect("can't read /proc/self/stat");
+        let stat_fields: Vec<&str> = stat[stat.rfind(')').unwrap_or(0) + 1..]
+            .split_whitespace()
+            .collect();
+        let ticks_ns = |field: usize| {
+            stat_fields
+                .get(field)
+                .and_then(|ticks| ticks.parse::<u64>().ok())
+                .unwrap_or(0)
+                * (1_000_000_000 / clock_ticks_per_second)
+        };
+
+        // Not readable in some containers
+        let io = fs::read_to_string("/proc/self/io").unwrap_or_default();
+        let io_bytes = |key: &str| {
+            io.lines()
+                .filter_map(|line| {
+                    let mut parts = line.splitn(2, ':');
+                    match (parts.next(), parts.next()) {
+                        (Some(k), Some(v)) if k == key => v.trim().parse::<u64>().ok(),
+                        _ => None,
+                    }
+                })
+                .next()
+                .unwrap_or(0)
+        };
+
+        let state_dirs = if state_dirs.is_empty() {
+            temporary_dirs()
+        } else {
+            state_dirs.to_vec()
+        };
+
+        let states = if stats::is_enabled() { stats::snapshot() } else { Vec::new() };
+        let backends = if stats::is_enabled() {
+            stats::backend_samples(&state_dirs, &states)
+        } else {
+            Vec::new()
+        };
+
+        ResourceSample {
+            elapsed_ns: elapsed_ns,
+            rss_bytes: pages * page_size,
+            // utime and stime are fields 14 and 15, the 12th and 13th after the command name
+            user_cpu_ns: ticks_ns(11),
+            system_cpu_ns: ticks_ns(12),
+            read_bytes: io_bytes("read_bytes"),
+            write_bytes: io_bytes("write_bytes"),
+            state_dir_bytes: state_dirs
+                .iter()
+                .map(|dir| (dir.to_string_lossy().into_owned(), dir_size(dir)))
+                .collect(),
+            states: states,
+            backends: backends,
+        }
+    }
+}
+
+pub(crate) fn temporary_dirs() -> Vec<PathBuf> {
+    fs::read_dir(".")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with(".tmp"))
+                .filter(|entry| entry.file_type().map_or(false, |t| t.is_dir()))
+                .map(|entry| entry.path())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the total size of the files in `path`, ignoring files that disappear meanwhile.
+pub fn dir_size(path: &Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(ref metadata) if metadata.is_dir() => fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| dir_size(&entry.path()))
+                    .sum()
+            })
+            .unwrap_or(0),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+/// Reads a state backend configuration file such as `faster.config`.
+///
+/// Each non-empty line that does not start with `#` is a `key = value` pair.
+pub fn read_backend_config(path: &::std::path::Path) -> ::std::collections::HashMap<String, String> {
+    let contents = ::std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Cannot read {:?}: {}", path, e));
+    contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => Some((key.trim().to_string(), value.trim().to_string())),
+                _ => None,
             }
-        });
+        })
+        .collect()
+}
+
+/// Returns the `q`-quantile of a histogram, given its CCDF as `(value, probability, count)`.
+pub fn ccdf_quantile<I: Iterator<Item = (u64, f64, u64)>>(ccdf: I, q: f64) -> Option<u64> {
+    let mut last = None;
+    for (value, prob, _count) in ccdf {
+        if prob <= 1.0 - q {
+            return Some(value);
+        }
+        last = Some(value);
     }
-    statm_reporter_running
+    last
+}
+
+/// Searches the highest rate for which `sustainable` holds, trying at most `steps` rates.
+///
+/// Starts at `rate` and doubles it until a rate is not sustainable, then bisects between the
+/// highest sustainable and the lowest unsustainable rate. Returns 0 if no rate was sustainable.
+pub fn search_max_rate<F: FnMut(u64) -> bool>(rate: u64, steps: usize, mut sustainable: F) -> u64 {
+    let mut low = 0;
+    let mut high: Option<u64> = None;
+    let mut rate = rate;
+    for _ in 0..steps {
+        if sustainable(rate) {
+            low = rate;
+        } else {
+            high = Some(rate);
+        }
+        rate = match high {
+            Some(high) => low + (high - low) / 2,
+            None => rate * 2,
+        };
+        if rate == low {
+            break;
+        }
+    }
+    low
+}
+
+/// The number of processes that Timely runs with the arguments `args`.
+///
+/// Reads `-n`/`--processes` like Timely does. Runs with a `-h`/`--hostfile` count as runs of at
+/// least two processes, as the hostfile lists the addresses of several.
+pub fn timely_processes(args: &[String]) -> usize {
+    let mut processes = 1;
+    let mut hostfile = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = if arg == "-n" || arg == "--processes" {
+            args.next().map(|s| s.as_str())
+        } else if arg.starts_with("--processes=") {
+            Some(&arg["--processes=".len()..])
+        } else if arg.starts_with("-n") {
+            Some(&arg["-n".len()..])
+        } else if arg == "-h" || arg == "--hostfile" || arg.starts_with("--hostfile=") {
+            hostfile = true;
+            None
+        } else {
+            None
+        };
+        if let Some(value) = value {
+            processes = value
+                .parse()
+                .unwrap_or_else(|_| panic!("couldn't parse number of processes '{}'", value));
+        }
+    }
+    if hostfile {
+        std::cmp::max(processes, 2)
+    } else {
+        processes
+    }
+}
+
+/// Checks that `output` has the same records as `correct` at every timestamp.
+///
+/// Panics on the first timestamp at which the records differ.
+pub fn verify<S: Scope, T: ExchangeData + Ord + ::std::fmt::Debug>(
+    correct: &Stream<S, T>,
+    output: &Stream<S, T>,
+) -> Stream<S, ()> {
+    use std::collections::HashMap;
+    use timely::dataflow::channels::pact::Exchange;
+    let mut in1_pending: HashMap<_, Vec<_>> = Default::default();
+    let mut in2_pending: HashMap<_, Vec<_>> = Default::default();
+    let mut data_buffer: Vec<T> = Vec::new();
+    correct.binary_notify(
+        &output,
+        Exchange::new(|_| 0),
+        Exchange::new(|_| 0),
+        "Verify",
+        vec![],
+        move |in1, in2, _out, not, _state_handle| {
+            in1.for_each(|time, data| {
+                data.swap(&mut data_buffer);
+                in1_pending
+                    .entry(time.time().clone())
+                    .or_insert_with(Default::default)
+                    .extend(data_buffer.drain(..));
+                not.notify_at(time.retain());
+            });
+            in2.for_each(|time, data| {
+                data.swap(&mut data_buffer);
+                in2_pending
+                    .entry(time.time().clone())
+                    .or_insert_with(Default::default)
+                    .extend(data_buffer.drain(..));
+                not.notify_at(time.retain());
+            });
+            not.for_each(|time, _, _| {
+                let mut v1 = in1_pending.remove(time.time()).unwrap_or_default();
+                let mut v2 = in2_pending.remove(time.time()).unwrap_or_default();
+                v1.sort();
+                v2.sort();
+                assert_eq!(v1.len(), v2.len());
+                let i1 = v1.iter();
+                let i2 = v2.iter();
+                for (a, b) in i1.zip(i2) {
+                    assert_eq!(a, b, " at {:?}", time.time());
+                }
+            })
+        },
+    )
 }
@@ -0,0 +1,650 @@
+//! Opt-in tracing of accesses to managed state.
+//!
+//! Queries wrap the primitives they get from `state_handle` into [`TracedMap`], [`TracedValue`]
+//! and [`TracedCount`], owned by a [`StateOwner`] that names the operator and the dataflow it was
+//! built in. When tracing is enabled on a worker with [`init`], every access is appended to the
+//! worker's trace file `state_trace_<worker>.bin`; otherwise the wrappers only forward calls to
+//! the managed primitive. The wrappers also count accesses in the state
+//! statistics of [`crate::stats`] and time them in the profile of [`crate::profile`], if enabled.
+//!
+//! A trace file starts with [`MAGIC`] followed by records. Each record starts with a tag byte:
+//!
+//! * `TAG_STATE`: `id: u32`, `kind: u8`, then the dataflow name, the operator name and the state
+//!   name, each as a `u16` length followed by UTF-8 bytes. Written once, before the first access
+//!   to the state.
+//! * `TAG_ACCESS`: `seq: u64`, `time: u64`, `id: u32`, `op: u8`, `key_hash: u64`,
+//!   `value_size: u32`, `rmw: u8`.
+//!
+//! All integers are little endian. `seq` counts the accesses of the worker and orders them.
+//! `time` is the dataflow time of the batch or notification the operator was processing when it
+//! made the access, as set with [`set_time`], and 0 for accesses made while building the
+//! dataflow. `value_size` is the serialized size of the value read, written or removed, or of the
+//! modification of a read-modify-write, or [`ABSENT`] if a read or remove found no value. `rmw`
+//! is the [`RmwKind`] of read-modify-writes, and `RmwKind::None` for other operations. Accesses
+//! to values and counts have key hash 0.
+
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+use timely::state::primitives::{ManagedCount, ManagedMap, ManagedValue};
+
+use crate::profile::{self, StateTimes};
+use crate::stats::{self, StateStats};
+
+pub const MAGIC: &[u8; 8] = b"NXSTATE3";
+pub const TAG_STATE: u8 = 0;
+pub const TAG_ACCESS: u8 = 1;
+/// Value size of reads and removes that found no value.
+pub const ABSENT: u32 = u32::max_value();
+
+/// The kind of managed primitive.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum StateKind {
+    Map = 0,
+    Value = 1,
+    Count = 2,
+}
+
+impl StateKind {
+    pub fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(StateKind::Map),
+            1 => Some(StateKind::Value),
+            2 => Some(StateKind::Count),
+            _ => None,
+        }
+    }
+}
+
+/// How a read-modify-write merges its modification into the value of its key.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum RmwKind {
+    /// The access is not a read-modify-write
+    None = 0,
+    /// Appends the modification, so that the value grows by its size, like vectors
+    Append = 1,
+    /// Combines the modification and the value into a value of about the same size, like counts,
+    /// sums, minima and sketches
+    Combine = 2,
+}
+
+impl RmwKind {
+    pub fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(RmwKind::None),
+            1 => Some(RmwKind::Append),
+            2 => Some(RmwKind::Combine),
+            _ => None,
+        }
+    }
+}
+
+/// The `RmwKind` of the `FasterRmw` implementation of a state value, which the traces of its
+/// read-modify-writes record.
+pub trait TracedRmw {
+    const RMW_KIND: RmwKind;
+}
+
+impl<T> TracedRmw for Vec<T> {
+    const RMW_KIND: RmwKind = RmwKind::Append;
+}
+
+impl TracedRmw for usize {
+    const RMW_KIND: RmwKind = RmwKind::Combine;
+}
+
+/// A state operation.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum Op {
+    Get = 0,
+    Insert = 1,
+    Remove = 2,
+    Rmw = 3,
+    Set = 4,
+    Take = 5,
+}
+
+impl Op {
+    pub const ALL: [Op; 6] = [Op::Get, Op::Insert, Op::Remove, Op::Rmw, Op::Set, Op::Take];
+
+    pub fn from_u8(op: u8) -> Option<Self> {
+        Op::ALL.get(op as usize).cloned()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::Get => "get",
+            Op::Insert => "insert",
+            Op::Remove => "remove",
+            Op::Rmw => "rmw",
+            Op::Set => "set",
+            Op::Take => "take",
+        }
+    }
+
+    /// Whether the operation writes state.
+    pub fn is_write(&self) -> bool {
+        match self {
+            Op::Get => false,
+            _ => true,
+        }
+    }
+}
+
+struct Tracer {
+    writer: BufWriter<File>,
+    seq: u64,
+    states: HashMap<(String, String, String), u32>,
+}
+
+impl Tracer {
+    fn register(&mut self, owner: &StateOwner, name: &str, kind: StateKind) -> u32 {
+        let key = (owner.dataflow.to_string(), owner.operator.to_string(), name.to_string());
+        if let Some(id) = self.states.get(&key) {
+            return *id;
+        }
+        let id = self.states.len() as u32;
+        let mut record = vec![TAG_STATE];
+        record.extend_from_slice(&id.to_le_bytes());
+        record.push(kind as u8);
+        for s in &[&*owner.dataflow, owner.operator, name] {
+            record.extend_from_slice(&(s.len() as u16).to_le_bytes());
+            record.extend_from_slice(s.as_bytes());
+        }
+        self.writer
+            .write_all(&record)
+            .expect("failed to write state trace");
+        self.states.insert(key, id);
+        id
+    }
+
+    fn access(&mut self, id: u32, op: Op, key_hash: u64, value_size: u32, rmw: RmwKind) {
+        let mut record = [0u8; 35];
+        record[0] = TAG_ACCESS;
+        record[1..9].copy_from_slice(&self.seq.to_le_bytes());
+        record[9..17].copy_from_slice(&TIME.with(|t| t.get()).to_le_bytes());
+        record[17..21].copy_from_slice(&id.to_le_bytes());
+        record[21] = op as u8;
+        record[22..30].copy_from_slice(&key_hash.to_le_bytes());
+        record[30..34].copy_from_slice(&value_size.to_le_bytes());
+        record[34] = rmw as u8;
+        self.writer
+            .write_all(&record)
+            .expect("failed to write state trace");
+        self.seq += 1;
+    }
+}
+
+thread_local! {
+    static TRACER: RefCell<Option<Tracer>> = RefCell::new(None);
+    static DATAFLOW: RefCell<Rc<str>> = RefCell::new(Rc::from(""));
+    static TIME: Cell<u64> = Cell::new(0);
+}
+
+/// Sets the dataflow time of the accesses the calling worker makes from now on.
+///
+/// Operators with traced state call it with the time of each input batch and notification before
+/// they access their state.
+pub fn set_time(time: usize) {
+    TIME.with(|t| t.set(time as u64));
+}
+
+/// Names the dataflows the calling worker builds from now on, until it is called again.
+pub fn set_dataflow(name: &str) {
+    DATAFLOW.with(|d| *d.borrow_mut() = Rc::from(name));
+}
+
+/// The operator that owns managed state, in the dataflow it was built in.
+///
+/// Operators create their owner while they are built, so that it names the dataflow the worker
+/// builds at that time, and pass it to the wrappers of their states. This keeps apart the states
+/// of operators with the same name in different dataflows, like the auction close of Q4 and Q6.
+#[derive(Clone, Debug)]
+pub struct StateOwner {
+    dataflow: Rc<str>,
+    operator: &'static str,
+}
+
+impl StateOwner {
+    pub fn new(operator: &'static str) -> Self {
+        StateOwner {
+            dataflow: DATAFLOW.with(|d| d.borrow().clone()),
+            operator: operator,
+        }
+    }
+
+    pub fn dataflow(&self) -> &str {
+        &self.dataflow
+    }
+
+    pub fn operator(&self) -> &'static str {
+        self.operator
+    }
+}
+
+/// The trace file of `worker` in `dir`.
+pub fn trace_file(dir: &Path, worker: usize) -> PathBuf {
+    dir.join(format!("state_trace_{}.bin", worker))
+}
+
+/// Enables tracing of the state accesses of the calling worker thread.
+pub fn init(dir: &Path, worker: usize) {
+    let path = trace_file(dir, worker);
+    let mut writer = BufWriter::new(
+        File::create(&path).unwrap_or_else(|e| panic!("Cannot create {:?}: {}", path, e)),
+    );
+    writer.write_all(MAGIC).expect("failed to write state trace");
+    TRACER.with(|t| {
+        *t.borrow_mut() = Some(Tracer {
+            writer: writer,
+            seq: 0,
+            states: HashMap::new(),
+        })
+    });
+}
+
+/// Flushes and closes the trace of the calling worker thread.
+pub fn finish() {
+    TRACER.with(|t| {
+        if let Some(mut tracer) = t.borrow_mut().take() {
+            tracer.writer.flush().expect("failed to flush state trace");
+        }
+    });
+}
+
+fn register(owner: &StateOwner, name: &str, kind: StateKind) -> Option<u32> {
+    TRACER.with(|t| {
+        t.borrow_mut()
+            .as_mut()
+            .map(|tracer| tracer.register(owner, name, kind))
+    })
+}
+
+fn record(id: u32, op: Op, key_hash: u64, value_size: u32, rmw: RmwKind) {
+    TRACER.with(|t| {
+        if let Some(tracer) = t.borrow_mut().as_mut() {
+            tracer.access(id, op, key_hash, value_size, rmw);
+        }
+    });
+}
+
+#[inline]
+fn hash_key<K: Hash>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[inline]
+fn value_size<V: Serialize>(value: &V) -> u32 {
+    bincode::serialized_size(value).unwrap_or(0) as u32
+}
+
+// Records the accesses to a state in the trace and in the state statistics, and their durations
+// in the profile, if enabled.
+struct Observer {
+    id: Option<u32>,
+    stats: Option<Arc<StateStats>>,
+    times: Option<Rc<StateTimes>>,
+}
+
+impl Observer {
+    fn new(owner: &StateOwner, name: &str, kind: StateKind) -> Self {
+        Observer {
+            id: register(owner, name, kind),
+            stats: stats::register(&owner.dataflow, owner.operator, name),
+            times: profile::register(&owner.dataflow, owner.operator, name),
+        }
+    }
+
+    // The start of a call to time, if profiling is enabled.
+    #[inline]
+    fn start(&self) -> Option<Instant> {
+        self.times.as_ref().map(|_| Instant::now())
+    }
+
+    #[inline]
+    fn timed(&self, op: Op, start: Option<Instant>) {
+        if let (Some(times), Some(start)) = (self.times.as_ref(), start) {
+            times.add(op, start);
+        }
+    }
+
+    #[inline]
+    fn enabled(&self) -> bool {
+        self.id.is_some() || self.stats.is_some()
+    }
+
+    #[inline]
+    fn access<F: FnOnce() -> u64>(&self, op: Op, key_hash: F, value_size: u32) {
+        self.access_rmw(op, key_hash, value_size, RmwKind::None)
+    }
+
+    #[inline]
+    fn access_rmw<F: FnOnce() -> u64>(&self, op: Op, key_hash: F, value_size: u32, rmw: RmwKind) {
+        if let Some(id) = self.id {
+            record(id, op, key_hash(), value_size, rmw);
+        }
+        if let Some(ref stats) = self.stats {
+            stats.access(op, value_size);
+        }
+    }
+}
+
+/// A `ManagedMap` that records its accesses.
+pub struct TracedMap<K, V> {
+    inner: Box<ManagedMap<K, V>>,
+    observer: Observer,
+}
+
+impl<K: Hash, V: Serialize> TracedMap<K, V> {
+    pub fn new(owner: &StateOwner, name: &str, inner: Box<ManagedMap<K, V>>) -> Self {
+        TracedMap {
+            inner: inner,
+            observer: Observer::new(owner, name, StateKind::Map),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<Rc<V>> {
+        let start = self.observer.start();
+        let value = self.inner.get(key);
+        self.observer.timed(Op::Get, start);
+        if self.observer.enabled() {
+            let size = value.as_ref().map_or(ABSENT, |v| value_size(&**v));
+            self.observer.access(Op::Get, || hash_key(key), size);
+        }
+        value
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.observer.enabled() {
+            self.observer
+                .access(Op::Insert, || hash_key(&key), value_size(&value));
+        }
+        let start = self.observer.start();
+        self.inner.insert(key, value);
+        self.observer.timed(Op::Insert, start);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let start = self.observer.start();
+        let value = self.inner.remove(key);
+        self.observer.timed(Op::Remove, start);
+        if self.observer.enabled() {
+            let size = value.as_ref().map_or(ABSENT, value_size);
+            self.observer.access(Op::Remove, || hash_key(key), size);
+        }
+        value
+    }
+
+    pub fn rmw(&mut self, key: K, modification: V)
+    where
+        V: TracedRmw,
+    {
+        if self.observer.enabled() {
+            self.observer.access_rmw(
+                Op::Rmw,
+                || hash_key(&key),
+                value_size(&modification),
+                V::RMW_KIND,
+            );
+        }
+        let start = self.observer.start();
+        self.inner.rmw(key, modification);
+        self.observer.timed(Op::Rmw, start);
+    }
+}
+
+/// A `ManagedValue` that records its accesses.
+pub struct TracedValue<V> {
+    inner: Box<ManagedValue<V>>,
+    observer: Observer,
+}
+
+impl<V: Serialize> TracedValue<V> {
+    pub fn new(owner: &StateOwner, name: &str, inner: Box<ManagedValue<V>>) -> Self {
+        TracedValue {
+            inner: inner,
+            observer: Observer::new(owner, name, StateKind::Value),
+        }
+    }
+
+    pub fn get(&self) -> Option<Rc<V>> {
+        let start = self.observer.start();
+        let value = self.inner.get();
+        self.observer.timed(Op::Get, start);
+        if self.observer.enabled() {
+            let size = value.as_ref().map_or(ABSENT, |v| value_size(&**v));
+            self.observer.access(Op::Get, || 0, size);
+        }
+        value
+    }
+
+    pub fn set(&mut self, value: V) {
+        if self.observer.enabled() {
+            self.observer.access(Op::Set, || 0, value_size(&value));
+        }
+        let start = self.observer.start();
+        self.inner.set(value);
+        self.observer.timed(Op::Set, start);
+    }
+
+    pub fn take(&mut self) -> Option<V> {
+        let start = self.observer.start();
+        let value = self.inner.take();
+        self.observer.timed(Op::Take, start);
+        if self.observer.enabled() {
+            let size = value.as_ref().map_or(ABSENT, value_size);
+            self.observer.access(Op::Take, || 0, size);
+        }
+        value
+    }
+
+    pub fn rmw(&mut self, modification: V)
+    where
+        V: TracedRmw,
+    {
+        if self.observer.enabled() {
+            self.observer
+                .access_rmw(Op::Rmw, || 0, value_size(&modification), V::RMW_KIND);
+        }
+        let start = self.observer.start();
+        self.inner.rmw(modification);
+        self.observer.timed(Op::Rmw, start);
+    }
+}
+
+// Serialized size of the values of a `ManagedCount`
+const COUNT_SIZE: u32 = 8;
+
+/// A `ManagedCount` that records its accesses.
+pub struct TracedCount {
+    inner: Box<ManagedCount>,
+    observer: Observer,
+}
+
+impl TracedCount {
+    pub fn new(owner: &StateOwner, name: &str, inner: Box<ManagedCount>) -> Self {
+        TracedCount {
+            inner: inner,
+            observer: Observer::new(owner, name, StateKind::Count),
+        }
+    }
+
+    pub fn get(&self) -> i64 {
+        self.observer.access(Op::Get, || 0, COUNT_SIZE);
+        let start = self.observer.start();
+        let value = self.inner.get();
+        self.observer.timed(Op::Get, start);
+        value
+    }
+
+    pub fn set(&mut self, value: i64) {
+        self.observer.access(Op::Set, || 0, COUNT_SIZE);
+        let start = self.observer.start();
+        self.inner.set(value);
+        self.observer.timed(Op::Set, start);
+    }
+
+    pub fn increase(&mut self, amount: i64) {
+        self.observer
+            .access_rmw(Op::Rmw, || 0, COUNT_SIZE, RmwKind::Combine);
+        let start = self.observer.start();
+        self.inner.increase(amount);
+        self.observer.timed(Op::Rmw, start);
+    }
+
+    pub fn decrease(&mut self, amount: i64) {
+        self.observer
+            .access_rmw(Op::Rmw, || 0, COUNT_SIZE, RmwKind::Combine);
+        let start = self.observer.start();
+        self.inner.decrease(amount);
+        self.observer.timed(Op::Rmw, start);
+    }
+}
+
+/// A record of a state trace.
+#[derive(Clone, Debug)]
+pub enum Record {
+    State(StateInfo),
+    Access(Access),
+}
+
+/// The definition of a traced state.
+#[derive(Clone, Debug)]
+pub struct StateInfo {
+    pub id: u32,
+    pub kind: StateKind,
+    pub dataflow: String,
+    pub operator: String,
+    pub name: String,
+}
+
+/// A traced state access.
+#[derive(Copy, Clone, Debug)]
+pub struct Access {
+    pub seq: u64,
+    pub time: u64,
+    pub id: u32,
+    pub op: Op,
+    pub key_hash: u64,
+    pub value_size: u32,
+    pub rmw: RmwKind,
+}
+
+impl Access {
+    /// The size of the value, or `None` if the access found no value.
+    pub fn value_size(&self) -> Option<u32> {
+        if self.value_size == ABSENT {
+            None
+        } else {
+            Some(self.value_size)
+        }
+    }
+}
+
+/// Reads the records of a state trace.
+pub struct TraceReader<R: Read> {
+    reader: R,
+}
+
+impl TraceReader<BufReader<File>> {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        TraceReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a state trace",
+            ));
+        }
+        Ok(TraceReader { reader: reader })
+    }
+
+    fn read_string(&mut self) -> io::Result<String> {
+        let mut len = [0u8; 2];
+        self.reader.read_exact(&mut len)?;
+        let mut bytes = vec![0u8; u16::from_le_bytes(len) as usize];
+        self.reader.read_exact(&mut bytes)?;
+        String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Reads the next record, or `None` at the end of the trace.
+    pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        let mut tag = [0u8; 1];
+        match self.reader.read_exact(&mut tag) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+        match tag[0] {
+            TAG_STATE => {
+                let mut header = [0u8; 5];
+                self.reader.read_exact(&mut header)?;
+                let mut id = [0u8; 4];
+                id.copy_from_slice(&header[0..4]);
+                let kind = StateKind::from_u8(header[4]).ok_or_else(|| invalid("bad state kind"))?;
+                let dataflow = self.read_string()?;
+                let operator = self.read_string()?;
+                let name = self.read_string()?;
+                Ok(Some(Record::State(StateInfo {
+                    id: u32::from_le_bytes(id),
+                    kind: kind,
+                    dataflow: dataflow,
+                    operator: operator,
+                    name: name,
+                })))
+            }
+            TAG_ACCESS => {
+                let mut record = [0u8; 34];
+                self.reader.read_exact(&mut record)?;
+                let mut seq = [0u8; 8];
+                let mut time = [0u8; 8];
+                let mut id = [0u8; 4];
+                let mut key_hash = [0u8; 8];
+                let mut value_size = [0u8; 4];
+                seq.copy_from_slice(&record[0..8]);
+                time.copy_from_slice(&record[8..16]);
+                id.copy_from_slice(&record[16..20]);
+                key_hash.copy_from_slice(&record[21..29]);
+                value_size.copy_from_slice(&record[29..33]);
+                let op = Op::from_u8(record[20]).ok_or_else(|| invalid("bad operation"))?;
+                let rmw = RmwKind::from_u8(record[33]).ok_or_else(|| invalid("bad rmw kind"))?;
+                Ok(Some(Record::Access(Access {
+                    seq: u64::from_le_bytes(seq),
+                    time: u64::from_le_bytes(time),
+                    id: u32::from_le_bytes(id),
+                    op: op,
+                    key_hash: u64::from_le_bytes(key_hash),
+                    value_size: u32::from_le_bytes(value_size),
+                    rmw: rmw,
+                })))
+            }
+            _ => Err(invalid("bad record tag")),
+        }
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        self.next_record().expect("failed to read state trace")
+    }
+}
@@ -0,0 +1,718 @@
+//! Checks that the managed queries produce the same output on every persistent state backend
+//! as on `InMemoryBackend`.
+//!
+//! Each test feeds a deterministic, bounded stream of events through a query twice, once on
+//! `InMemoryBackend` and once on the backend under test, and compares the outputs of each epoch
+//! with `verify`. Sliced windows are compared the same way against windows that records are
+//! merged into, on `InMemoryBackend`. FASTER and RocksDB read their configuration files from the
+//! working directory.
+
+extern crate nexmark;
+extern crate rand;
+extern crate timely;
+
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+use timely::dataflow::operators::capture::event::link::EventLink;
+use timely::dataflow::operators::capture::Replay;
+use timely::dataflow::operators::{Capture, Probe};
+use timely::dataflow::{InputHandle, ProbeHandle, Scope, Stream};
+use timely::state::backends::{
+    FASTERBackend, InMemoryBackend, RocksDBBackend, RocksDBMergeBackend, RocksDBMergeBackend2,
+};
+use timely::state::StateBackend;
+use timely::ExchangeData;
+
+use nexmark::config::{Config, NEXMarkConfig};
+use nexmark::event::{Auction, Bid, Event};
+use nexmark::queries::nexmark::*;
+use nexmark::queries::windows::global::*;
+use nexmark::queries::windows::keyed::*;
+use nexmark::queries::windows::{
+    Avg, Count, CountMinTopK, Distinct, Folded, HyperLogLog, Max, Median, Min, Rank,
+    SlidingWindows, StateLayout, Sum, TopK,
+};
+use nexmark::queries::{demux, NexmarkInput, NexmarkTimer};
+use nexmark::tools::verify;
+
+const EVENTS_PER_SECOND: usize = 10_000;
+const DURATION_NS: usize = 2_000_000_000;
+// Events are sent in epochs of 1ms, as in the driver
+const EPOCH_NS: usize = 1_000_000;
+
+// The windows of the sliding window queries, of 5 slides of 100ms
+fn sliding_windows() -> SlidingWindows {
+    SlidingWindows::new(500_000_000, 100_000_000)
+}
+
+/// A query under test.
+trait Query: Send + Sync + 'static {
+    type Output: ExchangeData + Ord + Debug;
+
+    fn build<S: Scope<Timestamp = usize>>(
+        &self,
+        input: &NexmarkInput,
+        nt: NexmarkTimer,
+        scope: &mut S,
+    ) -> Stream<S, Self::Output>;
+}
+
+/// Runs `expected_query` on `InMemoryBackend` and `query` on `B` and checks that their outputs
+/// match.
+fn compare<E: Query, Q: Query<Output = E::Output>, B: StateBackend>(expected_query: E, query: Q) {
+    timely::execute_from_args(Vec::<String>::new().into_iter(), move |worker, _node_state_handle| {
+        let mut input = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+
+        let bids = Rc::new(EventLink::new());
+        let auctions = Rc::new(EventLink::new());
+        let people = Rc::new(EventLink::new());
+        let expected_closed_auctions = Rc::new(EventLink::new());
+        let expected_closed_auctions_flex = Rc::new(EventLink::new());
+        let actual_closed_auctions = Rc::new(EventLink::new());
+        let actual_closed_auctions_flex = Rc::new(EventLink::new());
+        let expected = Rc::new(EventLink::new());
+        let actual = Rc::new(EventLink::new());
+
+        // Both runs read the same events, but each has its own intermediate streams
+        let expected_input = NexmarkInput {
+            bids: &bids,
+            auctions: &auctions,
+            people: &people,
+            closed_auctions: &expected_closed_auctions,
+            closed_auctions_flex: &expected_closed_auctions_flex,
+        };
+        let actual_input = NexmarkInput {
+            bids: &bids,
+            auctions: &auctions,
+            people: &people,
+            closed_auctions: &actual_closed_auctions,
+            closed_auctions_flex: &actual_closed_auctions_flex,
+        };
+        let nt = NexmarkTimer { time_dilation: 1 };
+
+        worker.dataflow::<_, _, _, InMemoryBackend>(|scope, _| {
+            demux(&input.to_stream(scope), &expected_input);
+        });
+        worker.dataflow::<_, _, _, InMemoryBackend>(|scope, _| {
+            expected_query
+                .build(&expected_input, nt, scope)
+                .capture_into(expected.clone());
+        });
+        worker.dataflow::<_, _, _, B>(|scope, _| {
+            query
+                .build(&actual_input, nt, scope)
+                .capture_into(actual.clone());
+        });
+        worker.dataflow::<_, _, _, InMemoryBackend>(|scope, _| {
+            let expected = Some(expected.clone()).replay_into(scope);
+            let actual = Some(actual.clone()).replay_into(scope);
+            verify(&expected, &actual).probe_with(&mut probe);
+        });
+
+        let mut config = Config::new();
+        config.insert("events-per-second", format!("{}", EVENTS_PER_SECOND));
+        config.insert("in-flight-auctions", format!("{}", EVENTS_PER_SECOND * 2592));
+        config.insert("first-event-number", format!("{}", worker.index()));
+        let mut config = NEXMarkConfig::new(&config);
+        config.base_time_ns = 0;
+        let mut rng = SmallRng::from_seed([worker.peers() as u8; 16]);
+
+        let mut events_so_far = worker.index();
+        let mut epoch = EPOCH_NS;
+        input.advance_to(epoch);
+        loop {
+            let event = Event::create(events_so_far, &mut rng, &mut config);
+            let event_epoch = (*event.time() / EPOCH_NS + 1) * EPOCH_NS;
+            if event_epoch > DURATION_NS {
+                break;
+            }
+            if event_epoch > epoch {
+                epoch = event_epoch;
+                input.advance_to(epoch);
+                worker.step();
+            }
+            input.send(event);
+            events_so_far += worker.peers();
+        }
+        input.close();
+        while worker.step() {}
+    })
+    .expect("unsuccessful execution")
+    .join()
+    .into_iter()
+    .for_each(|result| result.expect("worker failed"));
+}
+
+macro_rules! query {
+    // A query that lays out its windows in the backend with the `StateLayout` it holds
+    (
+        $name:ident($layout:ident),
+        $output:ty,
+        |$input:ident, $nt:ident, $scope:ident| $body:expr
+    ) => {
+        struct $name(StateLayout);
+
+        impl Query for $name {
+            type Output = $output;
+
+            fn build<S: Scope<Timestamp = usize>>(
+                &self,
+                $input: &NexmarkInput,
+                $nt: NexmarkTimer,
+                $scope: &mut S,
+            ) -> Stream<S, $output> {
+                let $layout = self.0;
+                $body
+            }
+        }
+    };
+    ($name:ident, $output:ty, |$input:ident, $nt:ident, $scope:ident| $body:expr) => {
+        struct $name;
+
+        impl Query for $name {
+            type Output = $output;
+
+            fn build<S: Scope<Timestamp = usize>>(
+                &self,
+                $input: &NexmarkInput,
+                $nt: NexmarkTimer,
+                $scope: &mut S,
+            ) -> Stream<S, $output> {
+                $body
+            }
+        }
+    };
+}
+
+/// Declares a module of tests that compare `$query` on each of the given backends.
+macro_rules! compare_backends {
+    ($module:ident, $query:expr, [$($test:ident: $backend:ty),*]) => {
+        mod $module {
+            use super::*;
+
+            $(
+                #[test]
+                fn $test() {
+                    compare::<_, _, $backend>($query, $query);
+                }
+            )*
+        }
+    };
+}
+
+query!(Q3, (String, String, String, usize), |input, nt, scope| {
+    q3_managed(input, nt, scope)
+});
+query!(Q4, (usize, usize), |input, nt, scope| {
+    q4_q6_common_managed(input, nt, scope).capture_into(input.closed_auctions.clone());
+    q4_managed(input, nt, scope)
+});
+query!(Q5, usize, |input, nt, scope| {
+    q5_managed(input, nt, scope, 5, 100_000_000)
+});
+query!(Q5Index, usize, |input, nt, scope| {
+    q5_managed_index(input, nt, scope, 5, 100_000_000)
+});
+query!(Q6, (usize, usize), |input, nt, scope| {
+    q4_q6_common_managed(input, nt, scope).capture_into(input.closed_auctions.clone());
+    q6_managed(input, nt, scope)
+});
+query!(Q7, usize, |input, nt, scope| {
+    q7_managed(input, nt, scope, 100_000_000)
+});
+query!(Q8, usize, |input, nt, scope| {
+    q8_managed(input, nt, scope, 500_000_000)
+});
+query!(Q8Map, usize, |input, nt, scope| {
+    q8_managed_map(input, nt, scope, 500_000_000)
+});
+query!(Q9, (Auction, Bid), |input, nt, scope| {
+    q9_managed(input, nt, scope)
+});
+query!(Q11, (usize, usize), |input, nt, scope| {
+    q11_managed(input, nt, scope, 100_000_000)
+});
+query!(Q12, (usize, usize, usize), |input, nt, scope| {
+    q12_managed(input, nt, scope, 100_000_000)
+});
+query!(Q13, (usize, usize, usize, usize, String), |input, nt, scope| {
+    q13_managed(input, nt, scope, 500)
+});
+query!(Q15, BidStats, |input, nt, scope| {
+    q15_managed(input, nt, scope)
+});
+query!(Q16, (String, BidStats), |input, nt, scope| {
+    q16_managed(input, nt, scope)
+});
+query!(
+    Q17,
+    (usize, usize, usize, usize, usize, usize, usize, usize, usize, usize),
+    |input, nt, scope| q17_managed(input, nt, scope)
+);
+query!(Q18, Bid, |input, nt, scope| q18_managed(input, nt, scope));
+query!(Q19, (usize, usize, usize, usize), |input, nt, scope| {
+    q19_managed(input, nt, scope)
+});
+query!(Q20, (Bid, Auction), |input, nt, scope| {
+    q20_managed(input, nt, scope)
+});
+query!(Window2Count, (usize, usize), |input, nt, scope| {
+    window_2_faster_count(input, nt, scope, 5, 100_000_000)
+});
+query!(Window2Rank, (usize, usize, usize), |input, nt, scope| {
+    window_2_faster_rank(input, nt, scope, 5, 100_000_000)
+});
+query!(Window2bCount, (usize, usize), |input, nt, scope| {
+    window_2b_rocksdb_count(input, nt, scope, 5, 100_000_000)
+});
+query!(Window2bRank, (usize, usize, usize), |input, nt, scope| {
+    window_2b_rocksdb_rank(input, nt, scope, 5, 100_000_000)
+});
+query!(Window2Sum(layout), (usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.price, *b.date_time),
+        Sum(|b: &(usize, usize, usize)| b.1),
+    )
+});
+query!(Window2Min(layout), (usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.price, *b.date_time),
+        Min(|b: &(usize, usize, usize)| b.1),
+    )
+});
+query!(Window2Max(layout), (usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.price, *b.date_time),
+        Max(|b: &(usize, usize, usize)| b.1),
+    )
+});
+query!(Window2Avg(layout), (usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.price, *b.date_time),
+        Avg(|b: &(usize, usize, usize)| b.1),
+    )
+});
+query!(Window2Distinct(layout), (usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.bidder, *b.date_time),
+        Distinct(|b: &(usize, usize, usize)| b.1),
+    )
+});
+query!(Window2Median(layout), (usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.price, *b.date_time),
+        Median(|b: &(usize, usize, usize)| b.1),
+    )
+});
+query!(Window2TopK(layout), (usize, usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, *b.date_time),
+        TopK::new(3, |b: &(usize, usize)| b.0),
+    )
+});
+query!(Window2HllDistinct(layout), (usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.bidder, *b.date_time),
+        HyperLogLog::new(10, |b: &(usize, usize, usize)| b.1),
+    )
+});
+query!(Window2CmTopK(layout), (usize, usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, *b.date_time),
+        CountMinTopK::new(3, 4, 256, |b: &(usize, usize)| b.0),
+    )
+});
+query!(Window2MinFolded(layout), (usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.price, *b.date_time),
+        Folded(Min(|b: &(usize, usize, usize)| b.1)),
+    )
+});
+query!(Window2MaxFolded(layout), (usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.price, *b.date_time),
+        Folded(Max(|b: &(usize, usize, usize)| b.1)),
+    )
+});
+query!(Window2AvgCounters, (usize, usize), |input, _nt, scope| {
+    window_2_avg_counters(input, scope, sliding_windows())
+});
+query!(Window2HllDistinctFolded(layout), (usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.bidder, *b.date_time),
+        Folded(HyperLogLog::new(10, |b: &(usize, usize, usize)| b.1)),
+    )
+});
+query!(Window2CmTopKFolded(layout), (usize, usize, usize), |input, _nt, scope| {
+    window_2(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, *b.date_time),
+        Folded(CountMinTopK::new(3, 4, 256, |b: &(usize, usize)| b.0)),
+    )
+});
+query!(WindowSlicedCount(layout), (usize, usize), |input, _nt, scope| {
+    window_sliced(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, *b.date_time),
+        Count,
+    )
+});
+query!(WindowSlicedRank(layout), (usize, usize, usize), |input, _nt, scope| {
+    window_sliced(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, *b.date_time),
+        Rank,
+    )
+});
+query!(WindowSlicedSum(layout), (usize, usize), |input, _nt, scope| {
+    window_sliced(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.price, *b.date_time),
+        Sum(|b: &(usize, usize, usize)| b.1),
+    )
+});
+query!(WindowSlicedMin(layout), (usize, usize), |input, _nt, scope| {
+    window_sliced(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.price, *b.date_time),
+        Min(|b: &(usize, usize, usize)| b.1),
+    )
+});
+query!(WindowSlicedMax(layout), (usize, usize), |input, _nt, scope| {
+    window_sliced(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.price, *b.date_time),
+        Max(|b: &(usize, usize, usize)| b.1),
+    )
+});
+query!(WindowSlicedMinFolded(layout), (usize, usize), |input, _nt, scope| {
+    window_sliced(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.price, *b.date_time),
+        Folded(Min(|b: &(usize, usize, usize)| b.1)),
+    )
+});
+query!(WindowSlicedMaxFolded(layout), (usize, usize), |input, _nt, scope| {
+    window_sliced(
+        input,
+        scope,
+        sliding_windows(),
+        layout,
+        |b| (b.auction, b.price, *b.date_time),
+        Folded(Max(|b: &(usize, usize, usize)| b.1)),
+    )
+});
+query!(WindowTumblingCount, (usize, usize), |input, nt, scope| {
+    window_tumbling_count(input, nt, scope, 100_000_000)
+});
+query!(WindowTumblingRank, (usize, usize, usize), |input, nt, scope| {
+    window_tumbling_rank(input, nt, scope, 100_000_000)
+});
+query!(WindowCountCount, (usize, (usize, usize)), |input, nt, scope| {
+    window_count_count(input, nt, scope, 3)
+});
+query!(WindowCountRank, (usize, (usize, usize, usize)), |input, nt, scope| {
+    window_count_rank(input, nt, scope, 3)
+});
+query!(SessionCount, (usize, (usize, usize)), |input, nt, scope| {
+    session_count(input, nt, scope, 100_000_000)
+});
+query!(SessionBCount, (usize, (usize, usize)), |input, nt, scope| {
+    session_b_count(input, nt, scope, 100_000_000)
+});
+
+// Queries without read-modify-writes run on the plain backends. Read-modify-writes that append
+// to vectors also run on `RocksDBMergeBackend`, and those that add counts on
+// `RocksDBMergeBackend2`, where windows are initialized before records are merged into them as in
+// the driver. Aggregations that neither append nor add run on `RocksDBMergeBackend` folded from
+// the records of their windows, as in the driver.
+compare_backends!(q3, Q3, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q4, Q4, [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend,
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(q5, Q5, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q5_index, Q5Index, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q6, Q6, [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend,
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(q7, Q7, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q8, Q8, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q8_map, Q8Map, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q9, Q9, [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend,
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(q11, Q11, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q12, Q12, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q13, Q13, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q15, Q15, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q16, Q16, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q17, Q17, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q18, Q18, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q19, Q19, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(q20, Q20, [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend,
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_2_count, Window2Count, [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend,
+    rocksdb_merge2: RocksDBMergeBackend2
+]);
+compare_backends!(window_2_rank, Window2Rank, [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend,
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_2b_count, Window2bCount, [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend,
+    rocksdb_merge2: RocksDBMergeBackend2
+]);
+compare_backends!(window_2b_rank, Window2bRank, [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend,
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_2_sum, Window2Sum(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_2_sum_merge_init, Window2Sum(StateLayout::MergeInit), [
+    rocksdb_merge2: RocksDBMergeBackend2
+]);
+compare_backends!(window_2_min, Window2Min(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_2_min_merge_init, Window2MinFolded(StateLayout::MergeInit), [
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_2_max, Window2Max(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_2_max_merge_init, Window2MaxFolded(StateLayout::MergeInit), [
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_2_avg, Window2Avg(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_2_avg_merge_init, Window2AvgCounters, [
+    rocksdb_merge2: RocksDBMergeBackend2
+]);
+compare_backends!(window_2_distinct, Window2Distinct(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_2_distinct_merge_init, Window2Distinct(StateLayout::MergeInit), [
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_2_median, Window2Median(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_2_median_merge_init, Window2Median(StateLayout::MergeInit), [
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_2_top_k, Window2TopK(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_2_top_k_merge_init, Window2TopK(StateLayout::MergeInit), [
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_2_hll_distinct, Window2HllDistinct(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(
+    window_2_hll_distinct_merge_init,
+    Window2HllDistinctFolded(StateLayout::MergeInit),
+    [rocksdb_merge: RocksDBMergeBackend]
+);
+compare_backends!(window_2_cm_top_k, Window2CmTopK(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_2_cm_top_k_merge_init, Window2CmTopKFolded(StateLayout::MergeInit), [
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_sliced_count, WindowSlicedCount(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_sliced_count_merge_init, WindowSlicedCount(StateLayout::MergeInit), [
+    rocksdb_merge2: RocksDBMergeBackend2
+]);
+compare_backends!(window_sliced_rank, WindowSlicedRank(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_sliced_rank_merge_init, WindowSlicedRank(StateLayout::MergeInit), [
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_sliced_sum, WindowSlicedSum(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_sliced_sum_merge_init, WindowSlicedSum(StateLayout::MergeInit), [
+    rocksdb_merge2: RocksDBMergeBackend2
+]);
+compare_backends!(window_sliced_min, WindowSlicedMin(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_sliced_max, WindowSlicedMax(StateLayout::Rmw), [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend
+]);
+compare_backends!(window_sliced_min_merge_init, WindowSlicedMinFolded(StateLayout::MergeInit), [
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_sliced_max_merge_init, WindowSlicedMaxFolded(StateLayout::MergeInit), [
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_tumbling_count, WindowTumblingCount, [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend,
+    rocksdb_merge2: RocksDBMergeBackend2
+]);
+compare_backends!(window_tumbling_rank, WindowTumblingRank, [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend,
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(window_count_count, WindowCountCount, [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend,
+    rocksdb_merge2: RocksDBMergeBackend2
+]);
+compare_backends!(window_count_rank, WindowCountRank, [
+    faster: FASTERBackend,
+    rocksdb: RocksDBBackend,
+    rocksdb_merge: RocksDBMergeBackend
+]);
+compare_backends!(session_count, SessionCount, [faster: FASTERBackend, rocksdb: RocksDBBackend]);
+compare_backends!(
+    session_b_count,
+    SessionBCount,
+    [faster: FASTERBackend, rocksdb: RocksDBBackend, rocksdb_merge: RocksDBMergeBackend]
+);
+
+/// Declares a module of tests that compare queries that compute the same windows in different
+/// ways, like sliced windows against windows that records are merged into.
+macro_rules! compare_outputs {
+    ($module:ident, [$($test:ident: $expected:expr, $actual:expr),*]) => {
+        mod $module {
+            use super::*;
+
+            $(
+                #[test]
+                fn $test() {
+                    compare::<_, _, InMemoryBackend>($expected, $actual);
+                }
+            )*
+        }
+    };
+}
+
+compare_outputs!(window_sliced_as_window_2, [
+    count: Window2Count, WindowSlicedCount(StateLayout::Rmw),
+    rank: Window2Rank, WindowSlicedRank(StateLayout::Rmw),
+    sum: Window2Sum(StateLayout::Rmw), WindowSlicedSum(StateLayout::Rmw),
+    min: Window2Min(StateLayout::Rmw), WindowSlicedMin(StateLayout::Rmw),
+    max: Window2Max(StateLayout::Rmw), WindowSlicedMax(StateLayout::Rmw),
+    min_folded: Window2Min(StateLayout::Rmw), WindowSlicedMinFolded(StateLayout::MergeInit),
+    max_folded: Window2Max(StateLayout::Rmw), WindowSlicedMaxFolded(StateLayout::MergeInit)
+]);
+
+// Folded aggregations and the counters of AVG emit the same outputs as the aggregations whose
+// states the merge operators cannot merge
+compare_outputs!(merge_layouts_as_window_2, [
+    min: Window2Min(StateLayout::Rmw), Window2MinFolded(StateLayout::MergeInit),
+    max: Window2Max(StateLayout::Rmw), Window2MaxFolded(StateLayout::MergeInit),
+    avg: Window2Avg(StateLayout::Rmw), Window2AvgCounters,
+    hll: Window2HllDistinct(StateLayout::Rmw), Window2HllDistinctFolded(StateLayout::MergeInit),
+    cm_top_k: Window2CmTopK(StateLayout::Rmw), Window2CmTopKFolded(StateLayout::MergeInit)
+]);
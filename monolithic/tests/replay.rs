@@ -0,0 +1,163 @@
+//! Checks that replaying a recording at a different speed scales event times along with the
+//! times the events are sent at.
+//!
+//! A recording of generated events is replayed at speeds 0.5 and 2 through a sliding window
+//! query, whose windows are scaled by the same speed, and must produce the same output as the
+//! recording replayed at its original speed.
+
+extern crate nexmark;
+extern crate rand;
+extern crate tempfile;
+extern crate timely;
+
+use std::path::Path;
+use std::rc::Rc;
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+use timely::dataflow::operators::capture::event::link::EventLink;
+use timely::dataflow::operators::{Inspect, Probe};
+use timely::dataflow::{InputHandle, ProbeHandle};
+use timely::state::backends::InMemoryBackend;
+
+use nexmark::config::{Config, NEXMarkConfig};
+use nexmark::event::Event;
+use nexmark::input::{input_file, EventWriter, TimedEvents};
+use nexmark::queries::windows::global::window_2_faster_count;
+use nexmark::queries::{demux, NexmarkInput, NexmarkTimer};
+
+const EVENTS_PER_SECOND: usize = 10_000;
+const DURATION_NS: usize = 2_000_000_000;
+// Events are sent in epochs of 1ms, as in the driver
+const EPOCH_NS: usize = 1_000_000;
+const WINDOW_SLIDE_NS: usize = 100_000_000;
+
+/// Records the events of a single worker, each sent at its event time, as the driver does.
+fn record(dir: &Path) -> Vec<Event> {
+    let mut config = Config::new();
+    config.insert("events-per-second", format!("{}", EVENTS_PER_SECOND));
+    config.insert("in-flight-auctions", format!("{}", EVENTS_PER_SECOND * 2592));
+    let mut config = NEXMarkConfig::new(&config);
+    config.base_time_ns = 0;
+    let mut rng = SmallRng::from_seed([1; 16]);
+
+    let mut writer = EventWriter::create(&input_file(dir, 0));
+    let mut events = Vec::new();
+    for events_so_far in 0.. {
+        let event = Event::create(events_so_far, &mut rng, &mut config);
+        if *event.time() >= DURATION_NS {
+            break;
+        }
+        writer.write(*event.time() as u64, &event);
+        events.push(event);
+    }
+    writer.finish();
+    events
+}
+
+/// Replays the recording in `dir` at `speed` through a sliding window count whose slide is
+/// scaled by `speed`, and returns its sorted output.
+fn replay(dir: &Path, speed: f64) -> Vec<(usize, usize)> {
+    let path = input_file(dir, 0);
+    let window_slide_ns = (WINDOW_SLIDE_NS as f64 / speed) as usize;
+    let run_ns = (DURATION_NS as f64 / speed) as usize;
+    let mut outputs = timely::execute_from_args(
+        Vec::<String>::new().into_iter(),
+        move |worker, _node_state_handle| {
+            let mut input = InputHandle::new();
+            let mut probe = ProbeHandle::new();
+            let output = Rc::new(std::cell::RefCell::new(Vec::new()));
+
+            let bids = Rc::new(EventLink::new());
+            let auctions = Rc::new(EventLink::new());
+            let people = Rc::new(EventLink::new());
+            let closed_auctions = Rc::new(EventLink::new());
+            let closed_auctions_flex = Rc::new(EventLink::new());
+            let nexmark_input = NexmarkInput {
+                bids: &bids,
+                auctions: &auctions,
+                people: &people,
+                closed_auctions: &closed_auctions,
+                closed_auctions_flex: &closed_auctions_flex,
+            };
+            let nt = NexmarkTimer { time_dilation: 1 };
+
+            worker.dataflow::<_, _, _, InMemoryBackend>(|scope, _| {
+                demux(&input.to_stream(scope), &nexmark_input);
+            });
+            worker.dataflow::<_, _, _, InMemoryBackend>(|scope, _| {
+                let output = output.clone();
+                window_2_faster_count(&nexmark_input, nt, scope, 5, window_slide_ns)
+                    .inspect(move |x| output.borrow_mut().push(*x))
+                    .probe_with(&mut probe);
+            });
+
+            // Events due at the end of each epoch are sent in it, as in the driver, and must
+            // not have event times before it
+            let mut replayer = TimedEvents::replay(&path, speed);
+            input.advance_to(1);
+            let mut epoch_end = 0;
+            while epoch_end < run_ns {
+                epoch_end += EPOCH_NS;
+                while let Some(event) = replayer.next_until(epoch_end as u64) {
+                    assert!(
+                        *event.time() + 1 >= *input.time(),
+                        "event at {} sent in epoch {}",
+                        *event.time(),
+                        input.time()
+                    );
+                    input.send(event);
+                }
+                input.advance_to(epoch_end + 1);
+                while probe.less_than(input.time()) {
+                    worker.step();
+                }
+            }
+            assert!(replayer.next().is_none());
+            input.close();
+            while worker.step() {}
+
+            let output = output.borrow().clone();
+            output
+        },
+    )
+    .expect("unsuccessful execution")
+    .join()
+    .into_iter()
+    .map(|result| result.expect("worker failed"))
+    .next()
+    .unwrap();
+    outputs.sort();
+    outputs
+}
+
+#[test]
+fn replay_scales_event_times() {
+    let dir = tempfile::tempdir().expect("Cannot create recording directory");
+    let events = record(dir.path());
+    for &speed in [0.5, 2.0].iter() {
+        let replayed: Vec<Event> = TimedEvents::replay(&input_file(dir.path(), 0), speed).collect();
+        assert_eq!(replayed.len(), events.len());
+        for (recorded, replayed) in events.iter().zip(replayed.iter()) {
+            let scaled = |t: usize| (t as f64 / speed) as usize;
+            assert_eq!(*replayed.time(), scaled(*recorded.time()));
+            if let (Event::Auction(recorded), Event::Auction(replayed)) = (recorded, replayed) {
+                assert_eq!(*replayed.expires, scaled(*recorded.expires));
+            }
+        }
+    }
+}
+
+#[test]
+fn replay_at_half_speed() {
+    let dir = tempfile::tempdir().expect("Cannot create recording directory");
+    record(dir.path());
+    assert_eq!(replay(dir.path(), 0.5), replay(dir.path(), 1.0));
+}
+
+#[test]
+fn replay_at_double_speed() {
+    let dir = tempfile::tempdir().expect("Cannot create recording directory");
+    record(dir.path());
+    assert_eq!(replay(dir.path(), 2.0), replay(dir.path(), 1.0));
+}
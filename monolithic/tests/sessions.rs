@@ -0,0 +1,132 @@
+//! Checks the outputs of `SessionWindowOperator` when sessions merge.
+//!
+//! Records are sent at chosen epochs, so that they arrive late or early with respect to their
+//! event times. The sessions of a key must be merged by a late record that bridges them, must not
+//! be closed by the timeouts of the windows they superseded, and must close at the current time
+//! if their gap already passed when their record arrived. On `RocksDBMergeBackend`, the records
+//! of a session are appended and counted when it closes. FASTER and RocksDB read their
+//! configuration files from the working directory.
+
+extern crate nexmark;
+extern crate timely;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use timely::dataflow::operators::{Input, Inspect};
+use timely::state::backends::{FASTERBackend, InMemoryBackend, RocksDBBackend, RocksDBMergeBackend};
+use timely::state::StateBackend;
+
+use nexmark::queries::windows::{Count, Folded, SessionWindowOperator, StateLayout};
+
+const GAP: usize = 10;
+
+// (epoch, key, event time) of the records, in the order they are sent
+const RECORDS: &[(usize, usize, usize)] = &[
+    (0, 1, 0),
+    (0, 2, 0),
+    // Opens a second session of key 1, as it is more than a gap after the first
+    (1, 1, 15),
+    // Bridges both sessions of key 1 before the first one closes, which moves the aggregate of
+    // the second session to the key of the first
+    (2, 1, 8),
+    // Opens a second session of key 2 after the first one was closed
+    (11, 2, 12),
+    // Extends the session of key 2, whose first timeout at 35 must not close it
+    (13, 2, 25),
+    (14, 2, 30),
+    // Arrives after its session should have closed, and closes it at the current time
+    (20, 3, 5),
+];
+
+/// Runs the records through a session count on backend `B` and returns the outputs with the
+/// times they were emitted at, as `(time, (key, (session end, count)))`. A `folded` count appends
+/// the records of a session and counts them when it closes.
+fn sessions<B: StateBackend>(
+    layout: StateLayout,
+    folded: bool,
+) -> Vec<(usize, (usize, (usize, usize)))> {
+    let mut outputs = timely::execute_from_args(
+        Vec::<String>::new().into_iter(),
+        move |worker, _node_state_handle| {
+            let outputs = Rc::new(RefCell::new(Vec::new()));
+            let mut input = worker.dataflow::<_, _, _, B>(|scope, _| {
+                let (input, records) = scope.new_input::<(usize, usize)>();
+                let outputs = outputs.clone();
+                let key = |r: &(usize, usize)| r.0;
+                let event_time = |r: &(usize, usize)| r.1;
+                let counts = if folded {
+                    SessionWindowOperator::new("Sessions", GAP, Folded(Count), layout)
+                        .apply(&records, key, event_time)
+                } else {
+                    SessionWindowOperator::new("Sessions", GAP, Count, layout)
+                        .apply(&records, key, event_time)
+                };
+                counts.inspect_batch(move |time, data| {
+                    outputs
+                        .borrow_mut()
+                        .extend(data.iter().map(|output| (*time, *output)));
+                });
+                input
+            });
+            for &(epoch, key, time) in RECORDS.iter() {
+                if epoch > *input.time() {
+                    input.advance_to(epoch);
+                    worker.step();
+                }
+                input.send((key, time));
+            }
+            input.close();
+            while worker.step() {}
+
+            let outputs = outputs.borrow().clone();
+            outputs
+        },
+    )
+    .expect("unsuccessful execution")
+    .join()
+    .into_iter()
+    .map(|result| result.expect("worker failed"))
+    .next()
+    .unwrap();
+    outputs.sort();
+    outputs
+}
+
+fn check<B: StateBackend>(layout: StateLayout, folded: bool) {
+    assert_eq!(
+        sessions::<B>(layout, folded),
+        vec![
+            (10, (2, (10, 1))),
+            (20, (3, (15, 1))),
+            (22, (2, (22, 1))),
+            (25, (1, (25, 3))),
+            (40, (2, (40, 2))),
+        ]
+    );
+}
+
+#[test]
+fn in_memory_rmw() {
+    check::<InMemoryBackend>(StateLayout::Rmw, false);
+}
+
+#[test]
+fn in_memory_merge_init() {
+    check::<InMemoryBackend>(StateLayout::MergeInit, false);
+}
+
+#[test]
+fn faster_rmw() {
+    check::<FASTERBackend>(StateLayout::Rmw, false);
+}
+
+#[test]
+fn rocksdb_merge_init() {
+    check::<RocksDBBackend>(StateLayout::MergeInit, false);
+}
+
+#[test]
+fn rocksdb_merge_folded() {
+    check::<RocksDBMergeBackend>(StateLayout::MergeInit, true);
+}
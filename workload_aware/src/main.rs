@@ -214,6 +214,22 @@ fn main() {
                 people_stream.capture_into(people.clone());
             });
 
+            // Q1: Currency conversion. Native.
+            if queries.iter().any(|x| *x == "q1") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q1(&nexmark_input, nexmark_timer, scope)
+                        .probe_with(&mut probe);
+                });
+            }
+
+            // Q2: Selection. Native.
+            if queries.iter().any(|x| *x == "q2") {
+                worker.dataflow(|scope| {
+                    ::nexmark::queries::q2(&nexmark_input, nexmark_timer, scope)
+                        .probe_with(&mut probe);
+                });
+            }
+
             // Q3: Join some auctions. Native.
             if queries.iter().any(|x| *x == "q3") {
                 worker.dataflow(|scope| {
@@ -0,0 +1,17 @@
+use timely::dataflow::operators::{Filter, Map};
+use timely::dataflow::{Scope, Stream};
+
+use crate::queries::{NexmarkInput, NexmarkTimer};
+
+pub fn q2<S: Scope<Timestamp = usize>>(
+    input: &NexmarkInput,
+    _nt: NexmarkTimer,
+    scope: &mut S,
+) -> Stream<S, (usize, usize)> {
+    // Select the bids on a fixed set of auctions
+    let auction_skip = 123;
+    input
+        .bids(scope)
+        .filter(move |b| b.auction % auction_skip == 0)
+        .map(|b| (b.auction, b.price))
+}